@@ -0,0 +1,212 @@
+//! Computer-controlled paddles.
+
+use vek::Vec2;
+
+use crate::config::Config;
+use crate::rng::Rng;
+use crate::sim::{Entity, PaddleId, PaddleInput, Side, Simulation, TIMESTEP};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+	Easy,
+	Normal,
+	Hard,
+}
+
+impl Difficulty {
+	pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Difficulty::Easy => "Easy",
+			Difficulty::Normal => "Normal",
+			Difficulty::Hard => "Hard",
+		}
+	}
+
+	pub fn profile(self) -> AiProfile {
+		match self {
+			Difficulty::Easy => AiProfile {
+				reaction_ticks: 20,
+				prediction_error: 60.0,
+				max_speed: 0.6,
+				spin: 0.0,
+			},
+			Difficulty::Normal => AiProfile {
+				reaction_ticks: 10,
+				prediction_error: 25.0,
+				max_speed: 0.8,
+				spin: 0.3,
+			},
+			Difficulty::Hard => AiProfile {
+				reaction_ticks: 3,
+				prediction_error: 6.0,
+				max_speed: 1.0,
+				spin: 0.7,
+			},
+		}
+	}
+}
+
+/// The knobs a difficulty level turns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AiProfile {
+	/// Ticks between the ball changing course and the AI noticing.
+	pub reaction_ticks: u32,
+	/// Largest distance, in pixels, the AI's guess of where the ball arrives can be off by.
+	pub prediction_error: f32,
+	/// Fraction of the paddle speed the AI is allowed to move at.
+	pub max_speed: f32,
+	/// How far from the centre of the paddle, as a fraction of its half height, the AI
+	/// tries to hit the ball to put spin on it.
+	pub spin: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ai {
+	paddle: PaddleId,
+	profile: AiProfile,
+	/// Where the AI wants the centre of its paddle to be, or `None` for the middle of the arena.
+	target: Option<f32>,
+	/// The ball velocity the current target was planned for.
+	planned_for: Option<(f32, f32)>,
+	react_in: u32,
+}
+
+impl Ai {
+	pub fn new(side: Side, difficulty: Difficulty) -> Ai {
+		Ai::with_profile(side, difficulty.profile())
+	}
+
+	pub fn with_profile(side: Side, profile: AiProfile) -> Ai {
+		Ai::for_paddle(PaddleId::new(side, 0), profile)
+	}
+
+	/// An AI moving `paddle`, which may be a forward in doubles.
+	pub fn for_paddle(paddle: PaddleId, profile: AiProfile) -> Ai {
+		Ai {
+			paddle,
+			profile,
+			target: None,
+			planned_for: None,
+			react_in: 0,
+		}
+	}
+
+	pub fn update(&mut self, simulation: &Simulation) -> PaddleInput {
+		let velocity = self.threat(simulation).velocity;
+		let seen = (velocity.x, velocity.y);
+		if self.planned_for != Some(seen) {
+			// The ball changed course: take a moment before reacting to it.
+			if self.react_in == 0 {
+				self.react_in = self.profile.reaction_ticks + 1;
+			}
+			self.react_in -= 1;
+			if self.react_in == 0 {
+				self.planned_for = Some(seen);
+				self.target = self.plan(simulation);
+			}
+		}
+
+		let config = &simulation.config;
+		let side = self.paddle.side;
+		let centre = along(side, simulation.paddle_at(self.paddle).centre());
+		let middle = along(side, Vec2::new(config.arena_width, config.arena_height) / 2.0);
+		let distance = self.target.unwrap_or(middle) - centre;
+		let max_speed = self.profile.max_speed;
+		PaddleInput::new((distance / (config.paddle_speed * TIMESTEP)).clamp(-max_speed, max_speed))
+	}
+
+	/// The ball to play: whichever will reach the paddle's face first, or the oldest one
+	/// if none are heading its way.
+	fn threat<'a>(&self, simulation: &'a Simulation) -> &'a Entity {
+		let face = self.face(simulation);
+		let axis = |value: Vec2<f32>| if self.paddle.side.is_horizontal() { value.y } else { value.x };
+		simulation.balls
+			.iter()
+			.map(|ball| &ball.entity)
+			.filter_map(|ball| {
+				let time = (face - axis(ball.position)) / axis(ball.velocity);
+				if time.is_finite() && time >= 0.0 { Some((ball, time)) } else { None }
+			})
+			.min_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map_or(simulation.ball(), |(ball, _)| ball)
+	}
+
+	/// Where the leading edge of a ball is when it reaches the paddle.
+	fn face(&self, simulation: &Simulation) -> f32 {
+		let paddle = simulation.paddle_at(self.paddle);
+		let ball = simulation.ball();
+		match self.paddle.side {
+			Side::Left => paddle.position.x + paddle.width(),
+			Side::Right => paddle.position.x - ball.width(),
+			Side::Top => paddle.position.y + paddle.height(),
+			Side::Bottom => paddle.position.y - ball.height(),
+		}
+	}
+
+	fn plan(&self, simulation: &Simulation) -> Option<f32> {
+		let side = self.paddle.side;
+		let paddle = simulation.paddle_at(self.paddle);
+		let ball = self.threat(simulation);
+		let face = self.face(simulation);
+
+		// If the ball is heading away, drift back to the middle.
+		let config = &simulation.config;
+		let arrival = if side.is_horizontal() {
+			predict_ball_x(config, ball, face)? + ball.width() / 2.0
+		} else {
+			predict_ball_y(config, ball, face)? + ball.height() / 2.0
+		};
+
+		// Seeded from the moment of planning, so the AI needs no state to stay reproducible.
+		let seed = simulation.seed ^ simulation.tick ^ ((side as u64) << 32) ^ ((self.paddle.index as u64) << 40);
+		let error = self.profile.prediction_error * (Rng::new(seed).next_f32() * 2.0 - 1.0);
+
+		// Hit the ball off-centre so the spin sends it away from the opponent.
+		let opponent = simulation.paddle(side.opponent());
+		let middle = along(side, Vec2::new(config.arena_width, config.arena_height) / 2.0);
+		let away = if along(side, opponent.centre()) < middle { 1.0 } else { -1.0 };
+		let spin_offset = self.profile.spin * along(side, paddle.size) / 2.0 * away;
+
+		Some(arrival + error - spin_offset)
+	}
+}
+
+/// The component of `value` along the side a paddle on `side` moves.
+fn along(side: Side, value: Vec2<f32>) -> f32 {
+	if side.is_horizontal() {
+		value.x
+	} else {
+		value.y
+	}
+}
+
+/// Where the top of `ball` will be when it reaches `x`, following its bounces off the
+/// walls, or `None` if it is not heading toward `x`.
+pub fn predict_ball_y(config: &Config, ball: &Entity, x: f32) -> Option<f32> {
+	let range = config.arena_height - ball.height();
+	predict(ball.position.x, ball.velocity.x, x, ball.position.y, ball.velocity.y, range)
+}
+
+/// Where the left of `ball` will be when it reaches `y`, as `predict_ball_y` does for
+/// paddles on the top and bottom. The walls are taken to be on the left and right, which
+/// in a four-way match they mostly are not, but the guess is only for one crossing.
+pub fn predict_ball_x(config: &Config, ball: &Entity, y: f32) -> Option<f32> {
+	let range = config.arena_width - ball.width();
+	predict(ball.position.y, ball.velocity.y, y, ball.position.x, ball.velocity.x, range)
+}
+
+/// Where something at `position` and moving at `velocity` along one axis will be on the
+/// other, where it is at `other` and moving at `other_velocity`, once it reaches `target`,
+/// bouncing within `0.0..=range` on that other axis.
+fn predict(position: f32, velocity: f32, target: f32, other: f32, other_velocity: f32, range: f32) -> Option<f32> {
+	let time = (target - position) / velocity;
+	if !time.is_finite() || time < 0.0 {
+		return None;
+	}
+
+	// Unfold the bounces: the ball moves freely in a mirrored, repeating arena.
+	let at = (other + other_velocity * time).rem_euclid(2.0 * range);
+	Some(if at > range { 2.0 * range - at } else { at })
+}
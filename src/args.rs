@@ -0,0 +1,17 @@
+//! Parsing of the command-line option values the game and the server have in common.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+/// `value` given to the option `name`, as a whole number.
+pub fn parse_number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+	value.parse().map_err(|_| format!("{} expects a whole number, but got {:?}", name, value))
+}
+
+/// `value` given to the option `name`, as a number of seconds from 0 to 60.
+pub fn parse_seconds(name: &str, value: &str) -> Result<Duration, String> {
+	match value.parse::<f32>() {
+		Ok(seconds) if (0.0..=60.0).contains(&seconds) => Ok(Duration::from_secs_f32(seconds)),
+		_ => Err(format!("{} expects a number of seconds from 0 to 60, but got {:?}", name, value)),
+	}
+}
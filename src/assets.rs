@@ -0,0 +1,34 @@
+use tetra::graphics::mesh::{Mesh, ShapeStyle};
+use tetra::graphics::text::Font;
+use tetra::graphics::{Rectangle, Texture};
+use tetra::Context;
+
+use mfight_ng::config::Config;
+
+/// Resources loaded once at startup and shared by every scene.
+pub struct Assets {
+	pub font: Font,
+	pub small_font: Font,
+	pub player1_texture: Texture,
+	pub player2_texture: Texture,
+	pub ball_texture: Texture,
+	/// An arena-sized rectangle for dimming the scene below an overlay.
+	pub overlay: Mesh,
+}
+
+impl Assets {
+	pub fn load(ctx: &mut Context, config: &Config) -> tetra::Result<Assets> {
+		Ok(Assets {
+			font: Font::vector(ctx, "./resources/Ubuntu-MI.ttf", 44.0)?,
+			small_font: Font::vector(ctx, "./resources/Ubuntu-MI.ttf", 28.0)?,
+			player1_texture: Texture::new(ctx, "./resources/player1.png")?,
+			player2_texture: Texture::new(ctx, "./resources/player2.png")?,
+			ball_texture: Texture::new(ctx, "./resources/ball.png")?,
+			overlay: Mesh::rectangle(
+				ctx,
+				ShapeStyle::Fill,
+				Rectangle::new(0.0, 0.0, config.arena_width, config.arena_height),
+			)?,
+		})
+	}
+}
@@ -0,0 +1,159 @@
+//! Dedicated server for online matches: it runs the only simulation that counts, so a
+//! modified client can't change how a match goes.
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use mfight_ng::args::{parse_number, parse_seconds};
+use mfight_ng::config::{Config, PRESETS};
+use mfight_ng::net::{LinkConditions, Server, ServerEvent, DEFAULT_PORT};
+use mfight_ng::sim::{MatchRules, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+const USAGE: &str = "\
+Usage: mfight-server [OPTIONS]
+
+Hosts online matches, one at a time, for players started with --server.
+
+Options:
+  --port <PORT>      Port to listen on [default: 7777]
+  --config <FILE>    Read the tuning from FILE
+  --preset <NAME>    Use a built-in tuning preset: classic, fast or big
+  --seed <N>         Seed of the first match; each match after it gets the next one
+                     [default: the current time]
+  --matches <N>      Stop after this many matches [default: keep going]
+  --name <NAME>      Name shown to players looking for LAN games [default: mfight-server]
+  --spectator-delay <S>
+                     Seconds spectators are shown matches late, from 0 to 60 [default: 2]
+  -h, --help         Print this help
+";
+
+#[derive(Debug, Default)]
+struct Options {
+	port: Option<u16>,
+	config: Option<PathBuf>,
+	preset: Option<String>,
+	seed: Option<u64>,
+	matches: Option<u32>,
+	name: Option<String>,
+	spectator_delay: Option<Duration>,
+	help: bool,
+}
+
+impl Options {
+	fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+		let mut options = Options::default();
+		let mut args = args.into_iter();
+
+		while let Some(arg) = args.next() {
+			let (name, inline) = match arg.find('=') {
+				Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+				_ => (arg.clone(), None),
+			};
+			let mut value = || {
+				inline.clone()
+					.or_else(|| args.next())
+					.ok_or_else(|| format!("{} needs a value", name))
+			};
+
+			match name.as_str() {
+				"--port" => options.port = Some(parse_number(&name, &value()?)?),
+				"--config" => options.config = Some(PathBuf::from(value()?)),
+				"--preset" => options.preset = Some(value()?),
+				"--seed" => options.seed = Some(parse_number(&name, &value()?)?),
+				"--matches" => options.matches = Some(parse_number(&name, &value()?)?),
+				"--name" => options.name = Some(value()?),
+				"--spectator-delay" => options.spectator_delay = Some(parse_seconds(&name, &value()?)?),
+				"-h" | "--help" => options.help = true,
+				_ => return Err(format!("unknown option {}", arg)),
+			}
+		}
+
+		if options.config.is_some() && options.preset.is_some() {
+			return Err("--config and --preset cannot be used together".to_string());
+		}
+		Ok(options)
+	}
+}
+
+/// Reports a problem with how the server was started, and exits.
+fn fail(message: &str) -> ! {
+	eprintln!("error: {}", message);
+	process::exit(2);
+}
+
+fn main() {
+	let options = Options::parse(env::args().skip(1)).unwrap_or_else(|err| fail(&format!("{}\n\n{}", err, USAGE)));
+	if options.help {
+		print!("{}", USAGE);
+		return;
+	}
+
+	let config = match (&options.preset, &options.config) {
+		(Some(name), _) => Config::preset(name).unwrap_or_else(|| {
+			fail(&format!("unknown preset {:?}, expected one of: {}", name, PRESETS.join(", ")))
+		}),
+		(None, Some(path)) => Config::load(path)
+			.unwrap_or_else(|err| fail(&format!("invalid config in {}: {}", path.display(), err))),
+		(None, None) => Config::default(),
+	};
+	let rules = MatchRules { win_score: config.win_score, ..MatchRules::default() };
+	let seed = options.seed.unwrap_or_else(|| {
+		SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+	});
+
+	let port = options.port.unwrap_or(DEFAULT_PORT);
+	let mut server = Server::bind(port, config, rules, seed, LinkConditions::default())
+		.unwrap_or_else(|err| fail(&format!("couldn't listen on port {}: {}", port, err)));
+	if let Some(name) = &options.name {
+		server.set_name(name);
+	}
+	if let Some(delay) = options.spectator_delay {
+		server.set_spectator_delay(delay);
+	}
+	println!("Listening on port {}", port);
+
+	let mut played = 0;
+	let mut timestep = FixedTimestep::new(TICK_RATE);
+	let mut last = Instant::now();
+	loop {
+		if let Err(err) = server.poll() {
+			fail(&format!("the server stopped: {}", err));
+		}
+		let now = Instant::now();
+		timestep.advance(now - last);
+		last = now;
+		while timestep.tick() {
+			if let Err(err) = server.step() {
+				fail(&format!("the server stopped: {}", err));
+			}
+		}
+
+		for event in server.take_events() {
+			match event {
+				ServerEvent::Joined(side, address) => println!("{} joined on the {} side", address, side.name()),
+				ServerEvent::Left(side) => println!("The {} player left", side.name()),
+				ServerEvent::Kicked(side, reason) => println!("Removed the {} player: {}", side.name(), reason),
+				ServerEvent::Started(seed) => println!("Match started (seed {})", seed),
+				ServerEvent::Finished(result) => {
+					played += 1;
+					let score = result.score;
+					let seconds = server.simulation().tick as f32 / TICK_RATE as f32;
+					println!(
+						"Match {}: {} side wins {} - {} after {:.1}s",
+						played, result.winner.name(), score.left, score.right, seconds,
+					);
+				}
+				ServerEvent::Abandoned => println!("Match abandoned"),
+			}
+		}
+		// Only stops once the last match is over, with its result sent to both players.
+		if options.matches.is_some_and(|matches| played >= matches) && !server.is_running() {
+			return;
+		}
+		thread::sleep(Duration::from_millis(1));
+	}
+}
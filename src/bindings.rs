@@ -0,0 +1,185 @@
+//! Keys bound to each logical action, saved to and loaded from a TOML file.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tetra::input::{self, Key};
+use tetra::Context;
+
+pub const BINDINGS_PATH: &str = "./bindings.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	P1Up,
+	P1Down,
+	P2Up,
+	P2Down,
+	Pause,
+	Confirm,
+	Back,
+}
+
+impl Action {
+	pub const ALL: [Action; 7] = [
+		Action::P1Up,
+		Action::P1Down,
+		Action::P2Up,
+		Action::P2Down,
+		Action::Pause,
+		Action::Confirm,
+		Action::Back,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Action::P1Up => "P1 up",
+			Action::P1Down => "P1 down",
+			Action::P2Up => "P2 up",
+			Action::P2Down => "P2 down",
+			Action::Pause => "Pause",
+			Action::Confirm => "Confirm",
+			Action::Back => "Back",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+	pub p1_up: Key,
+	pub p1_down: Key,
+	pub p2_up: Key,
+	pub p2_down: Key,
+	pub pause: Key,
+	pub confirm: Key,
+	pub back: Key,
+}
+
+impl Default for Bindings {
+	fn default() -> Bindings {
+		Bindings {
+			p1_up: Key::W,
+			p1_down: Key::S,
+			p2_up: Key::Up,
+			p2_down: Key::Down,
+			pause: Key::P,
+			confirm: Key::Enter,
+			back: Key::Escape,
+		}
+	}
+}
+
+impl Bindings {
+	/// Loads the bindings at `path`, falling back to the defaults if the file is missing
+	/// or cannot be read.
+	pub fn load_or_default<P: AsRef<Path>>(path: P) -> Bindings {
+		let path = path.as_ref();
+		match fs::read_to_string(path) {
+			Ok(text) => toml::from_str(&text).unwrap_or_else(|err| {
+				eprintln!("ignoring invalid key bindings in {}: {}", path.display(), err);
+				Bindings::default()
+			}),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Bindings::default(),
+			Err(err) => {
+				eprintln!("could not read key bindings from {}: {}", path.display(), err);
+				Bindings::default()
+			}
+		}
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		let text = toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+		fs::write(path, text)
+	}
+
+	pub fn get(&self, action: Action) -> Key {
+		match action {
+			Action::P1Up => self.p1_up,
+			Action::P1Down => self.p1_down,
+			Action::P2Up => self.p2_up,
+			Action::P2Down => self.p2_down,
+			Action::Pause => self.pause,
+			Action::Confirm => self.confirm,
+			Action::Back => self.back,
+		}
+	}
+
+	fn slot(&mut self, action: Action) -> &mut Key {
+		match action {
+			Action::P1Up => &mut self.p1_up,
+			Action::P1Down => &mut self.p1_down,
+			Action::P2Up => &mut self.p2_up,
+			Action::P2Down => &mut self.p2_down,
+			Action::Pause => &mut self.pause,
+			Action::Confirm => &mut self.confirm,
+			Action::Back => &mut self.back,
+		}
+	}
+
+	/// The other action already bound to `key`, if any.
+	pub fn conflict(&self, action: Action, key: Key) -> Option<Action> {
+		Action::ALL.iter()
+			.copied()
+			.find(|&other| other != action && self.get(other) == key)
+	}
+
+	/// Binds `key` to `action`. If another action was using `key`, it takes over the key
+	/// `action` had before, so no two actions ever share a key; that action is returned.
+	pub fn bind(&mut self, action: Action, key: Key) -> Option<Action> {
+		let conflict = self.conflict(action, key);
+		let previous = std::mem::replace(self.slot(action), key);
+		if let Some(other) = conflict {
+			*self.slot(other) = previous;
+		}
+		conflict
+	}
+
+	pub fn is_pressed(&self, ctx: &Context, action: Action) -> bool {
+		input::is_key_pressed(ctx, self.get(action))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn free_key_is_bound_without_conflict() {
+		let mut bindings = Bindings::default();
+		assert_eq!(bindings.conflict(Action::P1Up, Key::Q), None);
+		assert_eq!(bindings.bind(Action::P1Up, Key::Q), None);
+		assert_eq!(bindings.get(Action::P1Up), Key::Q);
+		assert_eq!(bindings.get(Action::P1Down), Key::S);
+	}
+
+	#[test]
+	fn taken_key_swaps_with_the_other_action() {
+		let mut bindings = Bindings::default();
+		assert_eq!(bindings.conflict(Action::P1Up, Key::Up), Some(Action::P2Up));
+		assert_eq!(bindings.bind(Action::P1Up, Key::Up), Some(Action::P2Up));
+		assert_eq!(bindings.get(Action::P1Up), Key::Up);
+		assert_eq!(bindings.get(Action::P2Up), Key::W);
+	}
+
+	#[test]
+	fn rebinding_the_same_key_is_not_a_conflict() {
+		let mut bindings = Bindings::default();
+		assert_eq!(bindings.bind(Action::Confirm, Key::Enter), None);
+		assert_eq!(bindings, Bindings::default());
+	}
+
+	#[test]
+	fn no_two_actions_ever_share_a_key() {
+		let mut bindings = Bindings::default();
+		let keys = [Key::Up, Key::S, Key::Escape, Key::P, Key::W, Key::Enter, Key::Down];
+		for (&action, &key) in Action::ALL.iter().zip(keys.iter()) {
+			bindings.bind(action, key);
+			let mut bound: Vec<_> = Action::ALL.iter().map(|&action| bindings.get(action) as u32).collect();
+			bound.sort_unstable();
+			bound.dedup();
+			assert_eq!(bound.len(), Action::ALL.len());
+		}
+	}
+}
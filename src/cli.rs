@@ -0,0 +1,267 @@
+//! Command-line options, for launching straight into a match or scripting headless ones.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use mfight_ng::ai::Difficulty;
+use mfight_ng::args::{parse_number, parse_seconds};
+use mfight_ng::net::{LinkConditions, DEFAULT_INPUT_DELAY, DEFAULT_PORT, DEFAULT_SPECTATOR_DELAY, MAX_INPUT_DELAY};
+
+use crate::controls::{Control, KeySet};
+
+pub const USAGE: &str = "\
+Usage: mfight-ng [OPTIONS]
+
+Options:
+  --mode <MODE>          Start a match right away: pvp, vs-cpu or cpu-vs-cpu
+  --difficulty <LEVEL>   Difficulty of CPU players: easy, normal or hard, or one per player
+                         such as hard,easy [default: normal]
+  --config <FILE>        Read the tuning from FILE instead of ./config.toml
+  --preset <NAME>        Use a built-in tuning preset: classic, fast or big
+  --window <WxH>         Window size, e.g. 1280x960 [default: the arena size]
+  --fullscreen           Start in fullscreen
+  --seed <N>             Seed for everything random in the match [default: the current time]
+  --headless             Play without a window and print the result (needs --mode cpu-vs-cpu)
+  --matches <N>          Number of matches to play with --headless [default: 1]
+  --replay <FILE>        Watch a saved replay, or with --headless check that it still plays
+                         out as recorded
+  --host <PORT>          Host an online match on PORT (usually 7777) and wait for a player
+  --connect <ADDRESS>    Join the online match hosted at ADDRESS, e.g. 192.168.1.20:7777
+  --server <ADDRESS>     Play the next match on the mfight-server at ADDRESS
+  --watch <ADDRESS>      Watch the match hosted or broadcast at ADDRESS
+  --spectators <PORT>    Let spectators watch local matches from PORT
+  --spectator-delay <S>  Seconds spectators are shown matches late, when hosting or with
+                         --spectators [default: 2]
+  --name <NAME>          Name shown to other players and spectators
+                         [default: this machine's name]
+  --input-delay <TICKS>  Ticks before the host's and joining player's inputs take effect,
+                         0 to 10 [default: 2]
+  --net-loss <PERCENT>   Drop this share of outgoing packets, to test bad connections
+  --net-latency <MS>     Hold back every outgoing packet this long
+  --net-jitter <MS>      Vary the held-back time by up to this much either way
+  -h, --help             Print this help
+
+Online matches with --headless are played by a CPU, at --difficulty.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	/// Two players sharing the keyboard.
+	Pvp,
+	/// One player at the keyboard against a CPU.
+	VsCpu,
+	CpuVsCpu,
+}
+
+impl Mode {
+	/// Who controls each player, indexed by `Player::index`, as is `difficulty`.
+	pub fn controls(self, difficulty: [Difficulty; 2]) -> [Control; 2] {
+		match self {
+			Mode::Pvp => [Control::Keyboard(KeySet::Player1), Control::Keyboard(KeySet::Player2)],
+			Mode::VsCpu => [Control::Keyboard(KeySet::Any), Control::Cpu(difficulty[1])],
+			Mode::CpuVsCpu => [Control::Cpu(difficulty[0]), Control::Cpu(difficulty[1])],
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+	pub mode: Option<Mode>,
+	/// Indexed by `Player::index`.
+	pub difficulty: [Difficulty; 2],
+	pub config: Option<PathBuf>,
+	pub preset: Option<String>,
+	pub window: Option<(i32, i32)>,
+	pub fullscreen: bool,
+	pub seed: Option<u64>,
+	pub headless: bool,
+	pub matches: u32,
+	pub replay: Option<PathBuf>,
+	/// Port to host an online match on.
+	pub host: Option<u16>,
+	/// Address of an online match to join, as given.
+	pub connect: Option<String>,
+	/// Address of a dedicated server to play on, as given.
+	pub server: Option<String>,
+	/// Address of a match to watch, as given.
+	pub watch: Option<String>,
+	/// Port to show local matches to spectators on.
+	pub spectators: Option<u16>,
+	pub spectator_delay: Duration,
+	/// Name to show other players and spectators.
+	pub name: Option<String>,
+	pub input_delay: u32,
+	pub conditions: LinkConditions,
+	pub help: bool,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			mode: None,
+			difficulty: [Difficulty::Normal; 2],
+			config: None,
+			preset: None,
+			window: None,
+			fullscreen: false,
+			seed: None,
+			headless: false,
+			matches: 1,
+			replay: None,
+			host: None,
+			connect: None,
+			server: None,
+			watch: None,
+			spectators: None,
+			spectator_delay: DEFAULT_SPECTATOR_DELAY,
+			name: None,
+			input_delay: DEFAULT_INPUT_DELAY,
+			conditions: LinkConditions::default(),
+			help: false,
+		}
+	}
+}
+
+impl Options {
+	/// Parses the program's arguments, not including the program name. Values can follow
+	/// their option either as the next argument or after an `=`.
+	pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+		let mut options = Options::default();
+		let mut args = args.into_iter();
+
+		while let Some(arg) = args.next() {
+			let (name, inline) = match arg.find('=') {
+				Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+				_ => (arg.clone(), None),
+			};
+			let is_flag = matches!(name.as_str(), "--fullscreen" | "--headless" | "-h" | "--help");
+			if is_flag && inline.is_some() {
+				return Err(format!("{} does not take a value", name));
+			}
+			let mut value = || {
+				inline.clone()
+					.or_else(|| args.next())
+					.ok_or_else(|| format!("{} needs a value", name))
+			};
+
+			match name.as_str() {
+				"--mode" => options.mode = Some(parse_mode(&value()?)?),
+				"--difficulty" => options.difficulty = parse_difficulties(&value()?)?,
+				"--config" => options.config = Some(PathBuf::from(value()?)),
+				"--preset" => options.preset = Some(value()?),
+				"--window" => options.window = Some(parse_size(&value()?)?),
+				"--fullscreen" => options.fullscreen = true,
+				"--seed" => options.seed = Some(parse_number(&name, &value()?)?),
+				"--headless" => options.headless = true,
+				"--matches" => options.matches = parse_number(&name, &value()?)?,
+				"--replay" => options.replay = Some(PathBuf::from(value()?)),
+				"--host" => options.host = Some(parse_number(&name, &value()?)?),
+				"--connect" => options.connect = Some(value()?),
+				"--server" => options.server = Some(value()?),
+				"--watch" => options.watch = Some(value()?),
+				"--spectators" => options.spectators = Some(parse_number(&name, &value()?)?),
+				"--spectator-delay" => options.spectator_delay = parse_seconds(&name, &value()?)?,
+				"--name" => options.name = Some(value()?),
+				"--input-delay" => options.input_delay = parse_number(&name, &value()?)?,
+				"--net-loss" => options.conditions.loss = parse_percent(&name, &value()?)?,
+				"--net-latency" => options.conditions.latency = parse_millis(&name, &value()?)?,
+				"--net-jitter" => options.conditions.jitter = parse_millis(&name, &value()?)?,
+				"-h" | "--help" => options.help = true,
+				_ => return Err(format!("unknown option {}", arg)),
+			}
+		}
+
+		if options.config.is_some() && options.preset.is_some() {
+			return Err("--config and --preset cannot be used together; name the preset in the config file instead".to_string());
+		}
+		if options.replay.is_some() && (options.mode.is_some() || options.config.is_some() || options.preset.is_some()) {
+			return Err("--replay plays the recorded match, so it cannot be combined with --mode, --config or --preset".to_string());
+		}
+		let online = [options.host.is_some(), options.connect.is_some(), options.server.is_some(), options.watch.is_some()];
+		if online.iter().filter(|&&given| given).count() > 1 {
+			return Err("only one of --host, --connect, --server and --watch can be used".to_string());
+		}
+		let online = online.contains(&true);
+		if online && (options.mode.is_some() || options.replay.is_some()) {
+			return Err("online matches cannot be combined with --mode or --replay".to_string());
+		}
+		if options.watch.is_some() && (options.config.is_some() || options.preset.is_some() || options.headless) {
+			return Err("--watch shows someone else's match, so it cannot be used with --config, --preset or --headless".to_string());
+		}
+		if options.spectators.is_some() && (online || options.replay.is_some() || options.headless) {
+			return Err("--spectators is for local matches; an online match's host takes spectators on its own port".to_string());
+		}
+		if (options.connect.is_some() || options.server.is_some()) && (options.config.is_some() || options.preset.is_some()) {
+			return Err("the host decides the tuning of an online match, so --connect and --server cannot be used with --config or --preset".to_string());
+		}
+		if options.name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+			return Err("--name cannot be empty".to_string());
+		}
+		if options.input_delay > MAX_INPUT_DELAY {
+			return Err(format!("--input-delay can be at most {}", MAX_INPUT_DELAY));
+		}
+		if options.headless && options.mode.unwrap_or(Mode::CpuVsCpu) != Mode::CpuVsCpu {
+			return Err("--headless can only play CPU against CPU (--mode cpu-vs-cpu)".to_string());
+		}
+		Ok(options)
+	}
+}
+
+fn parse_mode(value: &str) -> Result<Mode, String> {
+	match value {
+		"pvp" => Ok(Mode::Pvp),
+		"vs-cpu" => Ok(Mode::VsCpu),
+		"cpu-vs-cpu" => Ok(Mode::CpuVsCpu),
+		_ => Err(format!("unknown mode {:?}, expected pvp, vs-cpu or cpu-vs-cpu", value)),
+	}
+}
+
+fn parse_difficulties(value: &str) -> Result<[Difficulty; 2], String> {
+	match value.split_once(',') {
+		Some((first, second)) => Ok([parse_difficulty(first)?, parse_difficulty(second)?]),
+		None => parse_difficulty(value).map(|difficulty| [difficulty; 2]),
+	}
+}
+
+fn parse_difficulty(value: &str) -> Result<Difficulty, String> {
+	Difficulty::ALL.iter()
+		.copied()
+		.find(|difficulty| difficulty.name().eq_ignore_ascii_case(value))
+		.ok_or_else(|| format!("unknown difficulty {:?}, expected easy, normal or hard", value))
+}
+
+fn parse_size(value: &str) -> Result<(i32, i32), String> {
+	let invalid = || format!("invalid window size {:?}, expected WIDTHxHEIGHT such as 1280x960", value);
+	let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+	match (width.parse(), height.parse()) {
+		(Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+		_ => Err(invalid()),
+	}
+}
+
+/// The address in `value`, with the default port if it names none.
+pub fn with_default_port(value: &str) -> String {
+	// A bare IPv6 address has colons too, but no port unless it is in brackets.
+	let has_port = match value.rfind(':') {
+		Some(colon) => !value[..colon].contains(':') || value[..colon].ends_with(']'),
+		None => false,
+	};
+	if has_port {
+		value.to_string()
+	} else if value.contains(':') && !value.starts_with('[') {
+		format!("[{}]:{}", value, DEFAULT_PORT)
+	} else {
+		format!("{}:{}", value, DEFAULT_PORT)
+	}
+}
+
+fn parse_percent(name: &str, value: &str) -> Result<f32, String> {
+	match value.parse::<f32>() {
+		Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent / 100.0),
+		_ => Err(format!("{} expects a percentage from 0 to 100, but got {:?}", name, value)),
+	}
+}
+
+fn parse_millis(name: &str, value: &str) -> Result<Duration, String> {
+	parse_number(name, value).map(Duration::from_millis)
+}
@@ -0,0 +1,266 @@
+//! Tuning of the arena, paddles and ball, loaded from a TOML file so balance changes don't
+//! need a rebuild.
+//!
+//! A config file may name one of the built-in presets to start from and then override any
+//! of its values:
+//!
+//! ```toml
+//! preset = "fast"
+//! paddle_height = 80.0
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Names of the built-in presets, the first being the default.
+pub const PRESETS: [&str; 3] = ["classic", "fast", "big"];
+
+/// Largest serve angle allowed, in degrees from the horizontal.
+const MAX_SERVE_ANGLE: f32 = 75.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	/// Size of the playing field, and of the window showing it.
+	pub arena_width: f32,
+	pub arena_height: f32,
+	pub paddle_width: f32,
+	pub paddle_height: f32,
+	pub ball_size: f32,
+
+	// Speeds are in pixels per second.
+	pub paddle_speed: f32,
+	/// Speed of the ball when it is served.
+	pub ball_speed: f32,
+	/// The ball never moves faster than this, however many times it is hit.
+	pub max_ball_speed: f32,
+	/// Horizontal speed the ball gains from each paddle hit.
+	pub ball_acc: f32,
+	/// Vertical speed given to a ball hit by the very edge of a paddle.
+	pub paddle_spin: f32,
+
+	/// Range of angles, in degrees from the horizontal, the ball can be served at.
+	pub min_serve_angle: f32,
+	pub max_serve_angle: f32,
+
+	/// Points needed to win a match, unless changed in the options.
+	pub win_score: u32,
+}
+
+impl Default for Config {
+	fn default() -> Config {
+		Config {
+			arena_width: 640.0,
+			arena_height: 480.0,
+			paddle_width: 24.0,
+			paddle_height: 104.0,
+			ball_size: 22.0,
+			paddle_speed: 480.0,
+			ball_speed: 300.0,
+			max_ball_speed: 1200.0,
+			ball_acc: 3.0,
+			paddle_spin: 240.0,
+			min_serve_angle: 0.0,
+			max_serve_angle: 30.0,
+			win_score: 11,
+		}
+	}
+}
+
+impl Config {
+	/// The built-in preset called `name`, if there is one.
+	pub fn preset(name: &str) -> Option<Config> {
+		let classic = Config::default();
+		match name {
+			"classic" => Some(classic),
+			"fast" => Some(Config {
+				paddle_speed: 640.0,
+				ball_speed: 450.0,
+				max_ball_speed: 1600.0,
+				ball_acc: 12.0,
+				paddle_spin: 320.0,
+				min_serve_angle: 10.0,
+				max_serve_angle: 40.0,
+				win_score: 7,
+				..classic
+			}),
+			"big" => Some(Config {
+				arena_width: 960.0,
+				arena_height: 720.0,
+				paddle_width: 30.0,
+				paddle_height: 140.0,
+				ball_size: 28.0,
+				paddle_speed: 640.0,
+				ball_speed: 400.0,
+				max_ball_speed: 1600.0,
+				max_serve_angle: 25.0,
+				..classic
+			}),
+			_ => None,
+		}
+	}
+
+	/// Parses a config file, starting from the preset it names (or the default one) and
+	/// applying every value it sets on top.
+	pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+		let mut table = match text.parse::<toml::Value>()? {
+			toml::Value::Table(table) => table,
+			_ => unreachable!("a TOML document is always a table"),
+		};
+
+		let base = match table.remove("preset") {
+			Some(toml::Value::String(name)) => Config::preset(&name).ok_or(ConfigError::UnknownPreset(name))?,
+			Some(other) => {
+				return Err(ConfigError::Invalid {
+					field: "preset",
+					reason: format!("expected the name of a preset, found {}", other),
+				});
+			}
+			None => Config::default(),
+		};
+
+		let mut merged = match toml::Value::try_from(base)? {
+			toml::Value::Table(merged) => merged,
+			_ => unreachable!("a config always serializes to a table"),
+		};
+		merged.extend(table);
+
+		let config: Config = toml::Value::Table(merged).try_into()?;
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Reads and parses the config file at `path`.
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+		let path = path.as_ref();
+		let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+		Config::from_toml(&text)
+	}
+
+	/// Checks that every value makes for a playable game.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let invalid = |field: &'static str, reason: String| Err(ConfigError::Invalid { field, reason });
+
+		let positive = [
+			("arena_width", self.arena_width),
+			("arena_height", self.arena_height),
+			("paddle_width", self.paddle_width),
+			("paddle_height", self.paddle_height),
+			("ball_size", self.ball_size),
+			("paddle_speed", self.paddle_speed),
+			("ball_speed", self.ball_speed),
+			("max_ball_speed", self.max_ball_speed),
+		];
+		for &(field, value) in &positive {
+			if !(value.is_finite() && value > 0.0) {
+				return invalid(field, format!("must be a positive number, but is {}", value));
+			}
+		}
+		let non_negative = [("ball_acc", self.ball_acc), ("paddle_spin", self.paddle_spin)];
+		for &(field, value) in &non_negative {
+			if !(value.is_finite() && value >= 0.0) {
+				return invalid(field, format!("must not be negative, but is {}", value));
+			}
+		}
+
+		if self.arena_width < 320.0 {
+			return invalid("arena_width", format!("must be at least 320, but is {}", self.arena_width));
+		}
+		if self.arena_height < 240.0 {
+			return invalid("arena_height", format!("must be at least 240, but is {}", self.arena_height));
+		}
+		if self.paddle_height >= self.arena_height {
+			return invalid("paddle_height", format!(
+				"must be less than arena_height ({}), but is {}",
+				self.arena_height, self.paddle_height,
+			));
+		}
+		if self.paddle_width >= self.arena_width / 4.0 {
+			return invalid("paddle_width", format!(
+				"must be less than a quarter of arena_width ({}), but is {}",
+				self.arena_width / 4.0, self.paddle_width,
+			));
+		}
+		if self.ball_size >= self.arena_height / 2.0 {
+			return invalid("ball_size", format!(
+				"must be less than half of arena_height ({}), but is {}",
+				self.arena_height / 2.0, self.ball_size,
+			));
+		}
+		if self.max_ball_speed < self.ball_speed {
+			return invalid("max_ball_speed", format!(
+				"must be at least ball_speed ({}), but is {}",
+				self.ball_speed, self.max_ball_speed,
+			));
+		}
+
+		for &(field, angle) in &[("min_serve_angle", self.min_serve_angle), ("max_serve_angle", self.max_serve_angle)] {
+			if !(0.0..=MAX_SERVE_ANGLE).contains(&angle) {
+				return invalid(field, format!("must be between 0 and {} degrees, but is {}", MAX_SERVE_ANGLE, angle));
+			}
+		}
+		if self.min_serve_angle > self.max_serve_angle {
+			return invalid("min_serve_angle", format!(
+				"must not be more than max_serve_angle ({}), but is {}",
+				self.max_serve_angle, self.min_serve_angle,
+			));
+		}
+
+		if self.win_score == 0 {
+			return invalid("win_score", "must be at least 1".to_string());
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(PathBuf, io::Error),
+	/// The file is not valid TOML, or a value has the wrong type or an unknown name.
+	Parse(String),
+	UnknownPreset(String),
+	Invalid {
+		field: &'static str,
+		reason: String,
+	},
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfigError::Io(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+			ConfigError::Parse(message) => write!(f, "{}", message),
+			ConfigError::UnknownPreset(name) => {
+				write!(f, "unknown preset {:?}, expected one of: {}", name, PRESETS.join(", "))
+			}
+			ConfigError::Invalid { field, reason } => write!(f, "{} {}", field, reason),
+		}
+	}
+}
+
+impl Error for ConfigError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			ConfigError::Io(_, err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<toml::de::Error> for ConfigError {
+	fn from(err: toml::de::Error) -> ConfigError {
+		ConfigError::Parse(err.to_string())
+	}
+}
+
+impl From<toml::ser::Error> for ConfigError {
+	fn from(err: toml::ser::Error) -> ConfigError {
+		ConfigError::Parse(err.to_string())
+	}
+}
@@ -0,0 +1,117 @@
+//! Sources of paddle input: anything that can decide what a paddle does each tick.
+//!
+//! Controllers are generic over a context `C` so that ones backed by a device (keyboard,
+//! gamepad) can read it from the windowing library, while headless ones ignore it.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+
+use crate::ai::Ai;
+use crate::sim::{PaddleInput, Simulation};
+
+/// Requests a controller can make besides moving its paddle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Actions(u8);
+
+impl Actions {
+	pub const NONE: Actions = Actions(0);
+	pub const PAUSE: Actions = Actions(1);
+
+	pub fn contains(self, other: Actions) -> bool {
+		self.0 & other.0 == other.0 && other.0 != 0
+	}
+
+	pub fn insert(&mut self, other: Actions) {
+		self.0 |= other.0;
+	}
+}
+
+/// What a controller wants its paddle to do during one tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Intent {
+	pub input: PaddleInput,
+	pub actions: Actions,
+}
+
+impl From<PaddleInput> for Intent {
+	fn from(input: PaddleInput) -> Intent {
+		Intent { input, actions: Actions::NONE }
+	}
+}
+
+pub trait PaddleController<C: ?Sized = ()> {
+	/// Called once per rendered frame, before any ticks run, so controllers reading a device
+	/// can catch button presses that happen between ticks.
+	fn poll(&mut self, _ctx: &C) {}
+
+	/// What the paddle should do during the next tick of `simulation`.
+	fn intent(&mut self, ctx: &C, simulation: &Simulation) -> Intent;
+}
+
+impl<C: ?Sized> PaddleController<C> for Ai {
+	fn intent(&mut self, _ctx: &C, simulation: &Simulation) -> Intent {
+		self.update(simulation).into()
+	}
+}
+
+/// Leaves the paddle where it is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Idle;
+
+impl<C: ?Sized> PaddleController<C> for Idle {
+	fn intent(&mut self, _ctx: &C, _simulation: &Simulation) -> Intent {
+		Intent::default()
+	}
+}
+
+/// A bot driven by a closure, for tests and quick experiments.
+pub struct Scripted<F>(pub F);
+
+impl<C: ?Sized, F> PaddleController<C> for Scripted<F>
+where
+	F: FnMut(&Simulation) -> PaddleInput,
+{
+	fn intent(&mut self, _ctx: &C, simulation: &Simulation) -> Intent {
+		(self.0)(simulation).into()
+	}
+}
+
+/// Plays back previously recorded inputs, one per tick, then stands still.
+#[derive(Debug, Clone, Default)]
+pub struct Playback {
+	inputs: VecDeque<PaddleInput>,
+}
+
+impl Playback {
+	pub fn new<I: IntoIterator<Item = PaddleInput>>(inputs: I) -> Playback {
+		Playback { inputs: inputs.into_iter().collect() }
+	}
+}
+
+impl<C: ?Sized> PaddleController<C> for Playback {
+	fn intent(&mut self, _ctx: &C, _simulation: &Simulation) -> Intent {
+		self.inputs.pop_front().unwrap_or_default().into()
+	}
+}
+
+/// A player on another machine, whose inputs arrive over a channel fed by the network,
+/// one per tick. While none is waiting, the last one received is repeated.
+pub struct Remote {
+	receiver: Receiver<PaddleInput>,
+	last: PaddleInput,
+}
+
+impl Remote {
+	pub fn new(receiver: Receiver<PaddleInput>) -> Remote {
+		Remote { receiver, last: PaddleInput::default() }
+	}
+}
+
+impl<C: ?Sized> PaddleController<C> for Remote {
+	fn intent(&mut self, _ctx: &C, _simulation: &Simulation) -> Intent {
+		if let Ok(input) = self.receiver.try_recv() {
+			self.last = input;
+		}
+		self.last.into()
+	}
+}
@@ -0,0 +1,333 @@
+//! Paddle controllers backed by tetra's input devices, and the choice of controller made
+//! when setting up a match.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use tetra::input::{self, GamepadAxis, GamepadButton, Key};
+use tetra::math::Vec2;
+use tetra::Context;
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::control::{Actions, Intent, PaddleController};
+use mfight_ng::session::Player;
+use mfight_ng::sim::{PaddleId, PaddleInput, Side, Simulation, TIMESTEP};
+
+use crate::bindings::{Action, Bindings};
+use crate::scene::Shared;
+
+pub type BoxedController = Box<dyn PaddleController<Context>>;
+
+/// Stick deflections smaller than this count as the stick resting.
+const DEADZONE: f32 = 0.2;
+/// Shape of the stick response: above 1, small deflections give finer control.
+const RESPONSE_EXPONENT: f32 = 2.0;
+
+/// Maps a raw stick axis position to a paddle movement: positions inside the deadzone give
+/// no movement, and the rest of the range is rescaled onto a power curve from 0 to 1.
+pub fn stick_response(value: f32, deadzone: f32, exponent: f32) -> f32 {
+	let magnitude = value.abs();
+	if magnitude <= deadzone {
+		return 0.0;
+	}
+	let scaled = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0);
+	scaled.powf(exponent).copysign(value)
+}
+
+/// Which gamepad each player uses. Pads are handed to the first player without one as they
+/// are plugged in, and unplugging a pad frees its player's slot. Cheap to clone; every clone
+/// sees the same assignment, so controllers built before a pad arrives still pick it up.
+#[derive(Debug, Clone, Default)]
+pub struct GamepadSlots(Rc<Cell<[Option<usize>; 4]>>);
+
+impl GamepadSlots {
+	pub fn get(&self, player: Player) -> Option<usize> {
+		self.0.get()[player.index()]
+	}
+
+	/// Every assigned pad, for menus that any player can drive.
+	pub fn connected(&self) -> impl Iterator<Item = usize> {
+		IntoIterator::into_iter(self.0.get()).flatten()
+	}
+
+	pub fn connect(&self, id: usize) {
+		let mut slots = self.0.get();
+		if slots.contains(&Some(id)) {
+			return;
+		}
+		if let Some(slot) = slots.iter_mut().find(|slot| slot.is_none()) {
+			*slot = Some(id);
+			self.0.set(slots);
+		}
+	}
+
+	pub fn disconnect(&self, id: usize) {
+		let mut slots = self.0.get();
+		for slot in &mut slots {
+			if *slot == Some(id) {
+				*slot = None;
+			}
+		}
+		self.0.set(slots);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySet {
+	/// Player one's up and down keys.
+	Player1,
+	/// Player two's up and down keys.
+	Player2,
+	/// Either of the above, for a single player at the keyboard.
+	Any,
+}
+
+impl KeySet {
+	/// The (up, down) key pairs of this set under `bindings`, which move a paddle on the top
+	/// or bottom left and right.
+	fn keys(self, bindings: &Bindings) -> Vec<(Key, Key)> {
+		let player1 = (bindings.get(Action::P1Up), bindings.get(Action::P1Down));
+		let player2 = (bindings.get(Action::P2Up), bindings.get(Action::P2Down));
+		match self {
+			KeySet::Player1 => vec![player1],
+			KeySet::Player2 => vec![player2],
+			KeySet::Any => vec![player1, player2],
+		}
+	}
+}
+
+pub struct KeyboardController {
+	keys: Vec<(Key, Key)>,
+}
+
+impl KeyboardController {
+	pub fn new(keys: KeySet, bindings: &Bindings) -> KeyboardController {
+		KeyboardController { keys: keys.keys(bindings) }
+	}
+}
+
+impl PaddleController<Context> for KeyboardController {
+	fn intent(&mut self, ctx: &Context, _simulation: &Simulation) -> Intent {
+		let mut movement: f32 = 0.0;
+		for &(up, down) in &self.keys {
+			if input::is_key_down(ctx, up) {
+				movement -= 1.0;
+			}
+			if input::is_key_down(ctx, down) {
+				movement += 1.0;
+			}
+		}
+		PaddleInput::new(movement.clamp(-1.0, 1.0)).into()
+	}
+}
+
+/// Drives a paddle from whichever pad is assigned to a player: the left stick sets the
+/// paddle's speed, the D-pad moves it at full speed, and Start pauses. Paddles on the top
+/// and bottom follow the stick and D-pad left and right instead of up and down.
+pub struct GamepadController {
+	player: Player,
+	side: Side,
+	slots: GamepadSlots,
+	/// Actions seen since the last intent, so presses between ticks are not lost.
+	pending: Actions,
+}
+
+impl GamepadController {
+	pub fn new(player: Player, side: Side, slots: GamepadSlots) -> GamepadController {
+		GamepadController { player, side, slots, pending: Actions::NONE }
+	}
+}
+
+impl PaddleController<Context> for GamepadController {
+	fn poll(&mut self, ctx: &Context) {
+		let id = match self.slots.get(self.player) {
+			Some(id) => id,
+			None => return,
+		};
+		if input::is_gamepad_button_pressed(ctx, id, GamepadButton::Start) {
+			self.pending.insert(Actions::PAUSE);
+		}
+	}
+
+	fn intent(&mut self, ctx: &Context, _simulation: &Simulation) -> Intent {
+		let actions = std::mem::take(&mut self.pending);
+		let id = match self.slots.get(self.player) {
+			Some(id) => id,
+			None => return Intent { input: PaddleInput::default(), actions },
+		};
+
+		let (back, forward, axis) = if self.side.is_horizontal() {
+			(GamepadButton::Left, GamepadButton::Right, GamepadAxis::LeftStickX)
+		} else {
+			(GamepadButton::Up, GamepadButton::Down, GamepadAxis::LeftStickY)
+		};
+		let movement = if input::is_gamepad_button_down(ctx, id, back) {
+			-1.0
+		} else if input::is_gamepad_button_down(ctx, id, forward) {
+			1.0
+		} else {
+			let stick = input::get_gamepad_axis_position(ctx, id, axis);
+			stick_response(stick, DEADZONE, RESPONSE_EXPONENT)
+		};
+
+		Intent { input: PaddleInput::new(movement), actions }
+	}
+}
+
+/// Where the mouse is and how far it moved during the current frame, in arena coordinates
+/// rather than the window's, which may be scaled. Like `GamepadSlots`, every clone shares
+/// the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MouseTracker(Rc<MouseState>);
+
+#[derive(Debug, Default)]
+struct MouseState {
+	position: Cell<Vec2<f32>>,
+	delta: Cell<Vec2<f32>>,
+}
+
+impl MouseTracker {
+	pub fn position(&self) -> Vec2<f32> {
+		self.0.position.get()
+	}
+
+	pub fn set_position(&self, position: Vec2<f32>) {
+		self.0.position.set(position);
+	}
+
+	/// Movement since the last `clear_delta`.
+	pub fn delta(&self) -> Vec2<f32> {
+		self.0.delta.get()
+	}
+
+	pub fn add_delta(&self, delta: Vec2<f32>) {
+		self.0.delta.set(self.0.delta.get() + delta);
+	}
+
+	pub fn clear_delta(&self) {
+		self.0.delta.set(Vec2::zero());
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseMode {
+	/// The paddle chases the cursor.
+	Absolute,
+	/// The cursor is grabbed and hidden, and moving the mouse moves the paddle's target.
+	Relative,
+}
+
+/// Moves a paddle towards a target height set by the mouse, no faster than `max_speed`
+/// (a fraction of the paddle speed), so a flick of the wrist cannot teleport it. Paddles on
+/// the top and bottom follow the mouse left and right instead.
+pub struct MouseController {
+	paddle: PaddleId,
+	mode: MouseMode,
+	max_speed: f32,
+	mouse: MouseTracker,
+	/// In relative mode, where the paddle is heading; starts at the paddle's centre.
+	target: Option<f32>,
+	/// In relative mode, movement since the last intent.
+	delta: f32,
+}
+
+impl MouseController {
+	pub fn new(paddle: PaddleId, mode: MouseMode, max_speed: f32, mouse: MouseTracker) -> MouseController {
+		MouseController { paddle, mode, max_speed, mouse, target: None, delta: 0.0 }
+	}
+
+	/// The component of `value` along the side the paddle moves.
+	fn along(&self, value: Vec2<f32>) -> f32 {
+		if self.paddle.side.is_horizontal() {
+			value.x
+		} else {
+			value.y
+		}
+	}
+}
+
+impl PaddleController<Context> for MouseController {
+	fn poll(&mut self, _ctx: &Context) {
+		self.delta += self.along(self.mouse.delta());
+	}
+
+	fn intent(&mut self, _ctx: &Context, simulation: &Simulation) -> Intent {
+		let config = &simulation.config;
+		let paddle = simulation.paddle_at(self.paddle);
+		let centre = self.along(paddle.centre());
+		let target = match self.mode {
+			MouseMode::Absolute => self.along(self.mouse.position()),
+			MouseMode::Relative => {
+				let half = self.along(paddle.size) / 2.0;
+				let length = self.along(Vec2::new(config.arena_width, config.arena_height));
+				let target = self.target.unwrap_or(centre) + std::mem::take(&mut self.delta);
+				let target = target.clamp(half, length - half);
+				self.target = Some(target);
+				target
+			}
+		};
+
+		let limit = self.max_speed.min(1.0);
+		let movement = (target - centre) / (config.paddle_speed * TIMESTEP);
+		PaddleInput::new(movement.clamp(-limit, limit)).into()
+	}
+}
+
+/// Who controls a paddle, as picked when setting up a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+	Keyboard(KeySet),
+	/// The pad assigned to this player, whenever one is plugged in.
+	Gamepad(Player),
+	Mouse(MouseMode),
+	Cpu(Difficulty),
+}
+
+impl Control {
+	/// Every choice offered in the match setup, in the order they are cycled through.
+	pub fn choices() -> Vec<Control> {
+		let mut choices = vec![
+			Control::Keyboard(KeySet::Any),
+			Control::Keyboard(KeySet::Player1),
+			Control::Keyboard(KeySet::Player2),
+			Control::Gamepad(Player::One),
+			Control::Gamepad(Player::Two),
+			Control::Gamepad(Player::Three),
+			Control::Gamepad(Player::Four),
+			Control::Mouse(MouseMode::Absolute),
+			Control::Mouse(MouseMode::Relative),
+		];
+		choices.extend(Difficulty::ALL.iter().map(|&difficulty| Control::Cpu(difficulty)));
+		choices
+	}
+
+	pub fn name(self) -> String {
+		match self {
+			Control::Keyboard(KeySet::Any) => "Keyboard".to_string(),
+			Control::Keyboard(KeySet::Player1) => "Keyboard (P1 keys)".to_string(),
+			Control::Keyboard(KeySet::Player2) => "Keyboard (P2 keys)".to_string(),
+			Control::Gamepad(player) => format!("Gamepad {}", player.index() + 1),
+			Control::Mouse(MouseMode::Absolute) => "Mouse".to_string(),
+			Control::Mouse(MouseMode::Relative) => "Mouse (relative)".to_string(),
+			Control::Cpu(difficulty) => format!("CPU ({})", difficulty.name()),
+		}
+	}
+
+	/// Whether the cursor should be grabbed while this control is playing.
+	pub fn grabs_mouse(self) -> bool {
+		self == Control::Mouse(MouseMode::Relative)
+	}
+
+	pub fn build(self, paddle: PaddleId, shared: &Shared) -> BoxedController {
+		match self {
+			Control::Keyboard(keys) => Box::new(KeyboardController::new(keys, &shared.bindings)),
+			Control::Gamepad(player) => {
+				Box::new(GamepadController::new(player, paddle.side, shared.gamepads.clone()))
+			}
+			Control::Mouse(mode) => {
+				Box::new(MouseController::new(paddle, mode, shared.mouse_speed, shared.mouse.clone()))
+			}
+			Control::Cpu(difficulty) => Box::new(Ai::for_paddle(paddle, difficulty.profile())),
+		}
+	}
+}
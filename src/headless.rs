@@ -0,0 +1,217 @@
+//! Matches played without a window, as fast as the CPU allows, for scripts and benchmarks.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::control::PaddleController;
+use mfight_ng::net::OnlineMatch;
+use mfight_ng::replay::{Replay, ReplayPlayer, MAX_TICKS};
+use mfight_ng::session::{Player, Session};
+use mfight_ng::sim::{Format, Side, Simulation, TickInput, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+/// How long an online match keeps answering once it is over, so the other player gets
+/// every last input they need to finish it too.
+const LINGER: Duration = Duration::from_secs(1);
+
+/// Plays `matches` CPU-only matches of `session` in a row, printing each result and a
+/// summary. `difficulty` is indexed by `Player::index`.
+pub fn run(mut session: Session, difficulty: [Difficulty; 2], matches: u32) {
+	let started = Instant::now();
+	let mut ticks = 0;
+
+	for number in 1..=matches {
+		let mut simulation = session.new_match();
+		let mut players = [Player::One, Player::Two].map(|player| {
+			Ai::new(session.side_of(player), difficulty[player.index()])
+		});
+
+		while simulation.result.is_none() && simulation.tick < MAX_TICKS {
+			let mut input = TickInput::default();
+			for &player in &[Player::One, Player::Two] {
+				let intent = players[player.index()].intent(&(), &simulation);
+				input.set(session.side_of(player), intent.input);
+			}
+			simulation.step(&input);
+		}
+		ticks += simulation.tick;
+
+		let seconds = simulation.tick as f32 / TICK_RATE as f32;
+		match simulation.result {
+			Some(result) => {
+				let winner = session.player_on(result.winner).index() + 1;
+				let score = result.score;
+				println!(
+					"Match {}: player {} wins {} - {} after {:.1}s (seed {})",
+					number, winner, score.get(result.winner), score.get(result.winner.opponent()), seconds, simulation.seed,
+				);
+				session.record(&result);
+			}
+			None => {
+				let score = simulation.score;
+				println!(
+					"Match {}: abandoned at {} - {} after {:.1}s (seed {})",
+					number, score.left, score.right, seconds, simulation.seed,
+				);
+				// Unrecorded, so the next match would get the same seed and play out the same.
+				session.seed = session.seed.wrapping_add(1);
+			}
+		}
+	}
+
+	let stats = session.stats;
+	let elapsed = started.elapsed().as_secs_f64();
+	println!("Session: {} - {} over {} matches", stats.wins[0], stats.wins[1], stats.matches);
+	println!(
+		"Simulated {} ticks in {:.3}s ({:.0} ticks/s)",
+		ticks, elapsed, ticks as f64 / elapsed.max(f64::EPSILON),
+	);
+}
+
+/// Plays `replay` back and reports whether it still ends the way it was recorded.
+pub fn verify(replay: Replay) -> bool {
+	let mut player = ReplayPlayer::new(replay);
+	while player.step() {}
+
+	let simulation = player.simulation();
+	let seconds = simulation.tick as f32 / TICK_RATE as f32;
+	println!("Replay: {} after {:.1}s (seed {})", outcome(simulation), seconds, simulation.seed);
+
+	let verified = player.verify() == Some(true);
+	if verified {
+		println!("Replay verified");
+	} else {
+		println!("Replay desynced: the match no longer plays out as recorded");
+	}
+	verified
+}
+
+/// How `simulation` ended, or stands if it never did. A four-way match has no score to
+/// speak of, so every side's lives left are given instead.
+fn outcome(simulation: &Simulation) -> String {
+	let score = simulation.score;
+	match (simulation.rules.format, simulation.result) {
+		(Format::FourWay, result) => {
+			let lives = Side::ALL
+				.iter()
+				.map(|&side| format!("{} {}", side.name(), simulation.lives(side)))
+				.collect::<Vec<_>>()
+				.join(", ");
+			match result {
+				Some(result) => format!("{} side wins, lives left: {}", result.winner.name(), lives),
+				None => format!("ends with lives left: {}", lives),
+			}
+		}
+		(_, Some(result)) => format!("{} side wins {} - {}", result.winner.name(), score.left, score.right),
+		(_, None) => format!("ends at {} - {}", score.left, score.right),
+	}
+}
+
+/// Plays an online match in real time with a CPU at `difficulty` as the local player,
+/// returning whether it finished with both ends agreeing on how it went.
+pub fn run_online(mut online: Box<dyn OnlineMatch>, difficulty: Difficulty) -> bool {
+	println!("{}", online.waiting());
+
+	let mut ai = None;
+	let mut timestep = FixedTimestep::new(TICK_RATE);
+	let mut last = Instant::now();
+	while online.result().is_none() {
+		if let Err(err) = online.poll() {
+			eprintln!("Online match abandoned: {}", err);
+			return false;
+		}
+		let now = Instant::now();
+		let elapsed = now - last;
+		last = now;
+
+		// The clock only runs once the match does, or the CPU would rush to catch up.
+		if let (Some(side), true) = (online.local_side(), online.simulation().is_some()) {
+			timestep.advance(elapsed);
+			let ai = ai.get_or_insert_with(|| {
+				println!("Connected, playing on the {} side", side.name());
+				Ai::new(side, difficulty)
+			});
+			while timestep.tick() {
+				let input = match online.simulation() {
+					Some(simulation) => ai.update(simulation),
+					None => break,
+				};
+				match online.advance(input) {
+					Ok(true) => {}
+					Ok(false) => break,
+					Err(err) => {
+						eprintln!("Online match abandoned: {}", err);
+						return false;
+					}
+				}
+			}
+		}
+		thread::sleep(Duration::from_millis(1));
+	}
+
+	let lingering = Instant::now();
+	while lingering.elapsed() < LINGER && online.poll().is_ok() {
+		thread::sleep(Duration::from_millis(10));
+	}
+	online.leave();
+
+	// Only reached once the result is settled.
+	let (result, simulation, side) = match (online.result(), online.confirmed(), online.local_side()) {
+		(Some(result), Some(simulation), Some(side)) => (result, simulation, side),
+		_ => return false,
+	};
+	let seconds = simulation.tick as f32 / TICK_RATE as f32;
+	let score = result.score;
+	println!(
+		"Online match: {} side wins {} - {} after {:.1}s (seed {}), playing on the {} side",
+		result.winner.name(), score.left, score.right, seconds, simulation.seed, side.name(),
+	);
+	let latency = online.latency();
+	println!(
+		"Ping {} ms, jitter {} ms, {} corrections",
+		latency.ping().map_or(0, |ping| ping.as_millis()), latency.jitter().as_millis(), online.corrections(),
+	);
+	if online.is_desynced() {
+		println!("Desynced: the two machines disagreed on the state of the match");
+		return false;
+	}
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use mfight_ng::config::Config;
+	use mfight_ng::replay::Recorder;
+	use mfight_ng::sim::MatchRules;
+
+	use super::*;
+
+	#[test]
+	fn four_way_replay_is_verified_with_every_side_reported() {
+		let rules = MatchRules { format: Format::FourWay, lives: 1, ..MatchRules::default() };
+		let mut simulation = Simulation::with_config(Config::default(), rules, 12);
+		let mut recorder = Recorder::new(&simulation);
+		let mut ais = Side::ALL.map(|side| Ai::new(side, Difficulty::Normal));
+		while simulation.result.is_none() {
+			assert!(simulation.tick < MAX_TICKS, "the match never finished");
+			let mut input = TickInput::default();
+			for (ai, &side) in ais.iter_mut().zip(&Side::ALL) {
+				input.set(side, ai.intent(&(), &simulation).input);
+			}
+			recorder.record(&input);
+			simulation.step(&input);
+		}
+
+		let winner = simulation.result.unwrap().winner;
+		let lives = |side| if side == winner { 1 } else { 0 };
+		assert_eq!(
+			outcome(&simulation),
+			format!(
+				"{} side wins, lives left: left {}, right {}, top {}, bottom {}",
+				winner.name(), lives(Side::Left), lives(Side::Right), lives(Side::Top), lives(Side::Bottom),
+			),
+		);
+		assert!(verify(recorder.finish(&simulation)));
+	}
+}
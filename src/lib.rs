@@ -0,0 +1,12 @@
+//! Game rules of mfight-ng, with no dependency on windowing or rendering.
+
+pub mod ai;
+pub mod args;
+pub mod config;
+pub mod control;
+pub mod net;
+pub mod replay;
+pub mod rng;
+pub mod session;
+pub mod sim;
+pub mod timestep;
@@ -1,184 +1,175 @@
-use tetra::graphics::{self, Color, Rectangle, Texture, text::{Text, Font}};
-use tetra::{Context, ContextBuilder, State};
-use tetra::input::{self, Key};
-use tetra::math::Vec2;
-
-struct Entity {
-	texture: Texture,
-	position: Vec2<f32>,
-	velocity: Vec2<f32>,
+mod assets;
+mod bindings;
+mod cli;
+mod controls;
+mod headless;
+mod scene;
+
+use std::env;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tetra::time::Timestep;
+use tetra::ContextBuilder;
+
+use mfight_ng::config::{Config, PRESETS};
+use mfight_ng::net::{
+	default_name, Broadcast, Connection, LinkConditions, MatchSettings, OnlineMatch, ServerClient, Spectator,
+};
+use mfight_ng::replay::Replay;
+use mfight_ng::session::Session;
+use mfight_ng::sim::{MatchRules, Side};
+
+use crate::cli::{with_default_port, Options, USAGE};
+use crate::controls::{Control, KeySet};
+use crate::scene::{SceneManager, Start};
+
+const CONFIG_PATH: &str = "./config.toml";
+
+/// Reports a problem with how the game was started, and exits.
+fn fail(message: &str) -> ! {
+	eprintln!("error: {}", message);
+	process::exit(2);
 }
 
-impl Entity {
-	fn new(texture: Texture, position: Vec2<f32>, velocity: Vec2<f32>) -> Entity {
-		Entity { texture, position, velocity }
+/// The tuning chosen by `options`: a preset, a config file, or else `CONFIG_PATH` if it
+/// exists. An invalid config ends the program, rather than silently playing a different game.
+fn load_config(options: &Options) -> Config {
+	if let Some(name) = &options.preset {
+		return Config::preset(name).unwrap_or_else(|| {
+			fail(&format!("unknown preset {:?}, expected one of: {}", name, PRESETS.join(", ")))
+		});
 	}
 
-	fn draw(&self, ctx: &mut Context) {
-		self.texture.draw(ctx, self.position);
-	}
-
-	fn fix_position(&mut self) {
-		let max_y = WINDOW_HEIGHT - self.height();
-		if self.position.y > max_y {
-			self.position.y = max_y;
-		} else if self.position.y < 0.0 {
-			self.position.y = 0.0;
-		}
-	}
-
-	fn width(&self) -> f32 {
-		self.texture.width() as f32
-	}
-
-	fn height(&self) -> f32 {
-		self.texture.height() as f32
-	}
-
-	fn bounds(&self) -> Rectangle {
-		Rectangle::new(
-			self.position.x,
-			self.position.y,
-			self.width(),
-			self.height(),
-		)
-	}
-
-	fn centre(&self) -> Vec2<f32> {
-		Vec2::new(
-			self.position.x + self.width()  / 2.0,
-			self.position.y + self.height() / 2.0,
-		)
-	}
+	let path = match &options.config {
+		Some(path) => path.as_path(),
+		None if Path::new(CONFIG_PATH).exists() => Path::new(CONFIG_PATH),
+		None => return Config::default(),
+	};
+	Config::load(path).unwrap_or_else(|err| fail(&format!("invalid config in {}: {}", path.display(), err)))
 }
 
-struct GameState {
-	player1: Entity,
-	player2: Entity,
-	ball: Entity,
-	font: Font,
-	end_text: Option<Text>,
+/// The address `value` names, or else ends the program.
+fn resolve(value: &str) -> SocketAddr {
+	let address = with_default_port(value);
+	address.to_socket_addrs().ok()
+		.and_then(|mut addresses| addresses.next())
+		.unwrap_or_else(|| fail(&format!("couldn't find the host {}", address)))
 }
 
-impl GameState {
-	fn new(ctx: &mut Context) -> tetra::Result<GameState> {
-		let font = Font::vector(ctx, "./resources/Ubuntu-MI.ttf", 44.0)?;
-
-		let player1_texture = Texture::new(ctx, "./resources/player1.png")?;
-		let player1_position = Vec2::new(
-			16.0,
-			(WINDOW_HEIGHT - player1_texture.height() as f32) / 2.0,
-		);
-
-		let player2_texture = Texture::new(ctx, "./resources/player2.png")?;
-		let player2_position = Vec2::new(
-			WINDOW_WIDTH - player2_texture.width() as f32 - 16.0,
-			(WINDOW_HEIGHT - player2_texture.height() as f32) / 2.0,
-		);
-
-		let ball_texture = Texture::new(ctx, "./resources/ball.png")?;
-		let ball_position = Vec2::new(
-			(WINDOW_WIDTH -  ball_texture.width()  as f32) / 2.0,
-			(WINDOW_HEIGHT - ball_texture.height() as f32) / 2.0,
-		);
-
-		Ok(GameState {
-			player1: Entity::new(player1_texture, player1_position, Vec2::zero()),
-			player2: Entity::new(player2_texture, player2_position, Vec2::zero()),
-			ball:	Entity::new(ball_texture,	ball_position   , Vec2::new(-BALL_SPEED, 0.0)),
-			font,
-			end_text: None,
+/// The online match `options` ask for, if any. Joining waits for the host or server to
+/// answer, as its config decides the size of the arena.
+fn connect(options: &Options, name: &str, config: Config, seed: u64) -> Option<Box<dyn OnlineMatch>> {
+	let connection: Result<Box<dyn OnlineMatch>, _> = if let Some(port) = options.host {
+		let settings = MatchSettings {
+			config,
+			rules: MatchRules { win_score: config.win_score, ..MatchRules::default() },
+			seed,
+			input_delay: options.input_delay,
+			host_side: Side::Left,
+		};
+		Connection::host(port, name, settings, options.conditions).map(|mut host| {
+			host.set_spectator_delay(options.spectator_delay);
+			Box::new(host) as _
 		})
+	} else if let Some(address) = &options.connect {
+		let host = resolve(address);
+		println!("Connecting to {}...", host);
+		Connection::join(host, name, options.conditions).map(|join| Box::new(join) as _)
+	} else if let Some(address) = &options.server {
+		let server = resolve(address);
+		println!("Connecting to the server at {}...", server);
+		ServerClient::connect(server, name, options.conditions).map(|client| Box::new(client) as _)
+	} else {
+		return None;
+	};
+	let mut connection = connection.unwrap_or_else(|err| fail(&format!("couldn't start the online match: {}", err)));
+
+	while connection.config().is_none() {
+		if let Err(err) = connection.poll() {
+			fail(&format!("couldn't join the match: {}", err));
+		}
+		thread::sleep(Duration::from_millis(10));
 	}
+	Some(connection)
 }
 
-impl State for GameState {
-	fn update(&mut self, ctx: &mut Context) -> tetra::Result {
-		if self.end_text.is_some() {
-			return Ok(());
-		}
-
-		if input::is_key_down(ctx, Key::W) {
-			self.player1.position.y -= PADDLE_SPEED;
-		}
-		if input::is_key_down(ctx, Key::S) {
-			self.player1.position.y += PADDLE_SPEED;
-		}
-		self.player1.fix_position();
-
-		if input::is_key_down(ctx, Key::Up) {
-			self.player2.position.y -= PADDLE_SPEED;
-		}
-		if input::is_key_down(ctx, Key::Down) {
-			self.player2.position.y += PADDLE_SPEED;
-		}
-		self.player2.fix_position();
-		
-		self.ball.position += self.ball.velocity;
-		
-		let ball_bounds = self.ball.bounds();
-		let paddle_hit = if ball_bounds.intersects(&self.player1.bounds()) {
-			Some(&self.player1)
-		} else if ball_bounds.intersects(&self.player2.bounds()) {
-			Some(&self.player2)
-		} else {
-			None
-		};
-
-		if let Some(paddle) = paddle_hit {
-			// Increase the ball's velocity, then flip it.
-			self.ball.velocity.x = -(self.ball.velocity.x + (BALL_ACC * self.ball.velocity.x.signum()));
-
-			// Calculate the offset between the paddle and the ball, as a number between
-			// -1.0 and 1.0.
-			let offset = (paddle.centre().y - self.ball.centre().y) / paddle.height();
-	
-			// Apply the spin to the ball.
-			self.ball.velocity.y += PADDLE_SPIN * -offset;
-		}
-
-		if self.ball.position.y <= 0.0 || self.ball.position.y + self.ball.height() >= WINDOW_HEIGHT {
-			self.ball.velocity.y = -self.ball.velocity.y;
+/// The match `options` ask to watch, if any, once it is known how to show it.
+fn watch(options: &Options) -> Option<Spectator> {
+	let host = resolve(options.watch.as_ref()?);
+	println!("Asking {} to let us watch...", host);
+	let mut spectator = Spectator::watch(host, options.conditions)
+		.unwrap_or_else(|err| fail(&format!("couldn't watch the match: {}", err)));
+	while spectator.config().is_none() {
+		if let Err(err) = spectator.poll() {
+			fail(&format!("couldn't watch the match: {}", err));
 		}
+		thread::sleep(Duration::from_millis(10));
+	}
+	Some(spectator)
+}
 
-		if self.ball.position.x < 0.0 {
-			self.end_text = Some(Text::new("Player 2 win!", self.font.clone()));
-		} else if self.ball.position.x > WINDOW_WIDTH {
-			self.end_text = Some(Text::new("Player 1 win!", self.font.clone()));
-		}
-	
-		Ok(())
+fn main() -> tetra::Result {
+	let options = Options::parse(env::args().skip(1)).unwrap_or_else(|err| {
+		fail(&format!("{}\n\n{}", err, USAGE))
+	});
+	if options.help {
+		print!("{}", USAGE);
+		return Ok(());
 	}
 
-	fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
-		graphics::clear(ctx, Color::rgb(0.392, 0.584, 0.929));
-		
-		if let Some(text) = &mut self.end_text {
-			text.draw(ctx, Vec2::new(
-				WINDOW_WIDTH / 2.0 - 140.0,
-				WINDOW_HEIGHT / 2.0 - 22.0,
-			));
+	let replay = options.replay.as_ref().map(|path| {
+		Replay::load(path).unwrap_or_else(|err| fail(&format!("couldn't read replay {}: {}", path.display(), err)))
+	});
+	let config = replay.as_ref().map_or_else(|| load_config(&options), |replay| replay.config);
+	let seed = options.seed.unwrap_or_else(|| {
+		SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+	});
+	let name = options.name.clone().unwrap_or_else(default_name);
+	let connection = connect(&options, &name, config, seed);
+	let spectator = watch(&options);
+	let config = connection.as_ref().and_then(|connection| connection.config())
+		.or_else(|| spectator.as_ref().and_then(Spectator::config))
+		.unwrap_or(config);
+
+	if options.headless {
+		if let Some(connection) = connection {
+			if !headless::run_online(connection, options.difficulty[0]) {
+				process::exit(1);
+			}
 			return Ok(());
 		}
-
-		self.player1.draw(ctx);
-		self.player2.draw(ctx);
-		self.ball.draw(ctx);
-
-		Ok(())
+		if let Some(replay) = replay {
+			if !headless::verify(replay) {
+				process::exit(1);
+			}
+			return Ok(());
+		}
+		let rules = MatchRules { win_score: config.win_score, ..MatchRules::default() };
+		headless::run(Session::new(config, rules, seed), options.difficulty, options.matches);
+		return Ok(());
 	}
-}
-
-const WINDOW_WIDTH:  f32 = 640.0;
-const WINDOW_HEIGHT: f32 = 480.0;
-const PADDLE_SPEED:  f32 = 8.0;
-const BALL_SPEED:	f32 = 5.0;
-const PADDLE_SPIN: f32 = 4.0;
-const BALL_ACC: f32 = 0.05;
 
-fn main() -> tetra::Result {
-	ContextBuilder::new("Pong", WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32)
+	let (width, height) = options.window.unwrap_or((config.arena_width as i32, config.arena_height as i32));
+	let start = match (connection, spectator, replay, options.mode) {
+		(Some(connection), _, _, _) => Start::Online(connection, Control::Keyboard(KeySet::Any)),
+		(None, Some(spectator), _, _) => Start::Watch(Box::new(spectator)),
+		(None, None, Some(replay), _) => Start::Replay(replay),
+		(None, None, None, Some(mode)) => Start::Match(mode.controls(options.difficulty)),
+		(None, None, None, None) => Start::Title,
+	};
+	let broadcast = options.spectators.map(|port| {
+		Broadcast::bind(port, options.spectator_delay, LinkConditions::default())
+			.unwrap_or_else(|err| fail(&format!("couldn't let spectators in on port {}: {}", port, err)))
+	});
+	let spectator_delay = options.spectator_delay;
+	ContextBuilder::new("Pong", width, height)
+		.timestep(Timestep::Variable)
+		.resizable(true)
+		.fullscreen(options.fullscreen)
 		.build()?
-		.run(GameState::new)
+		.run(|ctx| SceneManager::new(ctx, config, seed, name, spectator_delay, broadcast, start))
 }
-
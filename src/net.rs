@@ -0,0 +1,680 @@
+//! Playing a match between two machines over UDP.
+//!
+//! Both peers run the whole simulation and only exchange their players' inputs, relying
+//! on it being deterministic. One peer hosts: it picks the config, rules, seed and input
+//! delay, and sends them to the peer that joins. `Rollback` hides the time inputs take to
+//! arrive.
+//!
+//! Alternatively a dedicated `Server` runs the only simulation that counts: `ServerClient`s
+//! send it their inputs and predict the match from the snapshots it sends back, so a
+//! modified client can't change how the match goes.
+//!
+//! On a local network, players find each other's `Lobby` with a `Browser` instead of
+//! typing addresses, and agree on sides there before the match starts.
+//!
+//! Spectators watch with a `Spectator`, from a server, the host of a match, or a
+//! `Broadcast` of local matches. They are shown the match a few seconds late, from
+//! snapshots, so they can join at any point of it.
+
+mod client;
+mod discovery;
+mod link;
+mod lobby;
+mod protocol;
+mod rollback;
+mod server;
+mod snapshot;
+mod spectate;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::sim::{MatchResult, PaddleInput, Side, Simulation};
+
+use self::spectate::Audience;
+
+pub use self::client::ServerClient;
+pub use self::discovery::{default_name, Browser, FoundGame, DISCOVERY_PORTS};
+pub use self::link::{Link, LinkConditions};
+pub use self::lobby::{Lobby, LobbyPlayer, COUNTDOWN};
+pub use self::protocol::{
+	Announcement, DecodeError, GameKind, MatchSettings, Message, MAX_INPUTS, MAX_NAME_LENGTH, PROTOCOL_VERSION,
+};
+pub use self::rollback::{Rollback, CHECKSUM_INTERVAL, MAX_PREDICTION};
+pub use self::server::{Server, ServerEvent, MAX_INPUT_AHEAD, MAX_VIOLATIONS, SNAPSHOT_INTERVAL};
+pub use self::snapshot::{BallState, Motion, Snapshot};
+pub use self::spectate::{Broadcast, Spectator, DEFAULT_SPECTATOR_DELAY, MAX_SPECTATORS};
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Input delay the host picks unless told otherwise, in ticks.
+pub const DEFAULT_INPUT_DELAY: u32 = 2;
+/// Longest input delay a host may ask for, in ticks; more makes the game unplayable.
+pub const MAX_INPUT_DELAY: u32 = 10;
+
+/// How often a joining peer repeats its request while the host hasn't answered.
+const JOIN_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a joining peer waits for the host to answer before giving up.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the peer may stay silent before the match is considered lost.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often inputs are sent while none are being made, so acknowledgements still flow.
+const RESEND_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum NetError {
+	Io(io::Error),
+	/// The host didn't answer.
+	NoAnswer,
+	/// The peer runs a build speaking another version of the protocol.
+	Version(u16),
+	/// The host sent settings this build can't play with.
+	BadSettings(String),
+	/// Nothing was heard from the peer for too long.
+	TimedOut,
+	PeerLeft,
+	/// The server turned us away, or removed us from the match.
+	Refused(String),
+	/// The match being watched is no longer shown.
+	Closed,
+}
+
+impl fmt::Display for NetError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			NetError::Io(err) => write!(f, "{}", err),
+			NetError::NoAnswer => write!(f, "the host did not answer"),
+			NetError::Version(version) => {
+				write!(f, "the other player runs protocol version {}, but this build runs {}", version, PROTOCOL_VERSION)
+			}
+			NetError::BadSettings(reason) => write!(f, "the host's settings are not playable: {}", reason),
+			NetError::TimedOut => write!(f, "lost the connection to the other player"),
+			NetError::PeerLeft => write!(f, "the other player left"),
+			NetError::Refused(reason) => write!(f, "the server refused us: {}", reason),
+			NetError::Closed => write!(f, "the match is no longer being shown"),
+		}
+	}
+}
+
+impl Error for NetError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			NetError::Io(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for NetError {
+	fn from(err: io::Error) -> NetError {
+		NetError::Io(err)
+	}
+}
+
+/// A non-blocking UDP socket sending through a `Link`.
+pub struct Socket {
+	socket: UdpSocket,
+	/// Packets on their way out, with where they are going.
+	link: Link<(SocketAddr, Vec<u8>)>,
+}
+
+impl Socket {
+	pub fn bind(address: SocketAddr, conditions: LinkConditions) -> io::Result<Socket> {
+		let socket = UdpSocket::bind(address)?;
+		socket.set_nonblocking(true)?;
+		let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+		Ok(Socket { socket, link: Link::new(conditions, seed) })
+	}
+
+	/// Binds any free port that can reach `remote`.
+	pub fn bind_for(remote: SocketAddr, conditions: LinkConditions) -> io::Result<Socket> {
+		let any = match remote {
+			SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+			SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+		};
+		Socket::bind(any, conditions)
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	pub fn send(&mut self, message: &Message, to: SocketAddr) -> io::Result<()> {
+		self.link.send(Instant::now(), (to, message.encode()));
+		self.flush()
+	}
+
+	/// Sends the packets the link has held back for long enough.
+	pub fn flush(&mut self) -> io::Result<()> {
+		for (to, packet) in self.link.due(Instant::now()) {
+			match self.socket.send_to(&packet, to) {
+				Ok(_) => {}
+				// Full buffers and unreachable peers lose the packet, as the network might.
+				Err(err) if is_transient(&err) => {}
+				Err(err) => return Err(err),
+			}
+		}
+		Ok(())
+	}
+
+	/// Sends `message` straight out, bypassing the link: for the last thing sent before
+	/// leaving, when nothing will flush the link again. Any failure is ignored.
+	pub fn send_now(&self, message: &Message, to: SocketAddr) {
+		let _ = self.socket.send_to(&message.encode(), to);
+	}
+
+	/// Sorts out a packet received `from` someone: the message, or `None` if it was garbled
+	/// or stray, which is dropped as if lost. A packet from an incompatible version of the
+	/// game is an error, unless `answer` is set: then `from` is refused in our own version,
+	/// which tells them why, and the packet dropped.
+	pub fn accept(
+		&mut self,
+		from: SocketAddr,
+		message: Result<Message, DecodeError>,
+		answer: bool,
+	) -> Result<Option<Message>, NetError> {
+		match message {
+			Ok(message) => Ok(Some(message)),
+			Err(DecodeError::Version(_)) if answer => {
+				self.send(&Message::Refuse { reason: "incompatible version".to_string() }, from)?;
+				Ok(None)
+			}
+			Err(DecodeError::Version(version)) => Err(NetError::Version(version)),
+			Err(_) => Ok(None),
+		}
+	}
+
+	/// The next packet received, if any is waiting.
+	pub fn receive(&mut self) -> io::Result<Option<(SocketAddr, Result<Message, DecodeError>)>> {
+		let mut buffer = [0; 2048];
+		loop {
+			match self.socket.recv_from(&mut buffer) {
+				Ok((length, from)) => return Ok(Some((from, Message::decode(&buffer[..length])))),
+				Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+				Err(err) if is_transient(&err) => continue,
+				Err(err) => return Err(err),
+			}
+		}
+	}
+}
+
+/// Errors that only mean a packet was lost. Some systems report a peer's closed port as
+/// an error on the next receive.
+fn is_transient(err: &io::Error) -> bool {
+	matches!(
+		err.kind(),
+		io::ErrorKind::WouldBlock | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused
+	)
+}
+
+/// Measures the round trip to the peer, smoothed the way TCP does (RFC 6298).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Latency {
+	round_trip: Option<Duration>,
+	jitter: Duration,
+}
+
+impl Latency {
+	/// Smoothed round-trip time, once measured.
+	pub fn ping(&self) -> Option<Duration> {
+		self.round_trip
+	}
+
+	/// Average difference between round trips and the smoothed round-trip time.
+	pub fn jitter(&self) -> Duration {
+		self.jitter
+	}
+
+	pub fn add_sample(&mut self, sample: Duration) {
+		let sample = sample.as_secs_f32();
+		match self.round_trip {
+			None => {
+				self.round_trip = Some(Duration::from_secs_f32(sample));
+				self.jitter = Duration::from_secs_f32(sample / 2.0);
+			}
+			Some(round_trip) => {
+				let (round_trip, jitter) = (round_trip.as_secs_f32(), self.jitter.as_secs_f32());
+				let jitter = 0.75 * jitter + 0.25 * (round_trip - sample).abs();
+				let round_trip = 0.875 * round_trip + 0.125 * sample;
+				self.round_trip = Some(Duration::from_secs_f32(round_trip));
+				self.jitter = Duration::from_secs_f32(jitter);
+			}
+		}
+	}
+
+	/// Takes the `Pong` answering a ping sent `time` microseconds after `started`.
+	pub fn pong(&mut self, started: Instant, time: u64, now: Instant) {
+		let sent = Duration::from_micros(time);
+		if let Some(round_trip) = now.duration_since(started).checked_sub(sent) {
+			self.add_sample(round_trip);
+		}
+	}
+}
+
+/// What the game needs from an online match, whether played peer to peer or on a server.
+/// Call `poll` every frame, and `advance` once per tick once `simulation` is available.
+pub trait OnlineMatch {
+	/// Handles every packet received since the last call, and keeps the connection alive.
+	fn poll(&mut self) -> Result<(), NetError>;
+
+	/// Plays the next tick with `input` as the local player's. Returns `false` if the match
+	/// is waiting for the other end, or not yet running.
+	fn advance(&mut self, input: PaddleInput) -> Result<bool, NetError>;
+
+	/// The match as it should be shown, once it started.
+	fn simulation(&self) -> Option<&Simulation>;
+
+	/// The match as far as every input in it is settled.
+	fn confirmed(&self) -> Option<&Simulation>;
+
+	/// The side the local player plays on, once known.
+	fn local_side(&self) -> Option<Side>;
+
+	/// The config the match is played with, once known.
+	fn config(&self) -> Option<Config>;
+
+	fn latency(&self) -> Latency;
+
+	/// What the match is waiting for before it can start.
+	fn waiting(&self) -> String;
+
+	/// Whether the match is held up waiting for the other end.
+	fn is_stalled(&self) -> bool;
+
+	/// Whether the two ends found they disagree on the state of the match.
+	fn is_desynced(&self) -> bool;
+
+	/// How many times a prediction of the match turned out wrong and was corrected.
+	fn corrections(&self) -> u64;
+
+	/// Tells the other end we are leaving, so it doesn't wait for us to time out.
+	fn leave(&mut self);
+
+	/// How many spectators this end shows the match to.
+	fn spectators(&self) -> usize {
+		0
+	}
+
+	/// The settled result, once the match is over.
+	fn result(&self) -> Option<MatchResult> {
+		self.confirmed().and_then(|simulation| simulation.result)
+	}
+}
+
+enum Role {
+	/// Waiting for, or playing against, whoever joins first.
+	Host,
+	/// Joining the host at this address.
+	Join(SocketAddr),
+}
+
+/// One side of an online match, from the handshake to the end. Call `poll` every frame,
+/// and `advance` once per tick once `rollback` is available.
+pub struct Connection {
+	socket: Socket,
+	role: Role,
+	/// Our player's name, and the peer's once it joined; shown to spectators.
+	name: String,
+	peer_name: String,
+	/// Known to the host from the start, and to a joining peer once the host answers.
+	settings: Option<MatchSettings>,
+	peer: Option<SocketAddr>,
+	rollback: Option<Rollback>,
+	/// Number of our inputs the peer has acknowledged.
+	acked: u64,
+	latency: Latency,
+	/// Zero point of the times in pings.
+	started: Instant,
+	last_heard: Instant,
+	last_sent: Option<Instant>,
+	last_ping: Option<Instant>,
+	/// The host's spectators.
+	audience: Option<Audience>,
+}
+
+impl Connection {
+	/// Waits on `port` for a peer to join a match played with `settings`, against a player
+	/// called `name`.
+	pub fn host(port: u16, name: &str, settings: MatchSettings, conditions: LinkConditions) -> Result<Connection, NetError> {
+		let socket = Socket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)), conditions)?;
+		Ok(Connection::new(socket, Role::Host, name, Some(settings)))
+	}
+
+	/// Asks the host at `host` to join its match, for a player called `name`.
+	pub fn join(host: SocketAddr, name: &str, conditions: LinkConditions) -> Result<Connection, NetError> {
+		let socket = Socket::bind_for(host, conditions)?;
+		Ok(Connection::new(socket, Role::Join(host), name, None))
+	}
+
+	fn new(socket: Socket, role: Role, name: &str, settings: Option<MatchSettings>) -> Connection {
+		let now = Instant::now();
+		// Spectators watch the host, which has the match from the start.
+		let audience = match role {
+			Role::Host => Some(Audience::new(DEFAULT_SPECTATOR_DELAY)),
+			Role::Join(_) => None,
+		};
+		Connection {
+			socket,
+			role,
+			name: name.to_string(),
+			peer_name: String::new(),
+			settings,
+			peer: None,
+			rollback: None,
+			acked: 0,
+			latency: Latency::default(),
+			started: now,
+			last_heard: now,
+			last_sent: None,
+			last_ping: None,
+			audience,
+		}
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	pub fn is_host(&self) -> bool {
+		matches!(self.role, Role::Host)
+	}
+
+	/// Changes how far behind the players spectators are shown the match. Only the host
+	/// has spectators.
+	pub fn set_spectator_delay(&mut self, delay: Duration) {
+		if let Some(audience) = &mut self.audience {
+			audience.set_delay(delay);
+		}
+	}
+
+	pub fn spectators(&self) -> usize {
+		self.audience.as_ref().map_or(0, Audience::spectators)
+	}
+
+	pub fn settings(&self) -> Option<&MatchSettings> {
+		self.settings.as_ref()
+	}
+
+	/// The match, once both peers are connected.
+	pub fn rollback(&self) -> Option<&Rollback> {
+		self.rollback.as_ref()
+	}
+
+	pub fn simulation(&self) -> Option<&Simulation> {
+		self.rollback.as_ref().map(Rollback::simulation)
+	}
+
+	/// The result both peers agree on, once the match is over.
+	pub fn result(&self) -> Option<MatchResult> {
+		self.rollback.as_ref().and_then(|rollback| rollback.confirmed().result)
+	}
+
+	pub fn latency(&self) -> Latency {
+		self.latency
+	}
+
+	/// Handles every packet received since the last call, and keeps the connection alive.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			self.handle(from, message, now)?;
+		}
+
+		match self.role {
+			Role::Join(host) if self.rollback.is_none() => {
+				if now.duration_since(self.started) > JOIN_TIMEOUT {
+					return Err(NetError::NoAnswer);
+				}
+				if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= JOIN_INTERVAL) {
+					self.socket.send(&Message::Join { name: self.name.clone() }, host)?;
+					self.last_sent = Some(now);
+				}
+			}
+			_ if self.rollback.is_some() => {
+				if now.duration_since(self.last_heard) > PEER_TIMEOUT {
+					return Err(NetError::TimedOut);
+				}
+				if self.last_ping.is_none_or(|ping| now.duration_since(ping) >= PING_INTERVAL) {
+					let time = now.duration_since(self.started).as_micros() as u64;
+					self.send(&Message::Ping { time })?;
+					self.last_ping = Some(now);
+				}
+				if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= RESEND_INTERVAL) {
+					self.send_inputs()?;
+				}
+			}
+			_ => {}
+		}
+
+		self.record();
+		if let Some(audience) = &mut self.audience {
+			audience.update(&mut self.socket, now)?;
+		}
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	/// Plays the next tick with `input` as the local player's, and sends it to the peer.
+	/// Returns `false` if the match is waiting for the peer's inputs, or not yet running.
+	pub fn advance(&mut self, input: PaddleInput) -> Result<bool, NetError> {
+		let advanced = match &mut self.rollback {
+			Some(rollback) => rollback.advance(input),
+			None => return Ok(false),
+		};
+		if advanced {
+			self.record();
+			self.send_inputs()?;
+		}
+		Ok(advanced)
+	}
+
+	/// Tells the peer and any spectators we are leaving, so they don't wait for us to time out.
+	pub fn leave(&mut self) {
+		if let Some(peer) = self.peer {
+			self.socket.send_now(&Message::Leave, peer);
+		}
+		if let Some(audience) = &mut self.audience {
+			audience.close(&mut self.socket);
+		}
+	}
+
+	fn handle(&mut self, from: SocketAddr, message: Result<Message, DecodeError>, now: Instant) -> Result<(), NetError> {
+		if let (Role::Host, Ok(message)) = (&self.role, &message) {
+			if self.peer != Some(from) && self.handle_stranger(from, message, now)? {
+				return Ok(());
+			}
+		}
+
+		let expected = match self.role {
+			Role::Join(host) => Some(host),
+			Role::Host => self.peer,
+		};
+		if expected.is_some_and(|expected| expected != from) {
+			// Not the peer we are playing with. Anyone else trying to join gets no answer
+			// and times out, as the match is full.
+			return Ok(());
+		}
+
+		let message = match self.socket.accept(from, message, self.is_host())? {
+			Some(message) => message,
+			None => return Ok(()),
+		};
+		self.last_heard = now;
+
+		match message {
+			Message::Join { name } if self.is_host() => {
+				let settings = match &self.settings {
+					Some(settings) => settings.clone(),
+					None => return Ok(()),
+				};
+				if self.peer.is_none() {
+					self.peer = Some(from);
+					self.peer_name = name;
+					self.start(&settings, settings.host_side);
+				}
+				// Sent again whenever asked, in case the last answer was lost.
+				self.socket.send(&Message::Welcome { settings, name: self.name.clone() }, from)?;
+			}
+			Message::Welcome { settings, name } if !self.is_host() => {
+				if self.rollback.is_some() {
+					return Ok(());
+				}
+				if settings.input_delay > MAX_INPUT_DELAY {
+					return Err(NetError::BadSettings(format!(
+						"input delay of {} ticks, the most is {}", settings.input_delay, MAX_INPUT_DELAY,
+					)));
+				}
+				self.peer = Some(from);
+				self.peer_name = name;
+				self.start(&settings, settings.host_side.opponent());
+				self.settings = Some(settings);
+			}
+			Message::Inputs { ack, first, inputs, checksum } => {
+				if let Some(rollback) = &mut self.rollback {
+					self.acked = self.acked.max(ack);
+					rollback.receive(first, &inputs);
+					rollback.sync(first.saturating_add(inputs.len() as u64).saturating_sub(1), ack);
+					if let Some((tick, hash)) = checksum {
+						rollback.check(tick, hash);
+					}
+				}
+			}
+			Message::Ping { time } => self.socket.send(&Message::Pong { time }, from)?,
+			Message::Pong { time } => self.latency.pong(self.started, time, now),
+			Message::Leave => return Err(NetError::PeerLeft),
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Handles a packet from someone other than the peer, who may be looking for games or
+	/// want to watch. Returns whether it was one of those.
+	fn handle_stranger(&mut self, from: SocketAddr, message: &Message, now: Instant) -> Result<bool, NetError> {
+		if let Some(audience) = &mut self.audience {
+			if audience.handle(&mut self.socket, from, message, now)? {
+				return Ok(true);
+			}
+		}
+		match (message, &self.settings) {
+			(Message::Discover, Some(settings)) => {
+				let announcement = Announcement {
+					name: self.name.clone(),
+					mode: settings.mode(),
+					kind: GameKind::Lobby,
+					players: 1 + self.peer.is_some() as u8,
+					capacity: 2,
+				};
+				self.socket.send(&Message::Announce(announcement), from)?;
+				Ok(true)
+			}
+			_ => Ok(false),
+		}
+	}
+
+	fn start(&mut self, settings: &MatchSettings, side: Side) {
+		let simulation = Simulation::with_config(settings.config, settings.rules, settings.seed);
+		if let Some(audience) = &mut self.audience {
+			let mut names = [self.name.clone(), self.peer_name.clone(), String::new(), String::new()];
+			if side == Side::Right {
+				names.swap(0, 1);
+			}
+			audience.begin(settings.config, settings.rules, settings.seed, names);
+		}
+		self.rollback = Some(Rollback::new(simulation, side, settings.input_delay));
+		self.record();
+		self.last_heard = Instant::now();
+	}
+
+	/// Shows spectators how far the match is settled.
+	fn record(&mut self) {
+		if let (Some(audience), Some(rollback)) = (&mut self.audience, &self.rollback) {
+			audience.record(rollback.confirmed(), rollback.confirmed_inputs());
+		}
+	}
+
+	fn send(&mut self, message: &Message) -> io::Result<()> {
+		match self.peer {
+			Some(peer) => self.socket.send(message, peer),
+			None => Ok(()),
+		}
+	}
+
+	fn send_inputs(&mut self) -> io::Result<()> {
+		let message = match &self.rollback {
+			Some(rollback) => {
+				let (first, inputs) = rollback.unacked(self.acked);
+				Message::Inputs {
+					ack: rollback.received(),
+					first,
+					inputs: inputs[..inputs.len().min(MAX_INPUTS)].to_vec(),
+					checksum: rollback.checksum(),
+				}
+			}
+			None => return Ok(()),
+		};
+		self.last_sent = Some(Instant::now());
+		self.send(&message)
+	}
+}
+
+impl OnlineMatch for Connection {
+	fn poll(&mut self) -> Result<(), NetError> {
+		Connection::poll(self)
+	}
+
+	fn advance(&mut self, input: PaddleInput) -> Result<bool, NetError> {
+		Connection::advance(self, input)
+	}
+
+	fn simulation(&self) -> Option<&Simulation> {
+		Connection::simulation(self)
+	}
+
+	fn confirmed(&self) -> Option<&Simulation> {
+		self.rollback.as_ref().map(Rollback::confirmed)
+	}
+
+	fn local_side(&self) -> Option<Side> {
+		self.rollback.as_ref().map(Rollback::local_side)
+	}
+
+	fn config(&self) -> Option<Config> {
+		self.settings.as_ref().map(|settings| settings.config)
+	}
+
+	fn latency(&self) -> Latency {
+		self.latency
+	}
+
+	fn waiting(&self) -> String {
+		match self.local_addr() {
+			Ok(address) if self.is_host() => format!("Waiting for a player on port {}", address.port()),
+			_ => "Connecting...".to_string(),
+		}
+	}
+
+	fn is_stalled(&self) -> bool {
+		self.rollback.as_ref().is_some_and(Rollback::is_stalled)
+	}
+
+	fn is_desynced(&self) -> bool {
+		self.rollback.as_ref().is_some_and(Rollback::is_desynced)
+	}
+
+	fn corrections(&self) -> u64 {
+		self.rollback.as_ref().map_or(0, Rollback::rollbacks)
+	}
+
+	fn leave(&mut self) {
+		Connection::leave(self)
+	}
+
+	fn spectators(&self) -> usize {
+		Connection::spectators(self)
+	}
+}
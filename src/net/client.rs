@@ -0,0 +1,382 @@
+//! A player's end of a match on a dedicated server.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use vek::Vec2;
+
+use crate::config::Config;
+use crate::sim::{MatchRules, PaddleInput, Side, Simulation, TickInput};
+
+use super::protocol::{DecodeError, Message, MAX_INPUTS};
+use super::server::SNAPSHOT_INTERVAL;
+use super::snapshot::{Motion, Snapshot};
+use super::{
+	Latency, LinkConditions, NetError, OnlineMatch, Socket, JOIN_INTERVAL, JOIN_TIMEOUT, PEER_TIMEOUT, PING_INTERVAL,
+	RESEND_INTERVAL,
+};
+
+/// How many ticks before the server needs them the client aims to have its inputs there.
+/// Less and jitter makes some arrive late; more and the server holds every input longer.
+const TARGET_MARGIN: i32 = 2;
+/// Margins the client leaves alone, however the network varies.
+const MIN_MARGIN: i32 = 1;
+const MAX_MARGIN: i32 = 5;
+/// Most ticks played at once to catch up with the server.
+const MAX_CATCH_UP: u32 = 8;
+/// Snapshots kept as baselines for the server's deltas, and to interpolate the opponent.
+const KEPT_SNAPSHOTS: usize = 64;
+
+/// What the server told us about the match we were admitted to.
+#[derive(Debug, Clone, Copy)]
+struct Admission {
+	config: Config,
+	rules: MatchRules,
+	seed: u64,
+	side: Side,
+}
+
+/// Plays a match on a `Server`. The local paddle moves at once, predicted from our own
+/// inputs, and is corrected whenever a snapshot shows the server saw it otherwise. The
+/// opponent is shown where the latest snapshots had them, a little in the past.
+pub struct ServerClient {
+	socket: Socket,
+	server: SocketAddr,
+	/// Shown to spectators.
+	name: String,
+	admission: Option<Admission>,
+	/// The latest snapshots received, oldest first.
+	snapshots: VecDeque<Snapshot>,
+	/// The latest snapshot, restored: the match as the server has it.
+	confirmed: Option<Simulation>,
+	/// `confirmed` played on with our pending inputs, and the opponent predicted.
+	predicted: Option<Simulation>,
+	/// `predicted` with the opponent moved to where the snapshots had them.
+	shown: Option<Simulation>,
+	/// Our inputs for the ticks after `confirmed`'s.
+	pending: VecDeque<PaddleInput>,
+	/// Tick the opponent is shown at.
+	view: u64,
+	/// The server's margin in the latest snapshot.
+	margin: i32,
+	/// Our tick the pace last changed at. The next change waits until the server's margin
+	/// counts inputs from after it.
+	adjusted: u64,
+	/// Ticks to play at once on the next advance, to catch up with the server.
+	extra: u32,
+	/// Ticks to sit out, to let the server catch up with us.
+	skip: u32,
+	corrections: u64,
+	latency: Latency,
+	/// Zero point of the times in pings.
+	started: Instant,
+	last_heard: Instant,
+	last_sent: Option<Instant>,
+	last_ping: Option<Instant>,
+}
+
+impl ServerClient {
+	/// Asks the server at `server` for a place in its next match, for a player called `name`.
+	pub fn connect(server: SocketAddr, name: &str, conditions: LinkConditions) -> Result<ServerClient, NetError> {
+		let socket = Socket::bind_for(server, conditions)?;
+		let now = Instant::now();
+		Ok(ServerClient {
+			socket,
+			server,
+			name: name.to_string(),
+			admission: None,
+			snapshots: VecDeque::new(),
+			confirmed: None,
+			predicted: None,
+			shown: None,
+			pending: VecDeque::new(),
+			view: 0,
+			margin: 0,
+			adjusted: 0,
+			extra: 0,
+			skip: 0,
+			corrections: 0,
+			latency: Latency::default(),
+			started: now,
+			last_heard: now,
+			last_sent: None,
+			last_ping: None,
+		})
+	}
+
+	/// The latest snapshot the server sent.
+	pub fn snapshot(&self) -> Option<&Snapshot> {
+		self.snapshots.back()
+	}
+
+	fn handle(&mut self, message: Result<Message, DecodeError>, now: Instant) -> Result<(), NetError> {
+		let message = match self.socket.accept(self.server, message, false)? {
+			Some(message) => message,
+			None => return Ok(()),
+		};
+		self.last_heard = now;
+
+		match message {
+			Message::Admit { config, rules, seed, side } if self.admission.is_none() => {
+				self.admission = Some(Admission { config, rules, seed, side });
+			}
+			Message::State { baseline, margin, delta } => self.receive(baseline, margin, &delta),
+			Message::Pong { time } => self.latency.pong(self.started, time, now),
+			Message::Refuse { reason } => return Err(NetError::Refused(reason)),
+			// The server saying the other player left.
+			Message::Leave => return Err(NetError::PeerLeft),
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn receive(&mut self, baseline: u64, margin: i32, delta: &[u8]) {
+		let admission = match self.admission {
+			Some(admission) => admission,
+			None => return,
+		};
+		let baseline = match baseline {
+			0 => None,
+			tick => match self.snapshots.iter().find(|snapshot| snapshot.tick == tick) {
+				Some(snapshot) => Some(snapshot),
+				// Encoded against one we no longer have; the next will do.
+				None => return,
+			},
+		};
+		let snapshot = match Snapshot::decode_delta(delta, baseline) {
+			Ok(snapshot) => snapshot,
+			Err(_) => return,
+		};
+		if self.snapshots.back().is_some_and(|latest| latest.tick >= snapshot.tick) {
+			// Overtaken by a later one, or repeated once the match is over.
+			return;
+		}
+
+		self.margin = margin;
+		self.reconcile(&snapshot, admission);
+		self.pace(snapshot.tick);
+		if self.snapshots.is_empty() {
+			self.view = snapshot.tick;
+		}
+		self.snapshots.push_back(snapshot);
+		if self.snapshots.len() > KEPT_SNAPSHOTS {
+			self.snapshots.pop_front();
+		}
+		self.show();
+	}
+
+	/// Restarts the prediction from `snapshot`, replaying the inputs the server hadn't used yet.
+	fn reconcile(&mut self, snapshot: &Snapshot, admission: Admission) {
+		let confirmed = snapshot.restore(admission.config, admission.rules, admission.seed);
+		let before = self.confirmed.as_ref().map_or(0, |simulation| simulation.tick);
+		let used = (snapshot.tick.saturating_sub(before) as usize).min(self.pending.len());
+		self.pending.drain(..used);
+
+		let opponent = snapshot.inputs.get(admission.side.opponent());
+		let mut predicted = confirmed.clone();
+		for &input in &self.pending {
+			predicted.step(&tick_input(admission.side, input, opponent));
+		}
+		if self.predicted.as_ref().is_some_and(|old| old.tick == predicted.tick && *old != predicted) {
+			self.corrections += 1;
+		}
+		self.confirmed = Some(confirmed);
+		self.predicted = Some(predicted);
+	}
+
+	/// Speeds up or slows down to keep our inputs reaching the server `TARGET_MARGIN` ticks
+	/// before it needs them.
+	fn pace(&mut self, tick: u64) {
+		let newest = tick as i64 + self.margin as i64;
+		if newest < self.adjusted as i64 {
+			// The server hasn't seen the last change yet.
+			return;
+		}
+		let ours = self.predicted.as_ref().map_or(0, |simulation| simulation.tick);
+		if self.margin < MIN_MARGIN {
+			self.extra = ((TARGET_MARGIN - self.margin) as u32).min(MAX_CATCH_UP);
+			self.adjusted = ours + self.extra as u64;
+		} else if self.margin > MAX_MARGIN {
+			self.skip = (self.margin - TARGET_MARGIN) as u32;
+			self.adjusted = ours + 1;
+		}
+	}
+
+	/// Moves the opponent in `shown` to where the snapshots had them at `view`.
+	fn show(&mut self) {
+		let (predicted, side) = match (&self.predicted, self.admission) {
+			(Some(predicted), Some(admission)) => (predicted, admission.side.opponent()),
+			_ => return,
+		};
+		let latest = match self.snapshots.back() {
+			Some(latest) => latest.tick,
+			None => return,
+		};
+		// A snapshot behind the latest, so there is nearly always one either side of it.
+		let target = latest.saturating_sub(SNAPSHOT_INTERVAL);
+		if self.view.abs_diff(target) > 4 * SNAPSHOT_INTERVAL {
+			self.view = target;
+		}
+		self.view = self.view.min(latest);
+
+		let view = self.view;
+		let motion = |snapshot: &Snapshot| snapshot.paddles[side as usize].first().copied();
+		let position = match self.snapshots.iter().position(|snapshot| snapshot.tick >= view) {
+			Some(i) if i > 0 => {
+				let (from, to) = (&self.snapshots[i - 1], &self.snapshots[i]);
+				let t = (view - from.tick) as f32 / (to.tick - from.tick) as f32;
+				match (motion(from), motion(to)) {
+					(Some(from), Some(to)) => interpolate(from, to, t),
+					_ => return,
+				}
+			}
+			Some(i) => match motion(&self.snapshots[i]) {
+				Some(motion) => motion.position,
+				None => return,
+			},
+			None => return,
+		};
+
+		let mut shown = predicted.clone();
+		shown.paddle_mut(side).position = position;
+		self.shown = Some(shown);
+	}
+
+	fn send_command(&mut self) -> Result<(), NetError> {
+		let first = match &self.confirmed {
+			Some(confirmed) => confirmed.tick + 1,
+			None => return Ok(()),
+		};
+		let message = Message::Command {
+			ack: self.snapshots.back().map_or(0, |snapshot| snapshot.tick),
+			first,
+			inputs: self.pending.iter().take(MAX_INPUTS).copied().collect(),
+		};
+		self.last_sent = Some(Instant::now());
+		Ok(self.socket.send(&message, self.server)?)
+	}
+}
+
+impl OnlineMatch for ServerClient {
+	fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			if from == self.server {
+				self.handle(message, now)?;
+			}
+		}
+
+		if self.admission.is_none() {
+			if now.duration_since(self.started) > JOIN_TIMEOUT {
+				return Err(NetError::NoAnswer);
+			}
+			if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= JOIN_INTERVAL) {
+				self.socket.send(&Message::Enter { name: self.name.clone() }, self.server)?;
+				self.last_sent = Some(now);
+			}
+		} else {
+			if now.duration_since(self.last_heard) > PEER_TIMEOUT {
+				return Err(NetError::TimedOut);
+			}
+			if self.last_ping.is_none_or(|ping| now.duration_since(ping) >= PING_INTERVAL) {
+				let time = now.duration_since(self.started).as_micros() as u64;
+				self.socket.send(&Message::Ping { time }, self.server)?;
+				self.last_ping = Some(now);
+			}
+			if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= RESEND_INTERVAL) {
+				self.send_command()?;
+			}
+		}
+
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	fn advance(&mut self, input: PaddleInput) -> Result<bool, NetError> {
+		let side = match self.admission {
+			Some(admission) => admission.side,
+			None => return Ok(false),
+		};
+		let opponent = match self.snapshots.back() {
+			Some(snapshot) if snapshot.result.is_none() => snapshot.inputs.get(side.opponent()),
+			_ => return Ok(false),
+		};
+		if self.is_stalled() {
+			return Ok(false);
+		}
+		if self.skip > 0 {
+			self.skip -= 1;
+			return Ok(false);
+		}
+
+		if let Some(predicted) = &mut self.predicted {
+			for _ in 0..=std::mem::take(&mut self.extra) {
+				predicted.step(&tick_input(side, input, opponent));
+				self.pending.push_back(input);
+				self.view += 1;
+			}
+		}
+		self.show();
+		self.send_command()?;
+		Ok(true)
+	}
+
+	fn simulation(&self) -> Option<&Simulation> {
+		self.shown.as_ref()
+	}
+
+	fn confirmed(&self) -> Option<&Simulation> {
+		self.confirmed.as_ref()
+	}
+
+	fn local_side(&self) -> Option<Side> {
+		self.admission.map(|admission| admission.side)
+	}
+
+	fn config(&self) -> Option<Config> {
+		self.admission.map(|admission| admission.config)
+	}
+
+	fn latency(&self) -> Latency {
+		self.latency
+	}
+
+	fn waiting(&self) -> String {
+		match self.admission {
+			Some(_) => "Waiting for an opponent...".to_string(),
+			None => "Connecting to the server...".to_string(),
+		}
+	}
+
+	/// Whether the server has stopped confirming our inputs for so long that there are
+	/// more than one packet holds.
+	fn is_stalled(&self) -> bool {
+		self.pending.len() >= MAX_INPUTS
+	}
+
+	/// Never: the server's state is the match, and any difference is corrected.
+	fn is_desynced(&self) -> bool {
+		false
+	}
+
+	fn corrections(&self) -> u64 {
+		self.corrections
+	}
+
+	fn leave(&mut self) {
+		self.socket.send_now(&Message::Leave, self.server);
+	}
+}
+
+fn tick_input(side: Side, local: PaddleInput, remote: PaddleInput) -> TickInput {
+	let mut input = TickInput::default();
+	input.set(side, local);
+	input.set(side.opponent(), remote);
+	input
+}
+
+/// Where an entity moving from `from` to `to` is a fraction `t` of the way.
+fn interpolate(from: Motion, to: Motion, t: f32) -> Vec2<f32> {
+	from.position + (to.position - from.position) * t
+}
@@ -0,0 +1,94 @@
+//! Finding games on the local network.
+
+use std::env;
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use super::protocol::{Announcement, Message};
+use super::{LinkConditions, NetError, Socket};
+
+/// Ports games listen on to be found. A lobby takes the first one free, so several can
+/// be hosted on one machine.
+pub const DISCOVERY_PORTS: RangeInclusive<u16> = super::DEFAULT_PORT..=super::DEFAULT_PORT + 7;
+
+/// How often the network is asked again, to notice new games and players.
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a game stays listed after it last answered.
+const GAME_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A game that answered, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundGame {
+	pub address: SocketAddr,
+	pub announcement: Announcement,
+	last_seen: Instant,
+}
+
+/// Keeps a list of the games on the local network. Call `poll` every frame.
+pub struct Browser {
+	socket: Socket,
+	/// Where queries go: the broadcast address, at each of the `DISCOVERY_PORTS`.
+	targets: Vec<SocketAddr>,
+	games: Vec<FoundGame>,
+	last_query: Option<Instant>,
+}
+
+impl Browser {
+	pub fn new() -> Result<Browser, NetError> {
+		let targets = DISCOVERY_PORTS.map(|port| SocketAddr::from((Ipv4Addr::BROADCAST, port))).collect();
+		Browser::with_targets(targets)
+	}
+
+	/// Asks only `targets`, e.g. known hosts on a network where broadcasts don't get through.
+	pub fn with_targets(targets: Vec<SocketAddr>) -> Result<Browser, NetError> {
+		let socket = Socket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), LinkConditions::default())?;
+		socket.socket.set_broadcast(true)?;
+		Ok(Browser { socket, targets, games: Vec::new(), last_query: None })
+	}
+
+	/// The games that answered lately, in the order they were first found.
+	pub fn games(&self) -> &[FoundGame] {
+		&self.games
+	}
+
+	/// Collects the answers received since the last call, and asks again now and then.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			if let Ok(Message::Announce(announcement)) = message {
+				match self.games.iter_mut().find(|game| game.address == from) {
+					Some(game) => {
+						game.announcement = announcement;
+						game.last_seen = now;
+					}
+					None => self.games.push(FoundGame { address: from, announcement, last_seen: now }),
+				}
+			}
+		}
+		self.games.retain(|game| now.duration_since(game.last_seen) <= GAME_TIMEOUT);
+
+		if self.last_query.is_none_or(|query| now.duration_since(query) >= QUERY_INTERVAL) {
+			let query = Message::Discover.encode();
+			for target in &self.targets {
+				// Networks without broadcasts just find nothing.
+				let _ = self.socket.socket.send_to(&query, target);
+			}
+			self.last_query = Some(now);
+		}
+		Ok(())
+	}
+}
+
+/// A name to show other players when none was given: the machine's, or the user's.
+pub fn default_name() -> String {
+	["HOSTNAME", "COMPUTERNAME"]
+		.iter()
+		.filter_map(|name| env::var(name).ok())
+		.chain(fs::read_to_string("/etc/hostname").ok())
+		.chain(["USER", "USERNAME"].iter().filter_map(|name| env::var(name).ok()))
+		.map(|name| name.trim().to_string())
+		.find(|name| !name.is_empty())
+		.unwrap_or_else(|| "Player".to_string())
+}
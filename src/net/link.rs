@@ -0,0 +1,60 @@
+//! A stand-in for a bad network, to try netcode against lost and late packets without one.
+
+use std::time::{Duration, Instant};
+
+use crate::rng::Rng;
+
+/// How outgoing packets are mistreated before they are really sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkConditions {
+	/// Fraction of packets dropped, in `0.0..=1.0`.
+	pub loss: f32,
+	/// Time every packet is held back.
+	pub latency: Duration,
+	/// Largest random change to `latency`, either way. Packets held for different times
+	/// can arrive out of order, as they may on a real network.
+	pub jitter: Duration,
+}
+
+impl LinkConditions {
+	/// Whether packets go out untouched.
+	pub fn is_perfect(&self) -> bool {
+		self.loss <= 0.0 && self.latency == Duration::from_secs(0) && self.jitter == Duration::from_secs(0)
+	}
+}
+
+/// Applies `LinkConditions` to packets: each one sent is dropped, or comes back out of
+/// `due` once its delay is over.
+#[derive(Debug, Clone)]
+pub struct Link<T> {
+	conditions: LinkConditions,
+	rng: Rng,
+	/// Packets waiting out their delay, with when they are due.
+	queue: Vec<(Instant, T)>,
+}
+
+impl<T> Link<T> {
+	pub fn new(conditions: LinkConditions, seed: u64) -> Link<T> {
+		Link { conditions, rng: Rng::new(seed), queue: Vec::new() }
+	}
+
+	pub fn conditions(&self) -> LinkConditions {
+		self.conditions
+	}
+
+	pub fn send(&mut self, now: Instant, packet: T) {
+		if self.rng.next_f32() < self.conditions.loss {
+			return;
+		}
+		let jitter = self.conditions.jitter.as_secs_f32() * self.rng.range(-1.0, 1.0);
+		let delay = (self.conditions.latency.as_secs_f32() + jitter).max(0.0);
+		self.queue.push((now + Duration::from_secs_f32(delay), packet));
+	}
+
+	/// Takes the packets whose delay is over by `now`, in the order they become due.
+	pub fn due(&mut self, now: Instant) -> Vec<T> {
+		self.queue.sort_by_key(|&(due, _)| due);
+		let count = self.queue.iter().take_while(|&&(due, _)| due <= now).count();
+		self.queue.drain(..count).map(|(_, packet)| packet).collect()
+	}
+}
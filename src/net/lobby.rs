@@ -0,0 +1,353 @@
+//! Where two players meet before an online match: they pick sides, say they are ready,
+//! and the match starts on both machines at once.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::sim::Side;
+
+use super::discovery::DISCOVERY_PORTS;
+use super::protocol::{Announcement, DecodeError, GameKind, MatchSettings, Message};
+use super::{
+	Connection, Latency, LinkConditions, NetError, Role, Socket, JOIN_INTERVAL, JOIN_TIMEOUT, MAX_INPUT_DELAY,
+	PEER_TIMEOUT, PING_INTERVAL, RESEND_INTERVAL,
+};
+
+/// Time between both players being ready and the match starting.
+pub const COUNTDOWN: Duration = Duration::from_secs(3);
+
+/// A player in a lobby, as far as this end knows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyPlayer {
+	pub name: String,
+	pub side: Side,
+	pub ready: bool,
+}
+
+/// One end of a lobby. The host decides the match and who plays where; the player who
+/// joins asks for changes. Call `poll` every frame, and once `should_start` says so,
+/// carry on with `into_connection`.
+pub struct Lobby {
+	socket: Socket,
+	role: Role,
+	name: String,
+	/// Known to the host from the start, and to a joining player once the host answers.
+	settings: Option<MatchSettings>,
+	peer: Option<SocketAddr>,
+	peer_name: String,
+	/// Whether the host, then the joining player, is ready.
+	ready: [bool; 2],
+	/// Swaps the joining player asked for: those made, or those granted by the host.
+	swaps: u8,
+	/// When the match starts, once both players are ready.
+	start_at: Option<Instant>,
+	latency: Latency,
+	/// Zero point of the times in pings.
+	started: Instant,
+	last_heard: Instant,
+	last_sent: Option<Instant>,
+	last_ping: Option<Instant>,
+}
+
+impl Lobby {
+	/// Opens a lobby for a match played with `settings`, on the first free port of
+	/// `DISCOVERY_PORTS` so other players can find it.
+	pub fn host(name: &str, settings: MatchSettings, conditions: LinkConditions) -> Result<Lobby, NetError> {
+		let mut error = None;
+		for port in DISCOVERY_PORTS {
+			match Socket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)), conditions) {
+				Ok(socket) => return Ok(Lobby::new(socket, Role::Host, name, Some(settings))),
+				Err(err) => error = Some(err),
+			}
+		}
+		Err(error.map_or(NetError::NoAnswer, NetError::Io))
+	}
+
+	/// Asks to join the lobby at `host`.
+	pub fn join(host: SocketAddr, name: &str, conditions: LinkConditions) -> Result<Lobby, NetError> {
+		let socket = Socket::bind_for(host, conditions)?;
+		Ok(Lobby::new(socket, Role::Join(host), name, None))
+	}
+
+	fn new(socket: Socket, role: Role, name: &str, settings: Option<MatchSettings>) -> Lobby {
+		let now = Instant::now();
+		Lobby {
+			socket,
+			role,
+			name: name.to_string(),
+			settings,
+			peer: None,
+			peer_name: String::new(),
+			ready: [false; 2],
+			swaps: 0,
+			start_at: None,
+			latency: Latency::default(),
+			started: now,
+			last_heard: now,
+			last_sent: None,
+			last_ping: None,
+		}
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	pub fn is_host(&self) -> bool {
+		matches!(self.role, Role::Host)
+	}
+
+	pub fn settings(&self) -> Option<&MatchSettings> {
+		self.settings.as_ref()
+	}
+
+	pub fn latency(&self) -> Latency {
+		self.latency
+	}
+
+	/// This end's player, once the side is known.
+	pub fn local(&self) -> Option<LobbyPlayer> {
+		let side = self.local_side()?;
+		Some(LobbyPlayer { name: self.name.clone(), side, ready: self.ready[self.index()] })
+	}
+
+	/// The other player, once there is one.
+	pub fn peer(&self) -> Option<LobbyPlayer> {
+		let side = self.local_side()?.opponent();
+		self.peer.map(|_| LobbyPlayer { name: self.peer_name.clone(), side, ready: self.ready[1 - self.index()] })
+	}
+
+	pub fn local_side(&self) -> Option<Side> {
+		let host_side = self.settings.as_ref()?.host_side;
+		Some(if self.is_host() { host_side } else { host_side.opponent() })
+	}
+
+	/// Time left before the match starts, once both players are ready.
+	pub fn countdown(&self) -> Option<Duration> {
+		self.start_at.map(|start| start.saturating_duration_since(Instant::now()))
+	}
+
+	/// Whether the countdown is over, and the match should start.
+	pub fn should_start(&self) -> bool {
+		self.countdown() == Some(Duration::from_secs(0))
+	}
+
+	pub fn set_ready(&mut self, ready: bool) -> Result<(), NetError> {
+		let index = self.index();
+		self.ready[index] = ready;
+		self.update_countdown(Instant::now());
+		self.changed()
+	}
+
+	/// Swaps the players' sides, or asks the host to. Neither is ready any more, so nobody
+	/// starts a match on a side they didn't see coming.
+	pub fn swap_sides(&mut self) -> Result<(), NetError> {
+		if self.is_host() {
+			if let Some(settings) = &mut self.settings {
+				settings.host_side = settings.host_side.opponent();
+			}
+			self.ready = [false; 2];
+			self.update_countdown(Instant::now());
+		} else {
+			self.swaps = self.swaps.wrapping_add(1);
+			self.ready[1] = false;
+		}
+		self.changed()
+	}
+
+	/// Tells the other player we are leaving.
+	pub fn leave(&mut self) {
+		if let Some(peer) = self.peer {
+			self.socket.send_now(&Message::Leave, peer);
+		}
+	}
+
+	/// The match the lobby was for, started.
+	pub fn into_connection(self) -> Connection {
+		let side = self.local_side();
+		let mut connection = Connection::new(self.socket, self.role, &self.name, self.settings);
+		connection.peer = self.peer;
+		connection.peer_name = self.peer_name;
+		connection.latency = self.latency;
+		if let (Some(settings), Some(side)) = (connection.settings.clone(), side) {
+			connection.start(&settings, side);
+		}
+		connection
+	}
+
+	/// Handles every packet received since the last call, and keeps the lobby up to date.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			self.handle(from, message, now)?;
+		}
+
+		let waiting = self.settings.is_none();
+		if waiting && now.duration_since(self.started) > JOIN_TIMEOUT {
+			return Err(NetError::NoAnswer);
+		}
+		if let Some(peer) = self.peer {
+			if now.duration_since(self.last_heard) > PEER_TIMEOUT {
+				if !self.is_host() {
+					return Err(NetError::TimedOut);
+				}
+				self.peer_left();
+			} else if self.last_ping.is_none_or(|ping| now.duration_since(ping) >= PING_INTERVAL) {
+				let time = now.duration_since(self.started).as_micros() as u64;
+				self.socket.send(&Message::Ping { time }, peer)?;
+				self.last_ping = Some(now);
+			}
+		}
+
+		let counting = self.start_at.is_some();
+		self.update_countdown(now);
+		let interval = if self.start_at.is_some() { RESEND_INTERVAL } else { JOIN_INTERVAL };
+		let due = self.last_sent.is_none_or(|sent| now.duration_since(sent) >= interval);
+		if due || counting != self.start_at.is_some() {
+			self.changed()?;
+		}
+
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	fn handle(&mut self, from: SocketAddr, message: Result<Message, DecodeError>, now: Instant) -> Result<(), NetError> {
+		let message = match self.socket.accept(from, message, self.is_host())? {
+			Some(message) => message,
+			None => return Ok(()),
+		};
+
+		if let (Role::Host, Message::Discover) = (&self.role, &message) {
+			let announcement = self.announcement();
+			return Ok(self.socket.send(&Message::Announce(announcement), from)?);
+		}
+		let expected = match self.role {
+			Role::Join(host) => Some(host),
+			Role::Host => self.peer,
+		};
+		match (expected, &message) {
+			(Some(expected), _) if expected == from => {}
+			(None, Message::Seat { swaps, .. }) => {
+				self.peer = Some(from);
+				self.ready[1] = false;
+				self.swaps = *swaps;
+			}
+			(Some(_), Message::Seat { .. }) => {
+				return Ok(self.socket.send(&Message::Refuse { reason: "the game is full".to_string() }, from)?);
+			}
+			_ => return Ok(()),
+		}
+		self.last_heard = now;
+
+		match message {
+			Message::Seat { name, ready, swaps } if self.is_host() => {
+				self.peer_name = name;
+				if swaps.wrapping_sub(self.swaps) as i8 > 0 {
+					self.swaps = swaps;
+					self.swap_sides()?;
+				} else {
+					self.ready[1] = ready;
+					self.update_countdown(now);
+					self.changed()?;
+				}
+			}
+			Message::Room { settings, names, ready } if !self.is_host() => {
+				if settings.input_delay > MAX_INPUT_DELAY {
+					return Err(NetError::BadSettings(format!(
+						"input delay of {} ticks, the most is {}", settings.input_delay, MAX_INPUT_DELAY,
+					)));
+				}
+				let [host, _] = names;
+				let swapped = self.settings.as_ref().is_some_and(|old| old.host_side != settings.host_side);
+				self.peer = Some(from);
+				self.peer_name = host;
+				self.settings = Some(settings);
+				// Our own readiness is ours to say, unless the host swapped sides under us.
+				self.ready = [ready[0], self.ready[1] && !swapped];
+				if ready != [true; 2] {
+					self.start_at = None;
+				}
+			}
+			Message::Start { after } if !self.is_host() && self.start_at.is_none() && self.ready[1] => {
+				// It was sent half a round trip ago.
+				let travelled = self.latency.ping().map_or(Duration::from_secs(0), |ping| ping / 2);
+				self.start_at = Some(now + Duration::from_millis(after.into()).saturating_sub(travelled));
+			}
+			// The host started without us hearing when; catch up straight away.
+			Message::Inputs { .. } if !self.is_host() => self.start_at = Some(now),
+			Message::Ping { time } => self.socket.send(&Message::Pong { time }, from)?,
+			Message::Pong { time } => self.latency.pong(self.started, time, now),
+			Message::Refuse { reason } => return Err(NetError::Refused(reason)),
+			Message::Leave if self.is_host() => self.peer_left(),
+			Message::Leave => return Err(NetError::PeerLeft),
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Starts the countdown on the host once both players are ready, and stops it if
+	/// either no longer is.
+	fn update_countdown(&mut self, now: Instant) {
+		if !self.is_host() {
+			return;
+		}
+		if self.peer.is_none() || self.ready != [true; 2] {
+			self.start_at = None;
+		} else if self.start_at.is_none() {
+			self.start_at = Some(now + COUNTDOWN);
+		}
+	}
+
+	/// Index into `ready` of this end's player.
+	fn index(&self) -> usize {
+		if self.is_host() { 0 } else { 1 }
+	}
+
+	fn announcement(&self) -> Announcement {
+		Announcement {
+			name: self.name.clone(),
+			mode: self.settings.as_ref().map(MatchSettings::mode).unwrap_or_default(),
+			kind: GameKind::Lobby,
+			players: 1 + self.peer.is_some() as u8,
+			capacity: 2,
+		}
+	}
+
+	/// The host waits for someone else to join.
+	fn peer_left(&mut self) {
+		self.peer = None;
+		self.peer_name.clear();
+		self.ready = [false; 2];
+		self.start_at = None;
+	}
+
+	/// Tells the other end how things stand: the whole room from the host, or what the
+	/// joining player wants.
+	fn changed(&mut self) -> Result<(), NetError> {
+		let message = match (&self.role, &self.settings) {
+			(Role::Host, Some(settings)) => match self.start_at {
+				Some(start) => {
+					let after = start.saturating_duration_since(Instant::now()).as_millis() as u32;
+					Message::Start { after }
+				}
+				None => Message::Room {
+					settings: settings.clone(),
+					names: [self.name.clone(), self.peer_name.clone()],
+					ready: self.ready,
+				},
+			},
+			(Role::Join(_), _) => Message::Seat { name: self.name.clone(), ready: self.ready[1], swaps: self.swaps },
+			(Role::Host, None) => return Ok(()),
+		};
+		let to = match self.role {
+			Role::Join(host) => host,
+			Role::Host => match self.peer {
+				Some(peer) => peer,
+				None => return Ok(()),
+			},
+		};
+		self.last_sent = Some(Instant::now());
+		Ok(self.socket.send(&message, to)?)
+	}
+}
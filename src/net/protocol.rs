@@ -0,0 +1,622 @@
+//! The packets peers exchange, and their little-endian binary encoding.
+//!
+//! Every packet starts with `"MF"` and the protocol version, so stray datagrams and peers
+//! running an incompatible build are told apart from garbled packets.
+
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::config::Config;
+use crate::sim::{Format, MatchRules, MultiBall, PaddleInput, RallyEnd, ServeRule, Side, Spawn};
+
+const MAGIC: &[u8; 2] = b"MF";
+pub const PROTOCOL_VERSION: u16 = 6;
+
+/// Most inputs one packet carries. Unacknowledged inputs beyond this wait for the next packet.
+pub const MAX_INPUTS: usize = 64;
+/// Longest player name or game description sent, in bytes; longer ones are cut short.
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// What the host decides about a match and sends to the peer joining it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchSettings {
+	pub config: Config,
+	pub rules: MatchRules,
+	pub seed: u64,
+	/// Ticks between a key press and the tick it affects, on both machines.
+	pub input_delay: u32,
+	pub host_side: Side,
+}
+
+impl MatchSettings {
+	/// What kind of match this is, for people looking for one to join.
+	pub fn mode(&self) -> String {
+		mode(&self.rules)
+	}
+}
+
+/// What kind of match is played by `rules`, as a game announces it.
+pub fn mode(rules: &MatchRules) -> String {
+	format!("1v1, first to {}", rules.win_score)
+}
+
+/// How a game found on the network is joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameKind {
+	/// A player's `Lobby`, joined peer to peer.
+	Lobby,
+	/// A dedicated `Server`.
+	Server,
+}
+
+/// A game's answer to `Discover`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+	/// The host player's or server's name.
+	pub name: String,
+	/// As `MatchSettings::mode`.
+	pub mode: String,
+	pub kind: GameKind,
+	pub players: u8,
+	pub capacity: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+	/// Asks a host to join its match; repeated until the host answers.
+	Join { name: String },
+	/// The host's answer to `Join`.
+	Welcome { settings: MatchSettings, name: String },
+	Inputs {
+		/// Number of the receiver's inputs the sender holds, counting from the first tick
+		/// with no gaps, so the receiver knows which ones it no longer needs to resend.
+		ack: u64,
+		/// Tick the first of `inputs` is for, counting from 1.
+		first: u64,
+		inputs: Vec<PaddleInput>,
+		/// The sender's latest state hash with both players' inputs confirmed, as
+		/// (tick, `Simulation::state_hash`), so the receiver can check they still agree.
+		checksum: Option<(u64, u64)>,
+	},
+	/// Asks for a `Pong` carrying `time` back, to measure the round trip.
+	Ping { time: u64 },
+	Pong { time: u64 },
+	/// The sender has left the match.
+	Leave,
+	/// Asks a dedicated server for a place in its next match; repeated until it answers.
+	Enter { name: String },
+	/// The server's answer to `Enter`: the match the client will play, and on which side.
+	Admit {
+		config: Config,
+		rules: MatchRules,
+		seed: u64,
+		side: Side,
+	},
+	/// A client's inputs for the server, which it holds until their ticks come.
+	Command {
+		/// Tick of the latest snapshot the client has, which the server may encode the
+		/// next ones against.
+		ack: u64,
+		/// Tick the first of `inputs` is for.
+		first: u64,
+		inputs: Vec<PaddleInput>,
+	},
+	/// The server's state of the match.
+	State {
+		/// Tick of the snapshot `delta` is encoded against, or 0 for none.
+		baseline: u64,
+		/// How many ticks ahead of the server the receiver's inputs arrive. Clients aim to
+		/// keep this small but positive, so their inputs are neither late nor held long.
+		margin: i32,
+		/// `Snapshot::encode_delta` of the state.
+		delta: Vec<u8>,
+	},
+	/// The server turned the receiver away, or removed it from the match.
+	Refuse { reason: String },
+	/// Broadcast to find games on the local network.
+	Discover,
+	Announce(Announcement),
+	/// What a player joining a lobby wants, repeated until the match starts. The first one
+	/// asks to join.
+	Seat {
+		name: String,
+		ready: bool,
+		/// Number of times the player asked to swap sides, so a request that is repeated,
+		/// or arrives after a later one, is only granted once.
+		swaps: u8,
+	},
+	/// The host's view of its lobby, sent whenever it changes and now and then anyway.
+	Room {
+		/// The match to be played, `host_side` included.
+		settings: MatchSettings,
+		/// The host's name, then the joining player's.
+		names: [String; 2],
+		/// Whether the host, then the joining player, is ready.
+		ready: [bool; 2],
+	},
+	/// Both players are ready: the match starts this many milliseconds after it was sent.
+	Start { after: u32 },
+	/// Asks to watch a match, and keeps asking for as long as the spectator stays. The
+	/// first one asks to join.
+	Watch {
+		/// `epoch` of the latest `Spectate`, or 0 before any.
+		epoch: u32,
+		/// Tick of the latest snapshot of that match the spectator has, which the next
+		/// ones may be encoded against.
+		ack: u64,
+	},
+	/// Tells a spectator which match it is shown; sent again whenever the match changes.
+	Spectate {
+		/// Counts the matches shown, so snapshots of the last one are told apart.
+		epoch: u32,
+		config: Config,
+		rules: MatchRules,
+		seed: u64,
+		/// Indexed by `Side`, and empty for sides nobody plays on.
+		names: [String; 4],
+		/// How far behind the match the spectator is shown it, in milliseconds.
+		delay: u32,
+	},
+	/// The state of the match a spectator watches, as it was `delay` ago.
+	View {
+		epoch: u32,
+		/// Tick of the snapshot `delta` is encoded against, or 0 for none.
+		baseline: u64,
+		/// `Snapshot::encode_delta` of the state.
+		delta: Vec<u8>,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+	/// Not one of our packets at all.
+	Foreign,
+	/// Sent by a build speaking another version of the protocol.
+	Version(u16),
+	Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DecodeError::Foreign => write!(f, "not an mfight-ng packet"),
+			DecodeError::Version(version) => {
+				write!(f, "peer speaks protocol version {}, but this build only speaks {}", version, PROTOCOL_VERSION)
+			}
+			DecodeError::Malformed(reason) => write!(f, "malformed packet: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for DecodeError {}
+
+const JOIN: u8 = 0;
+const WELCOME: u8 = 1;
+const INPUTS: u8 = 2;
+const PING: u8 = 3;
+const PONG: u8 = 4;
+const LEAVE: u8 = 5;
+const ENTER: u8 = 6;
+const ADMIT: u8 = 7;
+const COMMAND: u8 = 8;
+const STATE: u8 = 9;
+const REFUSE: u8 = 10;
+const DISCOVER: u8 = 11;
+const ANNOUNCE: u8 = 12;
+const SEAT: u8 = 13;
+const ROOM: u8 = 14;
+const START: u8 = 15;
+const WATCH: u8 = 16;
+const SPECTATE: u8 = 17;
+const VIEW: u8 = 18;
+
+impl Message {
+	pub fn encode(&self) -> Vec<u8> {
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+		match self {
+			Message::Join { name } => {
+				bytes.push(JOIN);
+				write_string(&mut bytes, name);
+			}
+			Message::Welcome { settings, name } => {
+				bytes.push(WELCOME);
+				write_string(&mut bytes, name);
+				write_settings(&mut bytes, settings);
+			}
+			Message::Inputs { ack, first, inputs, checksum } => {
+				bytes.push(INPUTS);
+				bytes.extend_from_slice(&ack.to_le_bytes());
+				bytes.extend_from_slice(&first.to_le_bytes());
+				write_inputs(&mut bytes, inputs);
+				match checksum {
+					Some((tick, hash)) => {
+						bytes.push(1);
+						bytes.extend_from_slice(&tick.to_le_bytes());
+						bytes.extend_from_slice(&hash.to_le_bytes());
+					}
+					None => bytes.push(0),
+				}
+			}
+			Message::Ping { time } => {
+				bytes.push(PING);
+				bytes.extend_from_slice(&time.to_le_bytes());
+			}
+			Message::Pong { time } => {
+				bytes.push(PONG);
+				bytes.extend_from_slice(&time.to_le_bytes());
+			}
+			Message::Leave => bytes.push(LEAVE),
+			Message::Enter { name } => {
+				bytes.push(ENTER);
+				write_string(&mut bytes, name);
+			}
+			Message::Admit { config, rules, seed, side } => {
+				bytes.push(ADMIT);
+				bytes.extend_from_slice(&seed.to_le_bytes());
+				write_rules(&mut bytes, rules);
+				bytes.push(*side as u8);
+				write_config(&mut bytes, config);
+			}
+			Message::Command { ack, first, inputs } => {
+				bytes.push(COMMAND);
+				bytes.extend_from_slice(&ack.to_le_bytes());
+				bytes.extend_from_slice(&first.to_le_bytes());
+				write_inputs(&mut bytes, inputs);
+			}
+			Message::State { baseline, margin, delta } => {
+				bytes.push(STATE);
+				bytes.extend_from_slice(&baseline.to_le_bytes());
+				bytes.extend_from_slice(&margin.to_le_bytes());
+				bytes.extend_from_slice(delta);
+			}
+			Message::Refuse { reason } => {
+				bytes.push(REFUSE);
+				bytes.extend_from_slice(reason.as_bytes());
+			}
+			Message::Discover => bytes.push(DISCOVER),
+			Message::Announce(announcement) => {
+				bytes.push(ANNOUNCE);
+				write_string(&mut bytes, &announcement.name);
+				write_string(&mut bytes, &announcement.mode);
+				bytes.push(match announcement.kind {
+					GameKind::Lobby => 0,
+					GameKind::Server => 1,
+				});
+				bytes.push(announcement.players);
+				bytes.push(announcement.capacity);
+			}
+			Message::Seat { name, ready, swaps } => {
+				bytes.push(SEAT);
+				write_string(&mut bytes, name);
+				bytes.push(*ready as u8);
+				bytes.push(*swaps);
+			}
+			Message::Room { settings, names, ready } => {
+				bytes.push(ROOM);
+				write_settings(&mut bytes, settings);
+				for (name, ready) in names.iter().zip(ready) {
+					write_string(&mut bytes, name);
+					bytes.push(*ready as u8);
+				}
+			}
+			Message::Start { after } => {
+				bytes.push(START);
+				bytes.extend_from_slice(&after.to_le_bytes());
+			}
+			Message::Watch { epoch, ack } => {
+				bytes.push(WATCH);
+				bytes.extend_from_slice(&epoch.to_le_bytes());
+				bytes.extend_from_slice(&ack.to_le_bytes());
+			}
+			Message::Spectate { epoch, config, rules, seed, names, delay } => {
+				bytes.push(SPECTATE);
+				bytes.extend_from_slice(&epoch.to_le_bytes());
+				bytes.extend_from_slice(&seed.to_le_bytes());
+				bytes.extend_from_slice(&delay.to_le_bytes());
+				write_rules(&mut bytes, rules);
+				for name in names {
+					write_string(&mut bytes, name);
+				}
+				write_config(&mut bytes, config);
+			}
+			Message::View { epoch, baseline, delta } => {
+				bytes.push(VIEW);
+				bytes.extend_from_slice(&epoch.to_le_bytes());
+				bytes.extend_from_slice(&baseline.to_le_bytes());
+				bytes.extend_from_slice(delta);
+			}
+		}
+		bytes
+	}
+
+	pub fn decode(bytes: &[u8]) -> Result<Message, DecodeError> {
+		let mut reader = Reader(bytes);
+		if reader.take(2).ok() != Some(&MAGIC[..]) {
+			return Err(DecodeError::Foreign);
+		}
+		let version = reader.u16().map_err(|_| DecodeError::Foreign)?;
+		if version != PROTOCOL_VERSION {
+			return Err(DecodeError::Version(version));
+		}
+
+		let message = match reader.u8()? {
+			JOIN => Message::Join { name: reader.string()? },
+			WELCOME => {
+				let name = reader.string()?;
+				Message::Welcome { settings: reader.settings()?, name }
+			}
+			INPUTS => {
+				let ack = reader.u64()?;
+				let first = reader.u64()?;
+				let inputs = reader.inputs()?;
+				let checksum = match reader.u8()? {
+					0 => None,
+					_ => Some((reader.u64()?, reader.u64()?)),
+				};
+				Message::Inputs { ack, first, inputs, checksum }
+			}
+			PING => Message::Ping { time: reader.u64()? },
+			PONG => Message::Pong { time: reader.u64()? },
+			LEAVE => Message::Leave,
+			ENTER => Message::Enter { name: reader.string()? },
+			ADMIT => {
+				let seed = reader.u64()?;
+				let rules = reader.duel_rules()?;
+				let side = side(reader.u8()?)?;
+				let config = reader.config()?;
+				Message::Admit { config, rules, seed, side }
+			}
+			COMMAND => {
+				let ack = reader.u64()?;
+				let first = reader.u64()?;
+				Message::Command { ack, first, inputs: reader.inputs()? }
+			}
+			STATE => {
+				let baseline = reader.u64()?;
+				let margin = i32::from_le_bytes(reader.array()?);
+				Message::State { baseline, margin, delta: reader.rest().to_vec() }
+			}
+			REFUSE => {
+				let reason = String::from_utf8_lossy(reader.rest()).into_owned();
+				Message::Refuse { reason }
+			}
+			DISCOVER => Message::Discover,
+			ANNOUNCE => {
+				let name = reader.string()?;
+				let mode = reader.string()?;
+				let kind = match reader.u8()? {
+					0 => GameKind::Lobby,
+					1 => GameKind::Server,
+					other => return Err(malformed(format!("unknown game kind {}", other))),
+				};
+				let players = reader.u8()?;
+				let capacity = reader.u8()?;
+				Message::Announce(Announcement { name, mode, kind, players, capacity })
+			}
+			SEAT => {
+				let name = reader.string()?;
+				let ready = reader.u8()? != 0;
+				Message::Seat { name, ready, swaps: reader.u8()? }
+			}
+			ROOM => {
+				let settings = reader.settings()?;
+				let (host, host_ready) = (reader.string()?, reader.u8()? != 0);
+				let (guest, guest_ready) = (reader.string()?, reader.u8()? != 0);
+				Message::Room { settings, names: [host, guest], ready: [host_ready, guest_ready] }
+			}
+			START => Message::Start { after: reader.u32()? },
+			WATCH => {
+				let epoch = reader.u32()?;
+				Message::Watch { epoch, ack: reader.u64()? }
+			}
+			SPECTATE => {
+				let epoch = reader.u32()?;
+				let seed = reader.u64()?;
+				let delay = reader.u32()?;
+				let rules = reader.rules()?;
+				let names = [reader.string()?, reader.string()?, reader.string()?, reader.string()?];
+				let config = reader.config()?;
+				Message::Spectate { epoch, config, rules, seed, names, delay }
+			}
+			VIEW => {
+				let epoch = reader.u32()?;
+				let baseline = reader.u64()?;
+				Message::View { epoch, baseline, delta: reader.rest().to_vec() }
+			}
+			other => return Err(malformed(format!("unknown message type {}", other))),
+		};
+
+		if !reader.0.is_empty() {
+			return Err(malformed("trailing bytes"));
+		}
+		Ok(message)
+	}
+}
+
+fn write_settings(bytes: &mut Vec<u8>, settings: &MatchSettings) {
+	bytes.extend_from_slice(&settings.seed.to_le_bytes());
+	write_rules(bytes, &settings.rules);
+	bytes.extend_from_slice(&settings.input_delay.to_le_bytes());
+	bytes.push(settings.host_side as u8);
+	write_config(bytes, &settings.config);
+}
+
+fn write_rules(bytes: &mut Vec<u8>, rules: &MatchRules) {
+	bytes.extend_from_slice(&rules.win_score.to_le_bytes());
+	bytes.extend_from_slice(&rules.win_by.to_le_bytes());
+	bytes.push(match rules.serve {
+		ServeRule::ToConceder => 0,
+		ServeRule::Alternate => 1,
+	});
+	bytes.push(match rules.format {
+		Format::Duel => 0,
+		Format::FourWay => 1,
+		Format::Doubles => 2,
+	});
+	bytes.extend_from_slice(&rules.lives.to_le_bytes());
+	let (kind, every, max_balls, rally_end) = match rules.multiball {
+		None => (0, 0, 0, RallyEnd::FirstOut),
+		Some(MultiBall { spawn: Spawn::Timer(ticks), max_balls, rally_end }) => (1, ticks, max_balls, rally_end),
+		Some(MultiBall { spawn: Spawn::Hits(hits), max_balls, rally_end }) => (2, hits, max_balls, rally_end),
+	};
+	bytes.push(kind);
+	bytes.extend_from_slice(&every.to_le_bytes());
+	bytes.extend_from_slice(&max_balls.to_le_bytes());
+	bytes.push(match rally_end {
+		RallyEnd::FirstOut => 0,
+		RallyEnd::LastOut => 1,
+	});
+}
+
+fn write_config(bytes: &mut Vec<u8>, config: &Config) {
+	// A config always serializes.
+	let config = toml::to_string(config).unwrap_or_default();
+	bytes.extend_from_slice(&(config.len() as u32).to_le_bytes());
+	bytes.extend_from_slice(config.as_bytes());
+}
+
+fn write_inputs(bytes: &mut Vec<u8>, inputs: &[PaddleInput]) {
+	let inputs = &inputs[..inputs.len().min(MAX_INPUTS)];
+	bytes.push(inputs.len() as u8);
+	for input in inputs {
+		bytes.extend_from_slice(&input.movement.to_le_bytes());
+	}
+}
+
+/// Writes at most `MAX_NAME_LENGTH` bytes of `text`, cut at a character boundary.
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+	let mut end = text.len().min(MAX_NAME_LENGTH);
+	while !text.is_char_boundary(end) {
+		end -= 1;
+	}
+	bytes.push(end as u8);
+	bytes.extend_from_slice(&text.as_bytes()[..end]);
+}
+
+fn malformed<S: Into<String>>(reason: S) -> DecodeError {
+	DecodeError::Malformed(reason.into())
+}
+
+fn side(value: u8) -> Result<Side, DecodeError> {
+	match value {
+		0 => Ok(Side::Left),
+		1 => Ok(Side::Right),
+		other => Err(malformed(format!("unknown side {}", other))),
+	}
+}
+
+/// Reads values off the front of a packet.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+		if self.0.len() < length {
+			return Err(malformed("packet is truncated"));
+		}
+		let (taken, rest) = self.0.split_at(length);
+		self.0 = rest;
+		Ok(taken)
+	}
+
+	fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+		// `take` returned exactly N bytes.
+		Ok(self.take(N)?.try_into().unwrap())
+	}
+
+	fn u8(&mut self) -> Result<u8, DecodeError> {
+		Ok(self.array::<1>()?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, DecodeError> {
+		self.array().map(u16::from_le_bytes)
+	}
+
+	fn u32(&mut self) -> Result<u32, DecodeError> {
+		self.array().map(u32::from_le_bytes)
+	}
+
+	fn u64(&mut self) -> Result<u64, DecodeError> {
+		self.array().map(u64::from_le_bytes)
+	}
+
+	fn f32(&mut self) -> Result<f32, DecodeError> {
+		self.array().map(f32::from_le_bytes)
+	}
+
+	/// Everything left of the packet.
+	fn rest(&mut self) -> &'a [u8] {
+		std::mem::take(&mut self.0)
+	}
+
+	fn string(&mut self) -> Result<String, DecodeError> {
+		let length = self.u8()? as usize;
+		let text = std::str::from_utf8(self.take(length)?).map_err(|_| malformed("text is not UTF-8"))?;
+		Ok(text.to_string())
+	}
+
+	fn settings(&mut self) -> Result<MatchSettings, DecodeError> {
+		let seed = self.u64()?;
+		let rules = self.duel_rules()?;
+		let input_delay = self.u32()?;
+		let host_side = side(self.u8()?)?;
+		let config = self.config()?;
+		Ok(MatchSettings { config, rules, seed, input_delay, host_side })
+	}
+
+	fn rules(&mut self) -> Result<MatchRules, DecodeError> {
+		let win_score = self.u32()?;
+		let win_by = self.u32()?;
+		let serve = match self.u8()? {
+			0 => ServeRule::ToConceder,
+			1 => ServeRule::Alternate,
+			other => return Err(malformed(format!("unknown serve rule {}", other))),
+		};
+		let format = match self.u8()? {
+			0 => Format::Duel,
+			1 => Format::FourWay,
+			2 => Format::Doubles,
+			other => return Err(malformed(format!("unknown format {}", other))),
+		};
+		let lives = self.u32()?;
+		let kind = self.u8()?;
+		let every = self.u32()?;
+		let max_balls = self.u32()?;
+		let rally_end = match self.u8()? {
+			0 => RallyEnd::FirstOut,
+			1 => RallyEnd::LastOut,
+			other => return Err(malformed(format!("unknown rally end {}", other))),
+		};
+		let spawn = match kind {
+			0 => None,
+			1 => Some(Spawn::Timer(every)),
+			2 => Some(Spawn::Hits(every)),
+			other => return Err(malformed(format!("unknown multi-ball spawn {}", other))),
+		};
+		let multiball = spawn.map(|spawn| MultiBall { spawn, max_balls, rally_end });
+		let rules = MatchRules { win_score, win_by, serve, format, lives, multiball };
+		rules.validate().map_err(malformed)?;
+		Ok(rules)
+	}
+
+	/// The rules of a match to be played online, which is always a single-ball duel. Any
+	/// other match can only be watched.
+	fn duel_rules(&mut self) -> Result<MatchRules, DecodeError> {
+		let rules = self.rules()?;
+		if rules.format != Format::Duel || rules.multiball.is_some() {
+			return Err(malformed("online matches are single-ball duels"));
+		}
+		Ok(rules)
+	}
+
+	fn config(&mut self) -> Result<Config, DecodeError> {
+		let length = self.u32()? as usize;
+		let config = std::str::from_utf8(self.take(length)?).map_err(|_| malformed("config is not UTF-8"))?;
+		Config::from_toml(config).map_err(|err| malformed(format!("invalid config: {}", err)))
+	}
+
+	fn inputs(&mut self) -> Result<Vec<PaddleInput>, DecodeError> {
+		let count = self.u8()?;
+		(0..count).map(|_| self.f32().map(PaddleInput::new)).collect()
+	}
+}
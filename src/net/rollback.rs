@@ -0,0 +1,267 @@
+//! Keeping a simulation going while the other player's inputs are still on their way.
+
+use std::collections::VecDeque;
+
+use crate::sim::{PaddleInput, Side, Simulation, TickInput};
+
+/// Furthest the simulation may run ahead of the last tick the remote player's input is
+/// known for. Past this the game waits rather than guess ever further.
+pub const MAX_PREDICTION: u64 = 12;
+
+/// Ticks between the state hashes peers compare to catch a desync.
+pub const CHECKSUM_INTERVAL: u64 = 60;
+
+/// How much further ahead of the remote player's inputs than they are of ours a peer may
+/// run before it waits for them to catch up, in ticks.
+const SYNC_TOLERANCE: u64 = 2;
+/// Fewest ticks between two waits, so catching up is spread out instead of a visible stop.
+const SYNC_INTERVAL: u64 = 8;
+
+/// Runs a match on one peer. Local inputs are applied `input_delay` ticks after they are
+/// made, which gives them that long to reach the other peer. Until the remote player's
+/// input for a tick arrives, it is predicted to be the same as their last known one, and
+/// when the prediction turns out wrong the ticks since are simulated again with the real
+/// input.
+#[derive(Debug, Clone)]
+pub struct Rollback {
+	local_side: Side,
+	/// Every local input of the match, the one at index `i` for tick `i + 1`.
+	local: Vec<PaddleInput>,
+	/// Every remote input received so far, without gaps, indexed like `local`.
+	remote: Vec<PaddleInput>,
+	/// The remote inputs `current` was simulated with for the ticks past the end of
+	/// `remote`, oldest first.
+	predictions: VecDeque<PaddleInput>,
+	/// The latest state both players' inputs are known for; it never needs rolling back.
+	confirmed: Simulation,
+	/// The state shown to the player, ahead of `confirmed` on predicted inputs.
+	current: Simulation,
+	/// Hashes of `confirmed` every `CHECKSUM_INTERVAL` ticks as (tick, hash), waiting to be
+	/// compared with the remote peer's.
+	checksums: VecDeque<(u64, u64)>,
+	/// The remote peer's hashes, waiting for ours to catch up.
+	remote_checksums: VecDeque<(u64, u64)>,
+	/// Our most recent hash, to send to the remote peer.
+	latest_checksum: Option<(u64, u64)>,
+	desynced: bool,
+	rollbacks: u64,
+	/// How far the remote peer last said it ran ahead of our inputs. The peer that started
+	/// first, or hears from the other sooner, would otherwise stay ahead and do all the
+	/// predicting and rolling back.
+	remote_lead: u64,
+	/// Ticks simulated since the last wait to let the remote peer catch up.
+	since_wait: u64,
+}
+
+impl Rollback {
+	/// Starts a match from `simulation`, which both peers must have created identically.
+	pub fn new(simulation: Simulation, local_side: Side, input_delay: u32) -> Rollback {
+		// Nobody has pressed anything before the first delayed input takes effect.
+		let delayed = vec![PaddleInput::default(); input_delay as usize];
+		Rollback {
+			local_side,
+			local: delayed.clone(),
+			remote: delayed,
+			predictions: VecDeque::new(),
+			confirmed: simulation.clone(),
+			current: simulation,
+			checksums: VecDeque::new(),
+			remote_checksums: VecDeque::new(),
+			latest_checksum: None,
+			desynced: false,
+			rollbacks: 0,
+			remote_lead: 0,
+			since_wait: 0,
+		}
+	}
+
+	pub fn local_side(&self) -> Side {
+		self.local_side
+	}
+
+	/// The state to show, possibly predicted.
+	pub fn simulation(&self) -> &Simulation {
+		&self.current
+	}
+
+	pub fn confirmed(&self) -> &Simulation {
+		&self.confirmed
+	}
+
+	/// The inputs both players made for `confirmed`'s tick.
+	pub fn confirmed_inputs(&self) -> TickInput {
+		match (self.confirmed.tick as usize).checked_sub(1) {
+			Some(tick) => self.tick_input(self.local[tick], self.remote[tick]),
+			None => TickInput::default(),
+		}
+	}
+
+	/// Whether the remote peer's state hash differed from ours for the same tick.
+	pub fn is_desynced(&self) -> bool {
+		self.desynced
+	}
+
+	/// Times a misprediction made the simulation go back and replay ticks.
+	pub fn rollbacks(&self) -> u64 {
+		self.rollbacks
+	}
+
+	/// Whether the simulation is as far ahead of the remote player as it may get, and has
+	/// to wait for their inputs.
+	pub fn is_stalled(&self) -> bool {
+		self.current.tick >= self.remote.len() as u64 + MAX_PREDICTION
+	}
+
+	/// Simulates the next tick with `input` queued as the local player's input, unless the
+	/// simulation is stalled or waiting a tick for the remote peer to catch up. Returns
+	/// whether it advanced.
+	pub fn advance(&mut self, input: PaddleInput) -> bool {
+		if self.is_stalled() || self.current.result.is_some() {
+			return false;
+		}
+		if self.since_wait >= SYNC_INTERVAL && self.lead() > self.remote_lead + SYNC_TOLERANCE {
+			self.since_wait = 0;
+			return false;
+		}
+		self.since_wait += 1;
+		self.local.push(input);
+		let tick = self.current.tick as usize;
+		let remote = match self.remote.get(tick) {
+			Some(&remote) => remote,
+			None => {
+				let predicted = self.predict();
+				self.predictions.push_back(predicted);
+				predicted
+			}
+		};
+		self.current.step(&self.tick_input(self.local[tick], remote));
+		self.confirm();
+		true
+	}
+
+	/// The local inputs the remote peer doesn't have yet, given that it holds the first
+	/// `acked` of them, as (tick of the first, inputs).
+	pub fn unacked(&self, acked: u64) -> (u64, &[PaddleInput]) {
+		let acked = (acked as usize).min(self.local.len());
+		(acked as u64 + 1, &self.local[acked..])
+	}
+
+	/// Takes how far the remote peer runs ahead: it had made `made` inputs, and received
+	/// `received` of ours.
+	pub fn sync(&mut self, made: u64, received: u64) {
+		self.remote_lead = made.saturating_sub(received);
+	}
+
+	/// How far we run ahead of the remote player's inputs.
+	fn lead(&self) -> u64 {
+		(self.local.len() as u64).saturating_sub(self.remote.len() as u64)
+	}
+
+	/// Number of the remote player's inputs known, without gaps.
+	pub fn received(&self) -> u64 {
+		self.remote.len() as u64
+	}
+
+	/// The latest hash of the confirmed state, as (tick, hash), to send to the remote peer.
+	pub fn checksum(&self) -> Option<(u64, u64)> {
+		self.latest_checksum
+	}
+
+	/// Takes the remote peer's hash of its confirmed state at `tick`, to compare with ours
+	/// once we have one for the same tick.
+	pub fn check(&mut self, tick: u64, hash: u64) {
+		if self.remote_checksums.back().is_none_or(|&(last, _)| tick > last) {
+			self.remote_checksums.push_back((tick, hash));
+			self.compare_checksums();
+		}
+	}
+
+	fn compare_checksums(&mut self) {
+		while let (Some(&(ours, our_hash)), Some(&(theirs, their_hash))) =
+			(self.checksums.front(), self.remote_checksums.front())
+		{
+			if ours <= theirs {
+				self.checksums.pop_front();
+			}
+			if theirs <= ours {
+				self.remote_checksums.pop_front();
+			}
+			if ours == theirs && our_hash != their_hash {
+				self.desynced = true;
+			}
+		}
+	}
+
+	/// Takes in remote inputs, the first for tick `first`. Ones already known are skipped,
+	/// and ones after a gap dropped: the remote peer resends everything not acknowledged.
+	pub fn receive(&mut self, first: u64, inputs: &[PaddleInput]) {
+		let known = self.remote.len() as u64;
+		if first == 0 || first > known + 1 {
+			return;
+		}
+		let new = &inputs[((known + 1 - first) as usize).min(inputs.len())..];
+		if new.is_empty() {
+			return;
+		}
+
+		let mut mispredicted = false;
+		for &input in new {
+			if let Some(predicted) = self.predictions.pop_front() {
+				mispredicted |= predicted.movement.to_bits() != input.movement.to_bits();
+			}
+			self.remote.push(input);
+		}
+		self.confirm();
+		if mispredicted {
+			self.roll_back();
+		}
+	}
+
+	/// Moves `confirmed` up to the latest tick with both inputs known that `current` has
+	/// reached.
+	fn confirm(&mut self) {
+		let end = self.current.tick.min(self.remote.len() as u64);
+		while self.confirmed.tick < end && self.confirmed.result.is_none() {
+			let tick = self.confirmed.tick as usize;
+			self.confirmed.step(&self.tick_input(self.local[tick], self.remote[tick]));
+			if self.confirmed.tick.is_multiple_of(CHECKSUM_INTERVAL) {
+				let checksum = (self.confirmed.tick, self.confirmed.state_hash());
+				self.checksums.push_back(checksum);
+				self.latest_checksum = Some(checksum);
+			}
+		}
+		self.compare_checksums();
+	}
+
+	/// Replays the ticks since `confirmed` with the inputs now known, predicting the rest again.
+	fn roll_back(&mut self) {
+		self.rollbacks += 1;
+		let target = self.current.tick;
+		self.current.clone_from(&self.confirmed);
+		self.predictions.clear();
+		let predicted = self.predict();
+		while self.current.tick < target && self.current.result.is_none() {
+			let tick = self.current.tick as usize;
+			let remote = match self.remote.get(tick) {
+				Some(&remote) => remote,
+				None => {
+					self.predictions.push_back(predicted);
+					predicted
+				}
+			};
+			self.current.step(&self.tick_input(self.local[tick], remote));
+		}
+	}
+
+	/// The remote player is guessed to keep doing what they last did.
+	fn predict(&self) -> PaddleInput {
+		self.remote.last().copied().unwrap_or_default()
+	}
+
+	fn tick_input(&self, local: PaddleInput, remote: PaddleInput) -> TickInput {
+		let mut input = TickInput::default();
+		input.set(self.local_side, local);
+		input.set(self.local_side.opponent(), remote);
+		input
+	}
+}
@@ -0,0 +1,379 @@
+//! The dedicated server: the one simulation of a match that counts.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::sim::{MatchResult, MatchRules, PaddleInput, Side, Simulation, TickInput};
+
+use super::protocol::{mode, Announcement, DecodeError, GameKind, Message};
+use super::snapshot::Snapshot;
+use super::spectate::{Audience, DEFAULT_SPECTATOR_DELAY};
+use super::{LinkConditions, NetError, Socket, PEER_TIMEOUT};
+
+/// Ticks between the snapshots sent to clients.
+pub const SNAPSHOT_INTERVAL: u64 = 2;
+/// How far ahead of the server a client's inputs may be, in ticks. Inputs further ahead
+/// are dropped, so a client can't make the server hold on to any number of them.
+pub const MAX_INPUT_AHEAD: u64 = 30;
+/// Invalid inputs a client may send before it is removed from the match. A few are
+/// forgiven, in case of a bug rather than a cheat.
+pub const MAX_VIOLATIONS: u32 = 10;
+
+/// Snapshots kept for clients to acknowledge, so later ones can be encoded against them.
+const HISTORY: usize = 64;
+/// How long a finished match stays up, resending its last snapshot so both clients see
+/// the result, before the server waits for the next players.
+const LINGER: Duration = Duration::from_secs(2);
+
+/// Something that happened on the server, for it to report.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+	Joined(Side, SocketAddr),
+	Left(Side),
+	/// The client on this side was removed from the match, for this reason.
+	Kicked(Side, String),
+	/// Both sides are taken, and a match with this seed began.
+	Started(u64),
+	Finished(MatchResult),
+	/// The match ended early, as a player left.
+	Abandoned,
+}
+
+/// A player on the server.
+struct Client {
+	address: SocketAddr,
+	name: String,
+	/// Inputs received for ticks still to come.
+	inputs: BTreeMap<u64, PaddleInput>,
+	/// The input last used, repeated for ticks whose input came late or never.
+	last_input: PaddleInput,
+	/// Latest tick the client has an input for.
+	newest: u64,
+	/// Tick of the latest snapshot the client has.
+	acked: u64,
+	violations: u32,
+	last_heard: Instant,
+}
+
+/// Hosts matches between clients that only send it inputs, one match at a time. Call
+/// `poll` often and `step` once per tick.
+pub struct Server {
+	socket: Socket,
+	/// Shown to players looking for games on the local network.
+	name: String,
+	config: Config,
+	rules: MatchRules,
+	/// Seed of the next match; each match gets a new one.
+	seed: u64,
+	/// Indexed by `Side`.
+	clients: [Option<Client>; 2],
+	simulation: Simulation,
+	/// The inputs that led to the current tick.
+	inputs: TickInput,
+	running: bool,
+	history: VecDeque<Snapshot>,
+	finished: Option<Instant>,
+	events: Vec<ServerEvent>,
+	audience: Audience,
+}
+
+impl Server {
+	/// Listens on `port` for clients to play matches of `config` and `rules`, starting
+	/// from `seed`.
+	pub fn bind(
+		port: u16,
+		config: Config,
+		rules: MatchRules,
+		seed: u64,
+		conditions: LinkConditions,
+	) -> Result<Server, NetError> {
+		let socket = Socket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)), conditions)?;
+		Ok(Server {
+			socket,
+			name: "mfight-server".to_string(),
+			config,
+			rules,
+			seed,
+			clients: [None, None],
+			simulation: Simulation::with_config(config, rules, seed),
+			inputs: TickInput::default(),
+			running: false,
+			history: VecDeque::new(),
+			finished: None,
+			events: Vec::new(),
+			audience: Audience::new(DEFAULT_SPECTATOR_DELAY),
+		})
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	pub fn set_name(&mut self, name: &str) {
+		self.name = name.to_string();
+	}
+
+	/// Changes how far behind the players spectators are shown matches.
+	pub fn set_spectator_delay(&mut self, delay: Duration) {
+		self.audience.set_delay(delay);
+	}
+
+	pub fn spectators(&self) -> usize {
+		self.audience.spectators()
+	}
+
+	/// The current match, or the next one while waiting for players.
+	pub fn simulation(&self) -> &Simulation {
+		&self.simulation
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.running
+	}
+
+	/// Everything that happened since the last call.
+	pub fn take_events(&mut self) -> Vec<ServerEvent> {
+		std::mem::take(&mut self.events)
+	}
+
+	/// Handles every packet received since the last call, and drops clients that went silent.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			self.handle(from, message, now)?;
+		}
+
+		for side in [Side::Left, Side::Right] {
+			let silent = self.clients[side as usize]
+				.as_ref()
+				.is_some_and(|client| now.duration_since(client.last_heard) > PEER_TIMEOUT);
+			if silent {
+				self.remove(side, ServerEvent::Left(side))?;
+			}
+		}
+
+		self.audience.update(&mut self.socket, now)?;
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	/// Plays the next tick of the running match with the inputs the clients sent for it,
+	/// and sends them snapshots.
+	pub fn step(&mut self) -> Result<(), NetError> {
+		if !self.running {
+			return Ok(());
+		}
+
+		if self.simulation.result.is_none() {
+			let tick = self.simulation.tick + 1;
+			for side in [Side::Left, Side::Right] {
+				if let Some(client) = &mut self.clients[side as usize] {
+					// A late or lost input is taken to be the same as the last one.
+					if let Some(input) = client.inputs.remove(&tick) {
+						client.last_input = input;
+					}
+					client.inputs = client.inputs.split_off(&tick);
+					self.inputs.set(side, client.last_input);
+				}
+			}
+			self.simulation.step(&self.inputs);
+			self.audience.record(&self.simulation, self.inputs);
+
+			if let Some(result) = self.simulation.result {
+				self.events.push(ServerEvent::Finished(result));
+				self.finished = Some(Instant::now());
+			}
+		}
+
+		let finished = self.simulation.result.is_some();
+		if finished || self.simulation.tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+			self.send_snapshots()?;
+		}
+		if self.finished.is_some_and(|finished| finished.elapsed() >= LINGER) {
+			self.reset();
+		}
+		Ok(())
+	}
+
+	fn handle(&mut self, from: SocketAddr, message: Result<Message, DecodeError>, now: Instant) -> Result<(), NetError> {
+		let side = [Side::Left, Side::Right]
+			.iter()
+			.copied()
+			.find(|&side| self.clients[side as usize].as_ref().is_some_and(|client| client.address == from));
+		let message = match self.socket.accept(from, message, true)? {
+			Some(message) => message,
+			None => return Ok(()),
+		};
+
+		let side = match side {
+			Some(side) => side,
+			None => {
+				if self.audience.handle(&mut self.socket, from, &message, now)? {
+					return Ok(());
+				}
+				match message {
+					Message::Enter { name } => self.admit(from, name, now)?,
+					Message::Ping { time } => self.socket.send(&Message::Pong { time }, from)?,
+					Message::Discover => {
+						let announcement = Announcement {
+							name: self.name.clone(),
+							mode: mode(&self.rules),
+							kind: GameKind::Server,
+							players: self.clients.iter().flatten().count() as u8,
+							capacity: 2,
+						};
+						self.socket.send(&Message::Announce(announcement), from)?;
+					}
+					// Strangers get nothing else from us.
+					_ => {}
+				}
+				return Ok(());
+			}
+		};
+		if let Some(client) = &mut self.clients[side as usize] {
+			client.last_heard = now;
+		}
+
+		match message {
+			// Our answer was lost; send it again.
+			Message::Enter { .. } => self.send_admit(side, from)?,
+			Message::Command { ack, first, inputs } => {
+				self.receive(side, ack, first, &inputs);
+				let violations = self.clients[side as usize].as_ref().map_or(0, |client| client.violations);
+				if violations >= MAX_VIOLATIONS {
+					let reason = "sent invalid inputs".to_string();
+					self.socket.send(&Message::Refuse { reason: reason.clone() }, from)?;
+					self.remove(side, ServerEvent::Kicked(side, reason))?;
+				}
+			}
+			Message::Ping { time } => self.socket.send(&Message::Pong { time }, from)?,
+			Message::Leave => self.remove(side, ServerEvent::Left(side))?,
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn admit(&mut self, from: SocketAddr, name: String, now: Instant) -> Result<(), NetError> {
+		let side = match [Side::Left, Side::Right].iter().copied().find(|&side| self.clients[side as usize].is_none()) {
+			Some(side) if !self.running => side,
+			_ => {
+				self.socket.send(&Message::Refuse { reason: "the server is full".to_string() }, from)?;
+				return Ok(());
+			}
+		};
+
+		self.clients[side as usize] = Some(Client {
+			address: from,
+			name,
+			inputs: BTreeMap::new(),
+			last_input: PaddleInput::default(),
+			newest: 0,
+			acked: 0,
+			violations: 0,
+			last_heard: now,
+		});
+		self.events.push(ServerEvent::Joined(side, from));
+		self.send_admit(side, from)?;
+
+		if self.clients.iter().all(Option::is_some) {
+			self.simulation = Simulation::with_config(self.config, self.rules, self.seed);
+			self.inputs = TickInput::default();
+			self.running = true;
+			self.events.push(ServerEvent::Started(self.seed));
+
+			let names = Side::ALL.map(|side| {
+				let client = self.clients.get(side as usize).and_then(Option::as_ref);
+				client.map_or_else(String::new, |client| client.name.clone())
+			});
+			self.audience.begin(self.config, self.rules, self.seed, names);
+			self.audience.record(&self.simulation, self.inputs);
+		}
+		Ok(())
+	}
+
+	fn send_admit(&mut self, side: Side, to: SocketAddr) -> Result<(), NetError> {
+		let admit = Message::Admit { config: self.config, rules: self.rules, seed: self.seed, side };
+		Ok(self.socket.send(&admit, to)?)
+	}
+
+	/// Takes the inputs a client sent, holding the valid ones for their ticks.
+	fn receive(&mut self, side: Side, ack: u64, first: u64, inputs: &[PaddleInput]) {
+		let tick = self.simulation.tick;
+		let client = match &mut self.clients[side as usize] {
+			Some(client) => client,
+			None => return,
+		};
+		client.acked = client.acked.max(ack);
+		// Tick 0 is the start, which needs no input, and nothing further ahead is ever held.
+		if first == 0 || first > tick + MAX_INPUT_AHEAD {
+			client.violations += 1;
+			return;
+		}
+		for (input_tick, input) in (first..).zip(inputs) {
+			if !input.movement.is_finite() || input.movement.abs() > 1.0 {
+				client.violations += 1;
+				continue;
+			}
+			if input_tick > tick + MAX_INPUT_AHEAD {
+				continue;
+			}
+			// Late inputs still count towards the margin, so the client knows to hurry.
+			client.newest = client.newest.max(input_tick);
+			if input_tick > tick {
+				client.inputs.insert(input_tick, *input);
+			}
+		}
+	}
+
+	/// Sends each client the current state, encoded against the latest one it has.
+	fn send_snapshots(&mut self) -> Result<(), NetError> {
+		let snapshot = Snapshot::capture(&self.simulation, self.inputs);
+		for client in self.clients.iter().flatten() {
+			let baseline = self.history.iter().find(|baseline| baseline.tick == client.acked);
+			let message = Message::State {
+				baseline: baseline.map_or(0, |baseline| baseline.tick),
+				margin: (client.newest as i64 - snapshot.tick as i64) as i32,
+				delta: snapshot.encode_delta(baseline),
+			};
+			self.socket.send(&message, client.address)?;
+		}
+
+		if self.history.back().is_none_or(|latest| latest.tick != snapshot.tick) {
+			self.history.push_back(snapshot);
+			if self.history.len() > HISTORY {
+				self.history.pop_front();
+			}
+		}
+		Ok(())
+	}
+
+	/// Takes the client on `side` out. A match it was playing is over, and the other player
+	/// is told so.
+	fn remove(&mut self, side: Side, event: ServerEvent) -> Result<(), NetError> {
+		self.clients[side as usize] = None;
+		self.events.push(event);
+		if self.running {
+			if self.simulation.result.is_none() {
+				if let Some(other) = &self.clients[side.opponent() as usize] {
+					self.socket.send(&Message::Leave, other.address)?;
+				}
+				self.events.push(ServerEvent::Abandoned);
+			}
+			self.reset();
+		}
+		Ok(())
+	}
+
+	/// Waits for the players of the next match.
+	fn reset(&mut self) {
+		self.clients = [None, None];
+		self.running = false;
+		self.finished = None;
+		self.history.clear();
+		self.seed = self.seed.wrapping_add(1);
+		self.simulation = Simulation::with_config(self.config, self.rules, self.seed);
+	}
+}
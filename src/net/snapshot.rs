@@ -0,0 +1,287 @@
+//! The state of a match as a server sends it, and the delta encoding that keeps it small.
+
+use std::convert::TryInto;
+
+use vek::Vec2;
+
+use crate::config::Config;
+use crate::rng::Rng;
+use crate::sim::{
+	Ball, Entity, MatchResult, MatchRules, PaddleId, PaddleInput, Score, Side, Simulation, TickInput, MAX_BALLS,
+};
+
+use super::protocol::DecodeError;
+
+/// Most paddles a match has: one a side in a four-way match, or two a side in doubles.
+const MAX_PADDLES: usize = 4;
+/// Number of 32-bit words a snapshot is made of. Paddles and balls a match doesn't have
+/// still take up their words, so each word always holds the same thing.
+const WORDS: usize = 2 + 1 + 4 * MAX_PADDLES + 1 + BALL_WORDS * MAX_BALLS as usize + 4 + 1 + 1 + 1 + 4 + 1 + 2 + 4;
+/// Number of 32-bit words each ball takes up.
+const BALL_WORDS: usize = 6;
+/// Number of 32-bit words in the mask of which words a delta holds.
+const MASK_WORDS: usize = WORDS.div_ceil(32);
+
+/// Where an entity is and where it is going. Sizes never change during a match, so they
+/// come from the config instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Motion {
+	pub position: Vec2<f32>,
+	pub velocity: Vec2<f32>,
+}
+
+impl Motion {
+	fn of(entity: &Entity) -> Motion {
+		Motion { position: entity.position, velocity: entity.velocity }
+	}
+
+	fn apply(self, entity: &mut Entity) {
+		entity.position = self.position;
+		entity.velocity = self.velocity;
+	}
+
+	fn words(self) -> [u32; 4] {
+		[self.position.x, self.position.y, self.velocity.x, self.velocity.y].map(f32::to_bits)
+	}
+
+	fn from_words(words: [u32; 4]) -> Motion {
+		let [x, y, dx, dy] = words.map(f32::from_bits);
+		Motion { position: Vec2::new(x, y), velocity: Vec2::new(dx, dy) }
+	}
+}
+
+/// A ball in play, as in `sim::Ball` but without its size.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BallState {
+	pub motion: Motion,
+	pub contact: Option<PaddleId>,
+	pub last_hit: Option<Side>,
+}
+
+impl BallState {
+	fn of(ball: &Ball) -> BallState {
+		BallState { motion: Motion::of(&ball.entity), contact: ball.contact, last_hit: ball.last_hit }
+	}
+}
+
+/// Everything about a match that changes as it is played, plus the inputs of the tick that
+/// led to it. The rest (config, rules, seed) is sent once when a client is admitted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+	pub tick: u64,
+	/// Every side's paddles, indexed by `Side` and nearest the goal line first, as in
+	/// `Simulation::paddles`.
+	pub paddles: [Vec<Motion>; 4],
+	/// The balls in play, oldest first, as in `Simulation::balls`.
+	pub balls: Vec<BallState>,
+	pub score: Score,
+	pub result: Option<MatchResult>,
+	pub serve_toward: Side,
+	pub serve_delay: u32,
+	/// Indexed by `Side`.
+	pub conceded: [u32; 4],
+	pub spawn_progress: u32,
+	/// `Rng::state` of the simulation's generator.
+	pub rng: u64,
+	/// What each side did during `tick`, for clients to predict they keep doing it.
+	pub inputs: TickInput,
+}
+
+impl Snapshot {
+	pub fn capture(simulation: &Simulation, inputs: TickInput) -> Snapshot {
+		Snapshot {
+			tick: simulation.tick,
+			paddles: Side::ALL.map(|side| simulation.paddles[side as usize].iter().map(Motion::of).collect()),
+			balls: simulation.balls.iter().map(BallState::of).collect(),
+			score: simulation.score,
+			result: simulation.result,
+			serve_toward: simulation.serve_toward,
+			serve_delay: simulation.serve_delay,
+			conceded: simulation.conceded,
+			spawn_progress: simulation.spawn_progress,
+			rng: simulation.rng.state(),
+			inputs,
+		}
+	}
+
+	/// The simulation this was captured from, given what was sent on admission. Paddles
+	/// the rules don't give the match are left out.
+	pub fn restore(&self, config: Config, rules: MatchRules, seed: u64) -> Simulation {
+		let mut simulation = Simulation::with_config(config, rules, seed);
+		simulation.tick = self.tick;
+		for (paddles, motions) in simulation.paddles.iter_mut().zip(&self.paddles) {
+			for (paddle, motion) in paddles.iter_mut().zip(motions) {
+				motion.apply(paddle);
+			}
+		}
+		// Every ball is the same size, so the one served at the start stands in for the rest.
+		let served = simulation.balls[0].entity.clone();
+		let paddles = &simulation.paddles;
+		simulation.balls = self.balls.iter().map(|state| {
+			let mut ball = Ball::new(served.clone());
+			state.motion.apply(&mut ball.entity);
+			ball.contact = state.contact.filter(|paddle| paddle.index < paddles[paddle.side as usize].len());
+			ball.last_hit = state.last_hit;
+			ball
+		}).collect();
+		simulation.score = self.score;
+		simulation.result = self.result;
+		simulation.serve_toward = self.serve_toward;
+		simulation.serve_delay = self.serve_delay;
+		simulation.conceded = self.conceded;
+		simulation.spawn_progress = self.spawn_progress;
+		simulation.rng = Rng::new(self.rng);
+		simulation
+	}
+
+	/// Encodes the words that differ from `baseline`, a snapshot the receiver already has,
+	/// or every word without one: a mask of which words follow, then those words.
+	pub fn encode_delta(&self, baseline: Option<&Snapshot>) -> Vec<u8> {
+		let words = self.words();
+		let base = baseline.map(Snapshot::words);
+		let mut mask = [0u32; MASK_WORDS];
+		let mut bytes = Vec::new();
+		for (i, word) in words.iter().enumerate() {
+			if base.as_ref().is_none_or(|base| base[i] != *word) {
+				mask[i / 32] |= 1 << (i % 32);
+				bytes.extend_from_slice(&word.to_le_bytes());
+			}
+		}
+		let mut encoded: Vec<u8> = mask.iter().flat_map(|word| word.to_le_bytes()).collect();
+		encoded.extend(bytes);
+		encoded
+	}
+
+	/// Reverses `encode_delta`, given the same `baseline`.
+	pub fn decode_delta(bytes: &[u8], baseline: Option<&Snapshot>) -> Result<Snapshot, DecodeError> {
+		let truncated = || DecodeError::Malformed("snapshot is truncated".to_string());
+		let mut words = match baseline {
+			Some(baseline) => baseline.words(),
+			None => [0; WORDS],
+		};
+		let mut rest = bytes;
+		let mut next = || -> Result<u32, DecodeError> {
+			let (value, tail) = (rest.get(..4).ok_or_else(truncated)?, &rest[4..]);
+			rest = tail;
+			Ok(u32::from_le_bytes(value.try_into().unwrap()))
+		};
+		let mut mask = [0u32; MASK_WORDS];
+		for word in &mut mask {
+			*word = next()?;
+		}
+		let present = |i: usize| mask[i / 32] & (1 << (i % 32)) != 0;
+		if (WORDS..MASK_WORDS * 32).any(present) {
+			return Err(DecodeError::Malformed("snapshot has too many words".to_string()));
+		}
+		if baseline.is_none() && !(0..WORDS).all(present) {
+			return Err(DecodeError::Malformed("snapshot needs a baseline".to_string()));
+		}
+
+		for (i, word) in words.iter_mut().enumerate() {
+			if present(i) {
+				*word = next()?;
+			}
+		}
+		if !rest.is_empty() {
+			return Err(DecodeError::Malformed("trailing bytes after snapshot".to_string()));
+		}
+		Snapshot::from_words(&words)
+	}
+
+	fn words(&self) -> [u32; WORDS] {
+		let mut words = Vec::with_capacity(WORDS);
+		words.push(self.tick as u32);
+		words.push((self.tick >> 32) as u32);
+		// A byte for each side's number of paddles.
+		words.push(self.paddles.iter().rev().fold(0, |counts, paddles| counts << 8 | paddles.len() as u32));
+		let mut paddles = self.paddles.iter().flatten();
+		for _ in 0..MAX_PADDLES {
+			words.extend(paddles.next().copied().unwrap_or_default().words());
+		}
+		words.push(self.balls.len() as u32);
+		for i in 0..MAX_BALLS as usize {
+			let ball = self.balls.get(i).copied().unwrap_or_default();
+			words.extend(ball.motion.words());
+			words.push(ball.contact.map_or(0, |paddle| (paddle.index as u32) << 8 | (paddle.side as u32 + 1)));
+			words.push(ball.last_hit.map_or(0, |side| side as u32 + 1));
+		}
+		words.extend(Side::ALL.iter().map(|&side| self.score.get(side)));
+		words.push(self.result.map_or(0, |result| result.winner as u32 + 1));
+		words.push(self.serve_toward as u32);
+		words.push(self.serve_delay);
+		words.extend(&self.conceded);
+		words.push(self.spawn_progress);
+		words.push(self.rng as u32);
+		words.push((self.rng >> 32) as u32);
+		words.extend(Side::ALL.iter().map(|&side| self.inputs.get(side).movement.to_bits()));
+		// `WORDS` counts exactly what is pushed above.
+		words.try_into().unwrap()
+	}
+
+	fn from_words(words: &[u32; WORDS]) -> Result<Snapshot, DecodeError> {
+		let side = |value: u32| match value {
+			0 => Ok(Side::Left),
+			1 => Ok(Side::Right),
+			2 => Ok(Side::Top),
+			3 => Ok(Side::Bottom),
+			_ => Err(DecodeError::Malformed(format!("unknown side {}", value))),
+		};
+		let optional_side = |value: u32| match value {
+			0 => Ok(None),
+			value => side(value - 1).map(Some),
+		};
+		// Every word is there, so `next` never runs out.
+		let mut words = words.iter().copied();
+		let mut next = || words.next().unwrap_or(0);
+
+		let tick = u64::from(next()) | u64::from(next()) << 32;
+		let counts = next();
+		let counts = Side::ALL.map(|side| (counts >> (8 * side as u32) & 0xff) as usize);
+		if counts.iter().sum::<usize>() > MAX_PADDLES {
+			return Err(DecodeError::Malformed("snapshot has too many paddles".to_string()));
+		}
+		let mut slots: Vec<_> = (0..MAX_PADDLES).map(|_| Motion::from_words([next(), next(), next(), next()])).collect();
+		let paddles = counts.map(|count| slots.drain(..count).collect::<Vec<_>>());
+		let count = next() as usize;
+		if !(1..=MAX_BALLS as usize).contains(&count) {
+			return Err(DecodeError::Malformed(format!("snapshot with {} balls", count)));
+		}
+		let mut balls = Vec::with_capacity(count);
+		for _ in 0..MAX_BALLS {
+			let motion = Motion::from_words([next(), next(), next(), next()]);
+			let contact = next();
+			let contact = optional_side(contact & 0xff)?.map(|side| PaddleId::new(side, (contact >> 8) as usize));
+			if contact.is_some_and(|paddle| paddle.index >= paddles[paddle.side as usize].len()) {
+				return Err(DecodeError::Malformed("ball touching a missing paddle".to_string()));
+			}
+			let last_hit = optional_side(next())?;
+			balls.push(BallState { motion, contact, last_hit });
+		}
+		balls.truncate(count);
+		let score = Score { left: next(), right: next(), top: next(), bottom: next() };
+		let result = optional_side(next())?.map(|winner| MatchResult { winner, score });
+		let serve_toward = side(next())?;
+		let serve_delay = next();
+		let conceded = [next(), next(), next(), next()];
+		let spawn_progress = next();
+		let rng = u64::from(next()) | u64::from(next()) << 32;
+		let mut inputs = TickInput::default();
+		for &side in &Side::ALL {
+			inputs.set(side, PaddleInput::new(f32::from_bits(next())));
+		}
+
+		Ok(Snapshot {
+			tick,
+			paddles,
+			balls,
+			score,
+			result,
+			serve_toward,
+			serve_delay,
+			conceded,
+			spawn_progress,
+			rng,
+			inputs,
+		})
+	}
+}
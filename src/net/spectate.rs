@@ -0,0 +1,487 @@
+//! Showing a match to spectators, a little behind the players.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::sim::{MatchRules, Simulation, TickInput};
+
+use super::protocol::{DecodeError, Message};
+use super::server::SNAPSHOT_INTERVAL;
+use super::snapshot::Snapshot;
+use super::{LinkConditions, NetError, Socket, JOIN_INTERVAL, JOIN_TIMEOUT, PEER_TIMEOUT, PING_INTERVAL, RESEND_INTERVAL};
+
+/// How far behind the players spectators are shown a match unless told otherwise. Enough
+/// that a player watching their own match learns nothing they could still use.
+pub const DEFAULT_SPECTATOR_DELAY: Duration = Duration::from_secs(2);
+/// Most spectators a match takes; more are turned away.
+pub const MAX_SPECTATORS: usize = 16;
+
+/// Snapshots kept as baselines for the deltas sent to spectators, by either end.
+const KEPT_SNAPSHOTS: usize = 64;
+/// Furthest a spectator's view may fall behind the latest snapshot before it skips ahead,
+/// in ticks.
+const MAX_LAG: u64 = 4 * SNAPSHOT_INTERVAL;
+
+/// What spectators are told about the match they watch.
+#[derive(Debug, Clone, PartialEq)]
+struct Showing {
+	config: Config,
+	rules: MatchRules,
+	seed: u64,
+	/// Indexed by `Side`, and empty for sides nobody plays on.
+	names: [String; 4],
+}
+
+/// Something recorded for spectators, to be sent once the delay has passed.
+enum Frame {
+	Begin(Showing),
+	Snapshot(Snapshot),
+}
+
+struct Watcher {
+	address: SocketAddr,
+	/// The latest snapshot the spectator has, as (epoch, tick).
+	acked: (u32, u64),
+	last_heard: Instant,
+}
+
+/// The spectators of whatever match its owner records, sharing the owner's socket: the
+/// owner passes on every packet from an address that isn't one of its players, and calls
+/// `update` every frame.
+pub struct Audience {
+	delay: Duration,
+	watchers: Vec<Watcher>,
+	/// Recorded frames still being held back, with when they were recorded, oldest first.
+	queue: VecDeque<(Instant, Frame)>,
+	/// Tick of the latest snapshot recorded of the current match.
+	recorded: Option<u64>,
+	/// The match being shown, once its first frame is due.
+	showing: Option<Showing>,
+	/// Number of matches shown so far.
+	epoch: u32,
+	/// Snapshots of the match being shown, sent already, oldest first.
+	history: VecDeque<Snapshot>,
+}
+
+impl Audience {
+	pub fn new(delay: Duration) -> Audience {
+		Audience {
+			delay,
+			watchers: Vec::new(),
+			queue: VecDeque::new(),
+			recorded: None,
+			showing: None,
+			epoch: 0,
+			history: VecDeque::new(),
+		}
+	}
+
+	/// Changes how far behind the match spectators are shown it, from now on.
+	pub fn set_delay(&mut self, delay: Duration) {
+		self.delay = delay;
+	}
+
+	pub fn spectators(&self) -> usize {
+		self.watchers.len()
+	}
+
+	/// Starts recording a new match, between players with `names` (indexed by `Side`, and
+	/// empty for sides nobody plays on). Spectators go on to it once they have seen the end
+	/// of the last one.
+	pub fn begin(&mut self, config: Config, rules: MatchRules, seed: u64, names: [String; 4]) {
+		let showing = Showing { config, rules, seed, names };
+		self.queue.push_back((Instant::now(), Frame::Begin(showing)));
+		self.recorded = None;
+	}
+
+	/// Records the state of the current match, given the inputs that led to it. Call it
+	/// every tick; only every `SNAPSHOT_INTERVAL`th tick, and the last, are kept.
+	pub fn record(&mut self, simulation: &Simulation, inputs: TickInput) {
+		let due = self.recorded.is_none_or(|tick| {
+			simulation.tick >= tick + SNAPSHOT_INTERVAL || (simulation.result.is_some() && simulation.tick > tick)
+		});
+		if due {
+			self.queue.push_back((Instant::now(), Frame::Snapshot(Snapshot::capture(simulation, inputs))));
+			self.recorded = Some(simulation.tick);
+		}
+	}
+
+	/// Handles `message` if it is from a spectator, or asks to become one. Returns whether
+	/// it was, so the owner can handle the rest.
+	pub fn handle(&mut self, socket: &mut Socket, from: SocketAddr, message: &Message, now: Instant) -> io::Result<bool> {
+		let index = match self.watchers.iter().position(|watcher| watcher.address == from) {
+			Some(index) => index,
+			None if matches!(message, Message::Watch { .. }) => {
+				if self.watchers.len() >= MAX_SPECTATORS {
+					socket.send(&Message::Refuse { reason: "too many spectators".to_string() }, from)?;
+					return Ok(true);
+				}
+				self.watchers.push(Watcher { address: from, acked: (0, 0), last_heard: now });
+				self.watchers.len() - 1
+			}
+			None => return Ok(false),
+		};
+		self.watchers[index].last_heard = now;
+
+		match *message {
+			Message::Watch { epoch, ack } => {
+				let current = self.epoch;
+				let watcher = &mut self.watchers[index];
+				if epoch == current && (epoch, ack) > watcher.acked {
+					watcher.acked = (epoch, ack);
+				}
+				// New spectators, and ones whose copy was lost, are brought up to date at once.
+				// Otherwise the next snapshot will do, unless the match is over.
+				let acked = watcher.acked;
+				let latest = self.history.back();
+				let behind = latest.is_some_and(|latest| acked != (current, latest.tick));
+				if epoch != self.epoch {
+					self.inform(socket, from)?;
+				}
+				if behind && (epoch != self.epoch || latest.is_some_and(|latest| latest.result.is_some())) {
+					self.send_view(socket, index)?;
+				}
+			}
+			Message::Ping { time } => socket.send(&Message::Pong { time }, from)?,
+			Message::Leave => {
+				self.watchers.remove(index);
+			}
+			_ => {}
+		}
+		Ok(true)
+	}
+
+	/// Sends spectators what was recorded long enough ago, and drops the ones gone silent.
+	pub fn update(&mut self, socket: &mut Socket, now: Instant) -> io::Result<()> {
+		while self.queue.front().is_some_and(|(recorded, _)| now.duration_since(*recorded) >= self.delay) {
+			match self.queue.pop_front() {
+				Some((_, Frame::Begin(showing))) => {
+					self.showing = Some(showing);
+					self.epoch += 1;
+					self.history.clear();
+					for i in 0..self.watchers.len() {
+						self.inform(socket, self.watchers[i].address)?;
+					}
+				}
+				Some((_, Frame::Snapshot(snapshot))) => {
+					self.history.push_back(snapshot);
+					if self.history.len() > KEPT_SNAPSHOTS {
+						self.history.pop_front();
+					}
+					for i in 0..self.watchers.len() {
+						self.send_view(socket, i)?;
+					}
+				}
+				None => {}
+			}
+		}
+
+		self.watchers.retain(|watcher| now.duration_since(watcher.last_heard) <= PEER_TIMEOUT);
+		Ok(())
+	}
+
+	/// Tells every spectator the match is no longer shown.
+	pub fn close(&mut self, socket: &mut Socket) {
+		for watcher in self.watchers.drain(..) {
+			socket.send_now(&Message::Leave, watcher.address);
+		}
+	}
+
+	/// Tells a spectator which match it is shown.
+	fn inform(&self, socket: &mut Socket, to: SocketAddr) -> io::Result<()> {
+		let showing = match &self.showing {
+			Some(showing) => showing.clone(),
+			None => return Ok(()),
+		};
+		let message = Message::Spectate {
+			epoch: self.epoch,
+			config: showing.config,
+			rules: showing.rules,
+			seed: showing.seed,
+			names: showing.names,
+			delay: self.delay.as_millis().min(u32::MAX as u128) as u32,
+		};
+		socket.send(&message, to)
+	}
+
+	/// Sends a spectator the latest snapshot, encoded against the latest one it has.
+	fn send_view(&self, socket: &mut Socket, index: usize) -> io::Result<()> {
+		let (watcher, latest) = match (self.watchers.get(index), self.history.back()) {
+			(Some(watcher), Some(latest)) => (watcher, latest),
+			_ => return Ok(()),
+		};
+		let (epoch, ack) = watcher.acked;
+		let baseline = self.history
+			.iter()
+			.find(|snapshot| epoch == self.epoch && ack > 0 && snapshot.tick == ack);
+		let message = Message::View {
+			epoch: self.epoch,
+			baseline: baseline.map_or(0, |baseline| baseline.tick),
+			delta: latest.encode_delta(baseline),
+		};
+		socket.send(&message, watcher.address)
+	}
+}
+
+/// Shows local matches to spectators, on a port of its own. Call `begin` as each match
+/// starts, `record` every tick, and `poll` every frame.
+pub struct Broadcast {
+	socket: Socket,
+	audience: Audience,
+}
+
+impl Broadcast {
+	/// Listens on `port` for spectators, to show them matches `delay` behind the players.
+	pub fn bind(port: u16, delay: Duration, conditions: LinkConditions) -> Result<Broadcast, NetError> {
+		let socket = Socket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)), conditions)?;
+		Ok(Broadcast { socket, audience: Audience::new(delay) })
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	pub fn spectators(&self) -> usize {
+		self.audience.spectators()
+	}
+
+	/// As `Audience::begin`.
+	pub fn begin(&mut self, config: Config, rules: MatchRules, seed: u64, names: [String; 4]) {
+		self.audience.begin(config, rules, seed, names);
+	}
+
+	/// As `Audience::record`.
+	pub fn record(&mut self, simulation: &Simulation, inputs: TickInput) {
+		self.audience.record(simulation, inputs);
+	}
+
+	/// Handles every packet received since the last call, and sends what is due.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			if let Some(message) = self.socket.accept(from, message, true)? {
+				self.audience.handle(&mut self.socket, from, &message, now)?;
+			}
+		}
+		self.audience.update(&mut self.socket, now)?;
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	/// Tells every spectator there is nothing more to see.
+	pub fn close(&mut self) {
+		self.audience.close(&mut self.socket);
+	}
+}
+
+impl Drop for Broadcast {
+	fn drop(&mut self) {
+		self.close();
+	}
+}
+
+/// What the spectator was told about the match it watches.
+#[derive(Debug, Clone)]
+struct Watching {
+	epoch: u32,
+	showing: Showing,
+	delay: Duration,
+}
+
+/// Watches a match hosted elsewhere: by a `Server`, the host of a peer-to-peer match, or a
+/// `Broadcast`. The match is shown from the snapshots received, stepped on between them.
+/// Call `poll` every frame and `advance` once per tick.
+pub struct Spectator {
+	socket: Socket,
+	host: SocketAddr,
+	watching: Option<Watching>,
+	/// The latest snapshots of the match, oldest first.
+	snapshots: VecDeque<Snapshot>,
+	shown: Option<Simulation>,
+	/// Zero point of the times in pings.
+	started: Instant,
+	last_heard: Instant,
+	last_sent: Option<Instant>,
+	last_ping: Option<Instant>,
+}
+
+impl Spectator {
+	/// Asks `host` to show us its match.
+	pub fn watch(host: SocketAddr, conditions: LinkConditions) -> Result<Spectator, NetError> {
+		let socket = Socket::bind_for(host, conditions)?;
+		let now = Instant::now();
+		Ok(Spectator {
+			socket,
+			host,
+			watching: None,
+			snapshots: VecDeque::new(),
+			shown: None,
+			started: now,
+			last_heard: now,
+			last_sent: None,
+			last_ping: None,
+		})
+	}
+
+	/// The match as it is shown, once a snapshot of it arrived.
+	pub fn simulation(&self) -> Option<&Simulation> {
+		self.shown.as_ref()
+	}
+
+	/// The players' names, indexed by `Side`, once known.
+	pub fn names(&self) -> Option<&[String; 4]> {
+		self.watching.as_ref().map(|watching| &watching.showing.names)
+	}
+
+	pub fn config(&self) -> Option<Config> {
+		self.watching.as_ref().map(|watching| watching.showing.config)
+	}
+
+	/// How far behind the players the match is shown, once known.
+	pub fn delay(&self) -> Option<Duration> {
+		self.watching.as_ref().map(|watching| watching.delay)
+	}
+
+	/// Handles every packet received since the last call, and keeps the connection alive.
+	pub fn poll(&mut self) -> Result<(), NetError> {
+		let now = Instant::now();
+		while let Some((from, message)) = self.socket.receive()? {
+			if from == self.host {
+				self.handle(message, now)?;
+			}
+		}
+
+		if self.watching.is_none() {
+			if now.duration_since(self.started) > JOIN_TIMEOUT {
+				return Err(NetError::NoAnswer);
+			}
+			if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= JOIN_INTERVAL) {
+				self.send_watch()?;
+			}
+		} else {
+			if now.duration_since(self.last_heard) > PEER_TIMEOUT {
+				return Err(NetError::TimedOut);
+			}
+			// Keeps us in the audience between matches, when there is nothing to acknowledge.
+			if self.last_ping.is_none_or(|ping| now.duration_since(ping) >= PING_INTERVAL) {
+				let time = now.duration_since(self.started).as_micros() as u64;
+				self.socket.send(&Message::Ping { time }, self.host)?;
+				self.last_ping = Some(now);
+			}
+			if self.last_sent.is_none_or(|sent| now.duration_since(sent) >= RESEND_INTERVAL) {
+				self.send_watch()?;
+			}
+		}
+
+		self.socket.flush()?;
+		Ok(())
+	}
+
+	/// Shows the next tick, if a snapshot of it or a later one has arrived. Returns whether
+	/// the match moved on.
+	pub fn advance(&mut self) -> bool {
+		let (watching, shown) = match (&self.watching, &mut self.shown) {
+			(Some(watching), Some(shown)) => (watching, shown),
+			_ => return false,
+		};
+		let showing = &watching.showing;
+		let latest = match self.snapshots.back() {
+			Some(latest) => latest,
+			None => return false,
+		};
+
+		if latest.tick > shown.tick + MAX_LAG {
+			// Fell behind, e.g. after a stall: skip to the snapshot before the latest.
+			let snapshot = &self.snapshots[self.snapshots.len().saturating_sub(2)];
+			*shown = snapshot.restore(showing.config, showing.rules, showing.seed);
+			return true;
+		}
+		// The first snapshot after the tick shown, to step towards or restore.
+		let next = match self.snapshots.iter().find(|snapshot| snapshot.tick > shown.tick) {
+			Some(next) => next,
+			None => return false,
+		};
+		if next.tick == shown.tick + 1 {
+			*shown = next.restore(showing.config, showing.rules, showing.seed);
+		} else {
+			// Predicted from what the players did by then; the snapshot corrects it.
+			shown.step(&next.inputs);
+		}
+		true
+	}
+
+	/// Tells the host we stopped watching.
+	pub fn leave(&mut self) {
+		self.socket.send_now(&Message::Leave, self.host);
+	}
+
+	fn handle(&mut self, message: Result<Message, DecodeError>, now: Instant) -> Result<(), NetError> {
+		let message = match self.socket.accept(self.host, message, false)? {
+			Some(message) => message,
+			None => return Ok(()),
+		};
+		self.last_heard = now;
+
+		match message {
+			Message::Spectate { epoch, config, rules, seed, names, delay } => {
+				if self.watching.as_ref().is_none_or(|watching| watching.epoch != epoch) {
+					self.snapshots.clear();
+					self.shown = None;
+				}
+				let showing = Showing { config, rules, seed, names };
+				let delay = Duration::from_millis(delay as u64);
+				self.watching = Some(Watching { epoch, showing, delay });
+			}
+			Message::View { epoch, baseline, delta } => self.receive(epoch, baseline, &delta),
+			Message::Refuse { reason } => return Err(NetError::Refused(reason)),
+			Message::Leave => return Err(NetError::Closed),
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn receive(&mut self, epoch: u32, baseline: u64, delta: &[u8]) {
+		let watching = match &self.watching {
+			Some(watching) if watching.epoch == epoch => watching,
+			// A snapshot of a match we haven't been told about yet, or no longer watch.
+			_ => return,
+		};
+		let baseline = match baseline {
+			0 => None,
+			tick => match self.snapshots.iter().find(|snapshot| snapshot.tick == tick) {
+				Some(snapshot) => Some(snapshot),
+				// Encoded against one we no longer have; the next will do.
+				None => return,
+			},
+		};
+		let snapshot = match Snapshot::decode_delta(delta, baseline) {
+			Ok(snapshot) => snapshot,
+			Err(_) => return,
+		};
+		if self.snapshots.back().is_some_and(|latest| latest.tick >= snapshot.tick) {
+			return;
+		}
+
+		if self.shown.is_none() {
+			// Joining mid-match: start from the first snapshot rather than the beginning.
+			let showing = &watching.showing;
+			self.shown = Some(snapshot.restore(showing.config, showing.rules, showing.seed));
+		}
+		self.snapshots.push_back(snapshot);
+		if self.snapshots.len() > KEPT_SNAPSHOTS {
+			self.snapshots.pop_front();
+		}
+	}
+
+	fn send_watch(&mut self) -> Result<(), NetError> {
+		let message = Message::Watch {
+			epoch: self.watching.as_ref().map_or(0, |watching| watching.epoch),
+			ack: self.snapshots.back().map_or(0, |snapshot| snapshot.tick),
+		};
+		self.last_sent = Some(Instant::now());
+		Ok(self.socket.send(&message, self.host)?)
+	}
+}
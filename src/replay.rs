@@ -0,0 +1,410 @@
+//! Recording matches and playing them back.
+//!
+//! A replay holds only what the simulation needs to replay a match exactly: the config,
+//! the rules, the seed and the inputs of every tick. Playback feeds those inputs back
+//! through a fresh simulation, and a hash of the final state detects when that no longer
+//! gives the recorded outcome.
+//!
+//! Files are little-endian binary:
+//!
+//! ```text
+//! "MFRP"  version: u16  seed: u64
+//! win_score: u32  win_by: u32  serve: u8  format: u8  lives: u32
+//! multi-ball: u8 (0 off, 1 timer, 2 hits)  spawn every: u32  max balls: u32  rally end: u8
+//! config length: u32  config as TOML
+//! run count: u32, then per run of identical ticks: length: u32  player1: f32  player2: f32
+//!     and in a four-way or doubles match  player3: f32  player4: f32
+//! final tick: u64  final state hash: u64
+//! ```
+//!
+//! Version 1 files, from before four-way matches, have no format or lives and are duels.
+//! Version 1 and 2 files have no multi-ball settings and are played with a single ball.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::config::{Config, ConfigError};
+use crate::sim::{Format, MatchRules, MultiBall, PaddleInput, RallyEnd, ServeRule, Simulation, Spawn, TickInput, TICK_RATE};
+
+const MAGIC: &[u8; 4] = b"MFRP";
+pub const VERSION: u16 = 3;
+
+/// Longest match a replay can hold, six hours of game time. Headless matches still
+/// undecided by then are abandoned, in case two evenly matched CPUs hardly ever miss.
+pub const MAX_TICKS: u64 = 6 * 60 * 60 * TICK_RATE as u64;
+
+/// Largest config a replay can hold, far more than any real one needs. Like `MAX_TICKS`,
+/// this keeps a corrupt file from asking for more memory than there is.
+const MAX_CONFIG_LENGTH: u32 = 64 * 1024;
+
+/// Ticks between the snapshots playback keeps for seeking, five seconds of play.
+pub const KEYFRAME_INTERVAL: u64 = 5 * TICK_RATE as u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+	pub config: Config,
+	pub rules: MatchRules,
+	pub seed: u64,
+	/// The inputs of every tick, in order.
+	pub inputs: Vec<TickInput>,
+	/// `Simulation::state_hash` after the last tick.
+	pub final_hash: u64,
+}
+
+impl Replay {
+	/// A simulation in the state the recorded match started from.
+	pub fn start(&self) -> Simulation {
+		Simulation::with_config(self.config, self.rules, self.seed)
+	}
+
+	pub fn len(&self) -> u64 {
+		self.inputs.len() as u64
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.inputs.is_empty()
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		self.write_to(&mut writer)?;
+		writer.flush()?;
+		Ok(())
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+		Replay::read_from(&mut BufReader::new(File::open(path)?))
+	}
+
+	pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+		writer.write_all(MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+		writer.write_all(&self.seed.to_le_bytes())?;
+
+		writer.write_all(&self.rules.win_score.to_le_bytes())?;
+		writer.write_all(&self.rules.win_by.to_le_bytes())?;
+		let serve: u8 = match self.rules.serve {
+			ServeRule::ToConceder => 0,
+			ServeRule::Alternate => 1,
+		};
+		writer.write_all(&[serve])?;
+		let format: u8 = match self.rules.format {
+			Format::Duel => 0,
+			Format::FourWay => 1,
+			Format::Doubles => 2,
+		};
+		writer.write_all(&[format])?;
+		writer.write_all(&self.rules.lives.to_le_bytes())?;
+		let (kind, every, max_balls, rally_end) = match self.rules.multiball {
+			None => (0, 0, 0, RallyEnd::FirstOut),
+			Some(MultiBall { spawn: Spawn::Timer(ticks), max_balls, rally_end }) => (1, ticks, max_balls, rally_end),
+			Some(MultiBall { spawn: Spawn::Hits(hits), max_balls, rally_end }) => (2, hits, max_balls, rally_end),
+		};
+		writer.write_all(&[kind])?;
+		writer.write_all(&every.to_le_bytes())?;
+		writer.write_all(&max_balls.to_le_bytes())?;
+		let rally_end: u8 = match rally_end {
+			RallyEnd::FirstOut => 0,
+			RallyEnd::LastOut => 1,
+		};
+		writer.write_all(&[rally_end])?;
+
+		let config = toml::to_string(&self.config).map_err(|err| ReplayError::Corrupt(err.to_string()))?;
+		writer.write_all(&(config.len() as u32).to_le_bytes())?;
+		writer.write_all(config.as_bytes())?;
+
+		let runs = runs(&self.inputs);
+		writer.write_all(&(runs.len() as u32).to_le_bytes())?;
+		for (length, input) in runs {
+			writer.write_all(&length.to_le_bytes())?;
+			writer.write_all(&input.player1.movement.to_le_bytes())?;
+			writer.write_all(&input.player2.movement.to_le_bytes())?;
+			if self.rules.format.players() == 4 {
+				writer.write_all(&input.player3.movement.to_le_bytes())?;
+				writer.write_all(&input.player4.movement.to_le_bytes())?;
+			}
+		}
+
+		writer.write_all(&self.len().to_le_bytes())?;
+		writer.write_all(&self.final_hash.to_le_bytes())?;
+		Ok(())
+	}
+
+	pub fn read_from<R: Read>(reader: &mut R) -> Result<Replay, ReplayError> {
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(ReplayError::NotAReplay);
+		}
+		let version = u16::from_le_bytes(read(reader)?);
+		if version == 0 || version > VERSION {
+			return Err(ReplayError::UnsupportedVersion(version));
+		}
+		let seed = u64::from_le_bytes(read(reader)?);
+
+		let win_score = u32::from_le_bytes(read(reader)?);
+		let win_by = u32::from_le_bytes(read(reader)?);
+		let serve = match read::<_, 1>(reader)? {
+			[0] => ServeRule::ToConceder,
+			[1] => ServeRule::Alternate,
+			[other] => return Err(ReplayError::Corrupt(format!("unknown serve rule {}", other))),
+		};
+		let mut rules = MatchRules { win_score, win_by, serve, ..MatchRules::default() };
+		if version >= 2 {
+			rules.format = match read::<_, 1>(reader)? {
+				[0] => Format::Duel,
+				[1] => Format::FourWay,
+				[2] => Format::Doubles,
+				[other] => return Err(ReplayError::Corrupt(format!("unknown format {}", other))),
+			};
+			rules.lives = u32::from_le_bytes(read(reader)?);
+		}
+		if version >= 3 {
+			let kind = read::<_, 1>(reader)?;
+			let every = u32::from_le_bytes(read(reader)?);
+			let max_balls = u32::from_le_bytes(read(reader)?);
+			let rally_end = match read::<_, 1>(reader)? {
+				[0] => RallyEnd::FirstOut,
+				[1] => RallyEnd::LastOut,
+				[other] => return Err(ReplayError::Corrupt(format!("unknown rally end {}", other))),
+			};
+			let spawn = match kind {
+				[0] => None,
+				[1] => Some(Spawn::Timer(every)),
+				[2] => Some(Spawn::Hits(every)),
+				[other] => return Err(ReplayError::Corrupt(format!("unknown multi-ball spawn {}", other))),
+			};
+			rules.multiball = spawn.map(|spawn| MultiBall { spawn, max_balls, rally_end });
+		}
+		rules.validate().map_err(ReplayError::Corrupt)?;
+
+		let length = u32::from_le_bytes(read(reader)?);
+		if length > MAX_CONFIG_LENGTH {
+			return Err(ReplayError::Corrupt(format!("config of {} bytes is too long", length)));
+		}
+		let mut config = Vec::new();
+		reader.by_ref().take(u64::from(length)).read_to_end(&mut config)?;
+		if config.len() != length as usize {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+		}
+		let config = String::from_utf8(config).map_err(|_| ReplayError::Corrupt("config is not UTF-8".to_string()))?;
+		let config = Config::from_toml(&config)?;
+
+		let runs = u32::from_le_bytes(read(reader)?);
+		let mut inputs = Vec::new();
+		let mut total = 0;
+		for _ in 0..runs {
+			let length = u32::from_le_bytes(read(reader)?);
+			total += u64::from(length);
+			if total > MAX_TICKS {
+				return Err(ReplayError::Corrupt(format!("more than {} ticks of input", MAX_TICKS)));
+			}
+			let mut input = TickInput {
+				player1: PaddleInput::new(f32::from_le_bytes(read(reader)?)),
+				player2: PaddleInput::new(f32::from_le_bytes(read(reader)?)),
+				..TickInput::default()
+			};
+			if rules.format.players() == 4 {
+				input.player3 = PaddleInput::new(f32::from_le_bytes(read(reader)?));
+				input.player4 = PaddleInput::new(f32::from_le_bytes(read(reader)?));
+			}
+			inputs.extend((0..length).map(|_| input));
+		}
+
+		let ticks = u64::from_le_bytes(read(reader)?);
+		if ticks != inputs.len() as u64 {
+			return Err(ReplayError::Corrupt(format!("expected {} ticks of input, found {}", ticks, inputs.len())));
+		}
+		let final_hash = u64::from_le_bytes(read(reader)?);
+
+		Ok(Replay { config, rules, seed, inputs, final_hash })
+	}
+}
+
+fn read<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+	let mut bytes = [0; N];
+	reader.read_exact(&mut bytes)?;
+	Ok(bytes)
+}
+
+/// Groups `inputs` into runs of identical ticks, which is most of a keyboard player's match.
+fn runs(inputs: &[TickInput]) -> Vec<(u32, TickInput)> {
+	let mut runs: Vec<(u32, TickInput)> = Vec::new();
+	for input in inputs {
+		match runs.last_mut() {
+			// Compare bit patterns, so e.g. 0.0 and -0.0 are kept apart.
+			Some((length, last)) if same_bits(last, input) && *length < u32::MAX => *length += 1,
+			_ => runs.push((1, *input)),
+		}
+	}
+	runs
+}
+
+fn same_bits(a: &TickInput, b: &TickInput) -> bool {
+	a.player1.movement.to_bits() == b.player1.movement.to_bits()
+		&& a.player2.movement.to_bits() == b.player2.movement.to_bits()
+		&& a.player3.movement.to_bits() == b.player3.movement.to_bits()
+		&& a.player4.movement.to_bits() == b.player4.movement.to_bits()
+}
+
+/// Collects the inputs of a match as it is played.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+	config: Config,
+	rules: MatchRules,
+	seed: u64,
+	inputs: Vec<TickInput>,
+}
+
+impl Recorder {
+	/// Starts recording `simulation`, which must not have been stepped yet.
+	pub fn new(simulation: &Simulation) -> Recorder {
+		Recorder {
+			config: simulation.config,
+			rules: simulation.rules,
+			seed: simulation.seed,
+			inputs: Vec::new(),
+		}
+	}
+
+	/// Records the input of the tick about to be simulated.
+	pub fn record(&mut self, input: &TickInput) {
+		self.inputs.push(*input);
+	}
+
+	/// The replay of the match so far, which has reached `simulation`.
+	pub fn finish(&self, simulation: &Simulation) -> Replay {
+		Replay {
+			config: self.config,
+			rules: self.rules,
+			seed: self.seed,
+			inputs: self.inputs.clone(),
+			final_hash: simulation.state_hash(),
+		}
+	}
+}
+
+/// Steps through a replay, able to jump to any tick.
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+	replay: Replay,
+	simulation: Simulation,
+	/// Snapshots taken every `KEYFRAME_INTERVAL` ticks, the first at tick 0, as far as
+	/// playback has got.
+	keyframes: Vec<Simulation>,
+}
+
+impl ReplayPlayer {
+	pub fn new(replay: Replay) -> ReplayPlayer {
+		let simulation = replay.start();
+		ReplayPlayer {
+			keyframes: vec![simulation.clone()],
+			simulation,
+			replay,
+		}
+	}
+
+	pub fn replay(&self) -> &Replay {
+		&self.replay
+	}
+
+	pub fn simulation(&self) -> &Simulation {
+		&self.simulation
+	}
+
+	pub fn tick(&self) -> u64 {
+		self.simulation.tick
+	}
+
+	/// Whether every recorded tick has been played, or the match ended before that (which
+	/// only happens if the replay desynced).
+	pub fn is_finished(&self) -> bool {
+		self.tick() >= self.replay.len() || self.simulation.result.is_some()
+	}
+
+	/// Simulates the next recorded tick, returning `false` if there are none left.
+	pub fn step(&mut self) -> bool {
+		if self.is_finished() {
+			return false;
+		}
+		let input = self.replay.inputs[self.tick() as usize];
+		self.simulation.step(&input);
+
+		let tick = self.tick();
+		if tick.is_multiple_of(KEYFRAME_INTERVAL) && tick / KEYFRAME_INTERVAL == self.keyframes.len() as u64 {
+			self.keyframes.push(self.simulation.clone());
+		}
+		true
+	}
+
+	/// Jumps to `tick` (clamped to the end of the replay) by re-simulating from the last
+	/// keyframe before it.
+	pub fn seek(&mut self, tick: u64) {
+		let tick = tick.min(self.replay.len());
+		let keyframe = ((tick / KEYFRAME_INTERVAL) as usize).min(self.keyframes.len() - 1);
+		let from_keyframe = self.keyframes[keyframe].tick;
+		if tick < self.tick() || from_keyframe > self.tick() {
+			self.simulation.clone_from(&self.keyframes[keyframe]);
+		}
+		while self.tick() < tick && self.step() {}
+	}
+
+	/// Once the end is reached, whether the simulation agrees with the recorded outcome.
+	/// `Some(false)` means the replay desynced: the simulation no longer plays the match
+	/// the way it was recorded.
+	pub fn verify(&self) -> Option<bool> {
+		if self.is_finished() {
+			Some(self.simulation.state_hash() == self.replay.final_hash)
+		} else {
+			None
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+	Io(io::Error),
+	NotAReplay,
+	UnsupportedVersion(u16),
+	Corrupt(String),
+	Config(ConfigError),
+}
+
+impl fmt::Display for ReplayError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ReplayError::Io(err) => write!(f, "{}", err),
+			ReplayError::NotAReplay => write!(f, "not a replay file"),
+			ReplayError::UnsupportedVersion(version) => {
+				write!(f, "replay version {} is not supported, only versions 1 to {}", version, VERSION)
+			}
+			ReplayError::Corrupt(reason) => write!(f, "corrupt replay: {}", reason),
+			ReplayError::Config(err) => write!(f, "invalid config in replay: {}", err),
+		}
+	}
+}
+
+impl Error for ReplayError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			ReplayError::Io(err) => Some(err),
+			ReplayError::Config(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for ReplayError {
+	fn from(err: io::Error) -> ReplayError {
+		ReplayError::Io(err)
+	}
+}
+
+impl From<ConfigError> for ReplayError {
+	fn from(err: ConfigError) -> ReplayError {
+		ReplayError::Config(err)
+	}
+}
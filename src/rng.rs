@@ -0,0 +1,45 @@
+//! A small seeded random number generator that only does integer arithmetic, so it gives
+//! the same numbers on every platform and a match can be replayed exactly from its seed.
+
+/// Increment of the SplitMix64 state, the odd number closest to 2^64 divided by the golden ratio.
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// SplitMix64, as described by Steele, Lea and Flood. Not suitable for cryptography.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Rng {
+		Rng { state: seed }
+	}
+
+	/// Everything the generator remembers; `Rng::new(rng.state())` carries on where `rng` is.
+	pub fn state(&self) -> u64 {
+		self.state
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(GAMMA);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+
+	/// A value in `0.0..1.0`, built from the top 24 bits so every value is exact.
+	pub fn next_f32(&mut self) -> f32 {
+		(self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+	}
+
+	/// A value in `min..max`, or `min` if the two are equal. `min` must not be above `max`.
+	pub fn range(&mut self, min: f32, max: f32) -> f32 {
+		debug_assert!(min <= max, "empty range {}..{}", min, max);
+		min + (max - min) * self.next_f32()
+	}
+
+	pub fn coin(&mut self) -> bool {
+		self.next_u64() >> 63 == 1
+	}
+}
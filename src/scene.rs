@@ -0,0 +1,269 @@
+//! A stack of scenes (menus, the match itself, overlays) driven by tetra's game loop.
+
+mod controls;
+mod game;
+mod lan;
+mod lobby;
+mod menu;
+mod mode_select;
+mod online;
+mod options;
+mod pause;
+mod replay;
+mod results;
+mod spectate;
+mod title;
+
+use std::time::Duration;
+
+use tetra::graphics::scaling::{ScalingMode, ScreenScaler};
+use tetra::graphics::text::Text;
+use tetra::graphics::{self, Color};
+use tetra::math::Vec2;
+use tetra::{window, Context, Event, State};
+
+use mfight_ng::config::Config;
+use mfight_ng::net::{Broadcast, OnlineMatch, Spectator};
+use mfight_ng::replay::Replay;
+use mfight_ng::session::Session;
+use mfight_ng::sim::MatchRules;
+
+use crate::assets::Assets;
+use crate::bindings::{Bindings, BINDINGS_PATH};
+use crate::controls::{Control, GamepadSlots, MouseTracker};
+
+pub use self::controls::ControlsScene;
+pub use self::game::{GameScene, MatchSetup};
+pub use self::lan::LanScene;
+pub use self::lobby::LobbyScene;
+pub use self::menu::Menu;
+pub use self::mode_select::ModeSelectScene;
+pub use self::online::OnlineScene;
+pub use self::options::OptionsScene;
+pub use self::pause::PauseScene;
+pub use self::replay::ReplayScene;
+pub use self::results::ResultsScene;
+pub use self::spectate::SpectateScene;
+pub use self::title::TitleScene;
+
+pub const BACKGROUND: Color = Color::rgb(0.392, 0.584, 0.929);
+
+/// State every scene can read and change: loaded assets, the player's settings and the
+/// connected gamepads.
+pub struct Shared {
+	pub assets: Assets,
+	pub config: Config,
+	pub rules: MatchRules,
+	pub bindings: Bindings,
+	pub gamepads: GamepadSlots,
+	/// Fastest a mouse-controlled paddle may move, as a fraction of the paddle speed.
+	pub mouse_speed: f32,
+	pub mouse: MouseTracker,
+	/// Seed of each session started from the menus.
+	pub seed: u64,
+	/// What other players and spectators see this one called.
+	pub name: String,
+	/// How far behind the players spectators are shown matches hosted here.
+	pub spectator_delay: Duration,
+	/// Shows local matches to spectators, if asked to.
+	pub broadcast: Option<Broadcast>,
+}
+
+/// What to show once the game has loaded.
+pub enum Start {
+	Title,
+	/// A match between these controls, indexed by `Player::index`.
+	Match([Control; 2]),
+	Replay(Replay),
+	/// An online match, with the local player using this control.
+	Online(Box<dyn OnlineMatch>, Control),
+	/// Someone else's match, as a spectator.
+	Watch(Box<Spectator>),
+}
+
+/// What the scene stack should do after a scene has updated.
+pub enum Transition {
+	None,
+	Push(Box<dyn Scene>),
+	Pop,
+	Replace(Box<dyn Scene>),
+	/// Pops this scene and replaces the one below it, e.g. restarting a match from its pause menu.
+	PopReplace(Box<dyn Scene>),
+	/// Drops every scene and starts over from this one.
+	Reset(Box<dyn Scene>),
+	Quit,
+}
+
+pub trait Scene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition>;
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result;
+
+	fn event(&mut self, _ctx: &mut Context, _shared: &mut Shared, _event: Event) -> tetra::Result<Transition> {
+		Ok(Transition::None)
+	}
+
+	/// Overlays are drawn on top of the scene below them instead of replacing it.
+	fn is_overlay(&self) -> bool {
+		false
+	}
+
+	/// Whether the cursor should be hidden and confined to the window while this scene is
+	/// on top, for relative mouse control.
+	fn grabs_mouse(&self) -> bool {
+		false
+	}
+}
+
+pub struct SceneManager {
+	scenes: Vec<Box<dyn Scene>>,
+	shared: Shared,
+	/// Scenes draw to an arena-sized canvas, which this scales to fit the window.
+	scaler: ScreenScaler,
+}
+
+impl SceneManager {
+	/// Starts at `start`, with the title screen underneath it to return to.
+	pub fn new(
+		ctx: &mut Context,
+		config: Config,
+		seed: u64,
+		name: String,
+		spectator_delay: Duration,
+		broadcast: Option<Broadcast>,
+		start: Start,
+	) -> tetra::Result<SceneManager> {
+		let (width, height) = (config.arena_width as i32, config.arena_height as i32);
+		let shared = Shared {
+			assets: Assets::load(ctx, &config)?,
+			config,
+			rules: MatchRules { win_score: config.win_score, ..MatchRules::default() },
+			bindings: Bindings::load_or_default(BINDINGS_PATH),
+			gamepads: GamepadSlots::default(),
+			mouse_speed: 1.0,
+			mouse: MouseTracker::default(),
+			seed,
+			name,
+			spectator_delay,
+			broadcast,
+		};
+
+		let mut scenes: Vec<Box<dyn Scene>> = vec![Box::new(TitleScene::new(&shared))];
+		match start {
+			Start::Title => {}
+			Start::Match(controls) => {
+				let setup = MatchSetup::new(Session::new(config, shared.rules, seed), controls.to_vec());
+				scenes.push(Box::new(GameScene::new(&shared, setup)));
+			}
+			Start::Replay(replay) => scenes.push(Box::new(ReplayScene::new(&shared, replay))),
+			Start::Online(connection, control) => {
+				scenes.push(Box::new(OnlineScene::new(&shared, connection, control)));
+			}
+			Start::Watch(spectator) => scenes.push(Box::new(SpectateScene::new(&shared, *spectator))),
+		}
+
+		Ok(SceneManager {
+			scenes,
+			shared,
+			scaler: ScreenScaler::with_window_size(ctx, width, height, ScalingMode::ShowAll)?,
+		})
+	}
+
+	fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+		match transition {
+			Transition::None => {}
+			Transition::Push(scene) => self.scenes.push(scene),
+			Transition::Pop => {
+				self.scenes.pop();
+			}
+			Transition::Replace(scene) => {
+				self.scenes.pop();
+				self.scenes.push(scene);
+			}
+			Transition::PopReplace(scene) => {
+				self.scenes.pop();
+				self.scenes.pop();
+				self.scenes.push(scene);
+			}
+			Transition::Reset(scene) => {
+				self.scenes.clear();
+				self.scenes.push(scene);
+			}
+			Transition::Quit => self.scenes.clear(),
+		}
+
+		if self.scenes.is_empty() {
+			window::quit(ctx);
+		}
+	}
+}
+
+impl State for SceneManager {
+	fn update(&mut self, ctx: &mut Context) -> tetra::Result {
+		self.shared.mouse.set_position(self.scaler.mouse_position(ctx));
+
+		let transition = match self.scenes.last_mut() {
+			Some(scene) => scene.update(ctx, &mut self.shared)?,
+			None => Transition::None,
+		};
+		self.apply(ctx, transition);
+		self.shared.mouse.clear_delta();
+
+		if let Some(Err(err)) = self.shared.broadcast.as_mut().map(Broadcast::poll) {
+			eprintln!("stopped showing matches to spectators: {}", err);
+			self.shared.broadcast = None;
+		}
+
+		let grab = self.scenes.last().is_some_and(|scene| scene.grabs_mouse());
+		if grab != window::is_relative_mouse_mode(ctx) {
+			window::set_relative_mouse_mode(ctx, grab);
+		}
+
+		Ok(())
+	}
+
+	fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
+		graphics::set_canvas(ctx, self.scaler.canvas());
+		graphics::clear(ctx, BACKGROUND);
+
+		let first = self.scenes.iter()
+			.rposition(|scene| !scene.is_overlay())
+			.unwrap_or(0);
+		for scene in &mut self.scenes[first..] {
+			scene.draw(ctx, &mut self.shared)?;
+		}
+
+		graphics::reset_canvas(ctx);
+		graphics::clear(ctx, Color::BLACK);
+		self.scaler.draw(ctx);
+
+		Ok(())
+	}
+
+	fn event(&mut self, ctx: &mut Context, event: Event) -> tetra::Result {
+		match event {
+			Event::GamepadAdded { id } => self.shared.gamepads.connect(id),
+			Event::GamepadRemoved { id } => self.shared.gamepads.disconnect(id),
+			Event::MouseMoved { position, delta } => {
+				// Scaling is linear, so moving in the window scales the same way anywhere.
+				let moved = self.scaler.unproject(position) - self.scaler.unproject(position - delta);
+				self.shared.mouse.add_delta(moved);
+			}
+			Event::Resized { width, height } => self.scaler.set_outer_size(width, height),
+			_ => {}
+		}
+
+		let transition = match self.scenes.last_mut() {
+			Some(scene) => scene.event(ctx, &mut self.shared, event)?,
+			None => Transition::None,
+		};
+		self.apply(ctx, transition);
+
+		Ok(())
+	}
+}
+
+pub fn draw_centred(ctx: &mut Context, shared: &Shared, text: &mut Text, y: f32) {
+	let width = text.get_bounds(ctx).map_or(0.0, |bounds| bounds.width);
+	text.draw(ctx, Vec2::new((shared.config.arena_width - width) / 2.0, y));
+}
@@ -0,0 +1,113 @@
+use tetra::graphics::text::Text;
+use tetra::input::Key;
+use tetra::{Context, Event};
+
+use crate::bindings::{Action, Bindings, BINDINGS_PATH};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, Menu, Scene, Shared, Transition};
+
+const RESET: usize = Action::ALL.len();
+
+pub struct ControlsScene {
+	title: Text,
+	menu: Menu,
+	message: Text,
+	/// The action waiting for a key press, if any.
+	capturing: Option<Action>,
+	/// Set when a key press ended a capture, so the same press isn't also read as going
+	/// back or picking a menu item before the next frame.
+	captured_this_frame: bool,
+}
+
+impl ControlsScene {
+	pub fn new(shared: &Shared) -> ControlsScene {
+		let mut items = vec![""; Action::ALL.len()];
+		items.extend(&["Reset to defaults", "Back"]);
+
+		let mut scene = ControlsScene {
+			title: Text::new("Controls", shared.assets.font.clone()),
+			menu: Menu::new(&shared.assets.small_font, &items),
+			message: Text::new("", shared.assets.small_font.clone()),
+			capturing: None,
+			captured_this_frame: false,
+		};
+		scene.refresh(&shared.bindings);
+		scene
+	}
+
+	fn refresh(&mut self, bindings: &Bindings) {
+		for (i, &action) in Action::ALL.iter().enumerate() {
+			self.menu.set_item(i, format!("{}: {:?}", action.name(), bindings.get(action)));
+		}
+	}
+
+	fn save(&mut self, bindings: &Bindings) {
+		if let Err(err) = bindings.save(BINDINGS_PATH) {
+			self.message.set_content(format!("Could not save bindings: {}", err));
+		}
+	}
+}
+
+impl Scene for ControlsScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if self.capturing.is_some() || std::mem::take(&mut self.captured_this_frame) {
+			return Ok(Transition::None);
+		}
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Pop);
+		}
+
+		match self.menu.update(ctx, shared) {
+			Some(i) if i < RESET => {
+				let action = Action::ALL[i];
+				self.capturing = Some(action);
+				self.message.set_content(format!("Press a key for {} (Esc to cancel)", action.name()));
+			}
+			Some(RESET) => {
+				shared.bindings = Bindings::default();
+				self.refresh(&shared.bindings);
+				self.message.set_content("Reset to defaults");
+				self.save(&shared.bindings);
+			}
+			Some(_) => return Ok(Transition::Pop),
+			None => {}
+		}
+
+		Ok(Transition::None)
+	}
+
+	fn event(&mut self, _ctx: &mut Context, shared: &mut Shared, event: Event) -> tetra::Result<Transition> {
+		let (action, key) = match (self.capturing, event) {
+			(Some(action), Event::KeyPressed { key }) => (action, key),
+			_ => return Ok(Transition::None),
+		};
+		self.capturing = None;
+		self.captured_this_frame = true;
+
+		// Escape cancels, unless it is exactly what the player wants to go back with.
+		if key == Key::Escape && action != Action::Back {
+			self.message.set_content("");
+			return Ok(Transition::None);
+		}
+
+		match shared.bindings.bind(action, key) {
+			Some(other) => self.message.set_content(format!(
+				"{:?} was used by {}, which now has {:?}",
+				key, other.name(), shared.bindings.get(other),
+			)),
+			None => self.message.set_content(""),
+		}
+		self.refresh(&shared.bindings);
+		self.save(&shared.bindings);
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.title, 20.0);
+		self.menu.draw(ctx, shared, 80.0);
+		draw_centred(ctx, shared, &mut self.message, 440.0);
+		Ok(())
+	}
+}
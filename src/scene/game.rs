@@ -0,0 +1,313 @@
+use std::f32::consts::FRAC_PI_2;
+
+use tetra::graphics::text::Text;
+use tetra::graphics::{Color, DrawParams, Texture};
+use tetra::input::{self, Key};
+use tetra::math::Vec2;
+use tetra::{time, Context, Event};
+
+use mfight_ng::control::Actions;
+use mfight_ng::replay::Recorder;
+use mfight_ng::session::{Player, Session};
+use mfight_ng::sim::{Entity, Format, Side, Simulation, TickInput, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+use crate::bindings::Action;
+use crate::controls::{BoxedController, Control};
+
+use super::{draw_centred, PauseScene, ResultsScene, Scene, Shared, Transition};
+
+/// Where to draw `current`, given how far rendering is between the previous tick and the current one.
+fn interpolate(previous: &Entity, current: &Entity, alpha: f32) -> Vec2<f32> {
+	Vec2::lerp(previous.position, current.position, alpha)
+}
+
+/// Draws `texture` stretched over an entity of `size` at `position`, since the config may
+/// make paddles and the ball larger or smaller than their images.
+fn draw_entity(ctx: &mut Context, texture: &Texture, position: Vec2<f32>, size: Vec2<f32>) {
+	let (width, height) = texture.size();
+	let scale = size / Vec2::new(width as f32, height as f32);
+	texture.draw(ctx, DrawParams::new().position(position).scale(scale));
+}
+
+/// Draws a top or bottom paddle: `texture` is upright, so it is turned on its side.
+fn draw_flat_entity(ctx: &mut Context, texture: &Texture, position: Vec2<f32>, size: Vec2<f32>) {
+	let (width, height) = texture.size();
+	let (width, height) = (width as f32, height as f32);
+	texture.draw(
+		ctx,
+		DrawParams::new()
+			.position(position + size / 2.0)
+			.origin(Vec2::new(width, height) / 2.0)
+			.rotation(FRAC_PI_2)
+			.scale(Vec2::new(size.y / width, size.x / height)),
+	);
+}
+
+const WALL_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.35);
+
+/// Draws the paddles in play and the ball `alpha` of the way from `previous` to `current`.
+/// The left and top paddles, forwards included, are drawn with the first of `textures`,
+/// the right and bottom ones with the second. In a four-way match the corners and closed
+/// goals are drawn too.
+pub(super) fn draw_simulation(
+	ctx: &mut Context,
+	shared: &Shared,
+	textures: [&Texture; 2],
+	previous: &Simulation,
+	current: &Simulation,
+	alpha: f32,
+) {
+	if current.rules.format == Format::FourWay {
+		let arena = Vec2::new(current.config.arena_width, current.config.arena_height);
+		for wall in current.walls() {
+			// The walls reach far outside the arena; only the part inside is drawn.
+			let (min, max) = (Vec2::partial_max(wall.min, Vec2::zero()), Vec2::partial_min(wall.max, arena));
+			if min.x < max.x && min.y < max.y {
+				let params = DrawParams::new().position(min).scale((max - min) / arena).color(WALL_COLOR);
+				shared.assets.overlay.draw(ctx, params);
+			}
+		}
+	}
+
+	for paddle in current.paddles_in_play() {
+		let texture = match paddle.side {
+			Side::Left | Side::Top => textures[0],
+			Side::Right | Side::Bottom => textures[1],
+		};
+		let position = interpolate(previous.paddle_at(paddle), current.paddle_at(paddle), alpha);
+		let size = current.paddle_at(paddle).size;
+		if paddle.side.is_horizontal() {
+			draw_flat_entity(ctx, texture, position, size);
+		} else {
+			draw_entity(ctx, texture, position, size);
+		}
+	}
+	// A ball joining or leaving mid-rally shifts the rest, so only balls lined up with
+	// the previous tick's are interpolated.
+	let moved = previous.balls.len() == current.balls.len();
+	for (index, ball) in current.balls.iter().enumerate() {
+		let position = if moved {
+			interpolate(&previous.balls[index].entity, &ball.entity, alpha)
+		} else {
+			ball.entity.position
+		};
+		draw_entity(ctx, &shared.assets.ball_texture, position, ball.entity.size);
+	}
+}
+
+/// Draws, in front of each goal still open in a four-way match, how many more goals its
+/// player can let in, reusing `text` for each.
+pub(super) fn draw_lives(ctx: &mut Context, text: &mut Text, simulation: &Simulation) {
+	let (width, height) = (simulation.config.arena_width, simulation.config.arena_height);
+	let inset = simulation.config.paddle_width + 32.0;
+	for side in simulation.sides_in_play() {
+		text.set_content(simulation.lives(side).to_string());
+		let size = text.get_bounds(ctx).map_or(Vec2::zero(), |bounds| Vec2::new(bounds.width, bounds.height));
+		let position = match side {
+			Side::Left => Vec2::new(inset, (height - size.y) / 2.0),
+			Side::Right => Vec2::new(width - inset - size.x, (height - size.y) / 2.0),
+			Side::Top => Vec2::new((width - size.x) / 2.0, inset),
+			Side::Bottom => Vec2::new((width - size.x) / 2.0, height - inset - size.y),
+		};
+		text.draw(ctx, position);
+	}
+}
+
+/// Everything needed to start (or restart) a match.
+#[derive(Debug, Clone)]
+pub struct MatchSetup {
+	pub session: Session,
+	/// Who controls each player, indexed by `Player::index`.
+	pub controls: Vec<Control>,
+}
+
+impl MatchSetup {
+	pub fn new(session: Session, controls: Vec<Control>) -> MatchSetup {
+		MatchSetup { session, controls }
+	}
+}
+
+pub struct GameScene {
+	setup: MatchSetup,
+	/// Indexed by `Player::index`.
+	controllers: Vec<BoxedController>,
+	/// Actions requested by the controllers during the last ticks.
+	actions: Actions,
+	simulation: Simulation,
+	previous: Simulation,
+	/// Every tick's input so far, so the match can be saved as a replay once it is over.
+	recorder: Recorder,
+	timestep: FixedTimestep,
+	score_text: Text,
+	lives_text: Text,
+	/// Debug builds only: while set, the simulation is frozen and advances a single tick
+	/// each time `.` is pressed.
+	frame_step: bool,
+	tick_text: Text,
+	/// Whether spectators were told this match began.
+	announced: bool,
+}
+
+impl GameScene {
+	pub fn new(shared: &Shared, setup: MatchSetup) -> GameScene {
+		let simulation = setup.session.new_match();
+		let controllers = setup.session.players()
+			.iter()
+			.map(|&player| setup.controls[player.index()].build(setup.session.paddle_of(player), shared))
+			.collect();
+
+		GameScene {
+			controllers,
+			actions: Actions::NONE,
+			setup,
+			previous: simulation.clone(),
+			recorder: Recorder::new(&simulation),
+			simulation,
+			timestep: FixedTimestep::new(TICK_RATE),
+			score_text: Text::new("0 - 0", shared.assets.font.clone()),
+			lives_text: Text::new("", shared.assets.small_font.clone()),
+			frame_step: false,
+			tick_text: Text::new("", shared.assets.small_font.clone()),
+			announced: false,
+		}
+	}
+
+	fn pause(&self, shared: &Shared) -> Transition {
+		Transition::Push(Box::new(PauseScene::new(shared, self.setup.clone())))
+	}
+
+	fn input(&mut self, ctx: &Context) -> TickInput {
+		let mut input = TickInput::default();
+		for &player in self.setup.session.players() {
+			let intent = self.controllers[player.index()].intent(ctx, &self.simulation);
+			self.actions.insert(intent.actions);
+			input.set_paddle(self.setup.session.paddle_of(player), intent.input);
+		}
+		input
+	}
+
+	fn step(&mut self, ctx: &Context, shared: &mut Shared) {
+		let input = self.input(ctx);
+		self.recorder.record(&input);
+		self.previous.clone_from(&self.simulation);
+		self.simulation.step(&input);
+		if let (true, Some(broadcast)) = (self.announced, &mut shared.broadcast) {
+			broadcast.record(&self.simulation, input);
+		}
+		let conceded = self.simulation.conceded != self.previous.conceded;
+		if self.simulation.score != self.previous.score || conceded {
+			// The ball was just served from the centre, so don't draw it sliding there.
+			self.previous.clone_from(&self.simulation);
+		}
+	}
+
+	/// Tells spectators about the match, the first time it updates.
+	fn announce(&mut self, shared: &mut Shared) {
+		let session = &self.setup.session;
+		if self.announced || shared.broadcast.is_none() {
+			return;
+		}
+		let controls = &self.setup.controls;
+		let humans = controls.iter().filter(|control| !matches!(control, Control::Cpu(_))).count();
+		let name = |player: Player| match controls[player.index()] {
+			Control::Cpu(_) => controls[player.index()].name(),
+			_ if humans == 1 => shared.name.clone(),
+			_ => format!("Player {}", player.index() + 1),
+		};
+		// A doubles team is named after both its players, defender first.
+		let names = Side::ALL.map(|side| {
+			let team = session.players().iter().filter(|&&player| session.side_of(player) == side);
+			team.map(|&player| name(player)).collect::<Vec<_>>().join(" & ")
+		});
+
+		if let Some(broadcast) = &mut shared.broadcast {
+			broadcast.begin(session.config, session.rules, session.match_seed(), names);
+			broadcast.record(&self.simulation, TickInput::default());
+		}
+		self.announced = true;
+	}
+
+	fn texture_for<'a>(&self, shared: &'a Shared, side: Side) -> &'a Texture {
+		match self.setup.session.player_on(side) {
+			Player::One | Player::Three => &shared.assets.player1_texture,
+			Player::Two | Player::Four => &shared.assets.player2_texture,
+		}
+	}
+}
+
+impl Scene for GameScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		let bindings = &shared.bindings;
+		if bindings.is_pressed(ctx, Action::Pause) || bindings.is_pressed(ctx, Action::Back) {
+			return Ok(self.pause(shared));
+		}
+		for controller in &mut self.controllers {
+			controller.poll(ctx);
+		}
+		if cfg!(debug_assertions) && input::is_key_pressed(ctx, Key::F1) {
+			self.frame_step = !self.frame_step;
+		}
+
+		self.announce(shared);
+		let score = self.simulation.score;
+		if self.frame_step {
+			if input::is_key_pressed(ctx, Key::Period) {
+				self.step(ctx, shared);
+			}
+			self.tick_text.set_content(format!("Frame step: tick {}", self.simulation.tick));
+		} else {
+			self.timestep.advance(time::get_delta_time(ctx));
+			while self.timestep.tick() {
+				self.step(ctx, shared);
+			}
+		}
+
+		if std::mem::take(&mut self.actions).contains(Actions::PAUSE) {
+			return Ok(self.pause(shared));
+		}
+
+		if self.simulation.score != score {
+			let score = self.simulation.score;
+			self.score_text.set_content(format!("{} - {}", score.left, score.right));
+		}
+
+		if let Some(result) = self.simulation.result {
+			self.setup.session.record(&result);
+			let replay = self.recorder.finish(&self.simulation);
+			let results = ResultsScene::new(shared, self.setup.clone(), result, replay);
+			return Ok(Transition::Replace(Box::new(results)));
+		}
+
+		Ok(Transition::None)
+	}
+
+	fn event(&mut self, _ctx: &mut Context, shared: &mut Shared, event: Event) -> tetra::Result<Transition> {
+		Ok(match event {
+			Event::FocusLost => self.pause(shared),
+			_ => Transition::None,
+		})
+	}
+
+	fn grabs_mouse(&self) -> bool {
+		self.setup.controls.iter().any(|control| control.grabs_mouse())
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		match self.simulation.rules.format {
+			Format::Duel | Format::Doubles => draw_centred(ctx, shared, &mut self.score_text, 8.0),
+			Format::FourWay => draw_lives(ctx, &mut self.lives_text, &self.simulation),
+		}
+
+		// Frame stepping shows exactly the last simulated tick.
+		let alpha = if self.frame_step { 1.0 } else { self.timestep.alpha() };
+		let textures = [self.texture_for(shared, Side::Left), self.texture_for(shared, Side::Right)];
+		draw_simulation(ctx, shared, textures, &self.previous, &self.simulation, alpha);
+
+		if self.frame_step {
+			self.tick_text.draw(ctx, Vec2::new(8.0, 8.0));
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,137 @@
+use tetra::graphics::text::Text;
+use tetra::Context;
+
+use mfight_ng::net::{
+	Browser, FoundGame, GameKind, Lobby, LinkConditions, MatchSettings, ServerClient, Spectator, DEFAULT_INPUT_DELAY,
+};
+use mfight_ng::sim::{Format, MatchRules, Side};
+
+use crate::controls::{Control, KeySet};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, LobbyScene, Menu, OnlineScene, Scene, Shared, SpectateScene, Transition};
+
+/// Lists the games on the local network, to join one or host another.
+pub struct LanScene {
+	title: Text,
+	/// Missing if the network couldn't be searched; hosting still works.
+	browser: Option<Browser>,
+	/// The games in the menu, between "Host a game" and "Back".
+	games: Vec<FoundGame>,
+	menu: Menu,
+	status_text: Text,
+}
+
+impl LanScene {
+	pub fn new(shared: &Shared) -> LanScene {
+		let mut status_text = Text::new("Looking for games...", shared.assets.small_font.clone());
+		let browser = Browser::new()
+			.map_err(|err| status_text.set_content(format!("Can't look for games: {}", err)))
+			.ok();
+		LanScene {
+			title: Text::new("LAN games", shared.assets.font.clone()),
+			browser,
+			games: Vec::new(),
+			menu: Menu::new(&shared.assets.small_font, &["Host a game", "Back"]),
+			status_text,
+		}
+	}
+
+	fn refresh(&mut self) {
+		let games = match &self.browser {
+			Some(browser) => browser.games(),
+			None => return,
+		};
+		let same = |a: &FoundGame, b: &FoundGame| a.address == b.address && a.announcement == b.announcement;
+		if games.len() == self.games.len() && games.iter().zip(&self.games).all(|(a, b)| same(a, b)) {
+			return;
+		}
+		self.games = games.to_vec();
+
+		let mut items = vec!["Host a game".to_string()];
+		items.extend(self.games.iter().map(|game| {
+			let announcement = &game.announcement;
+			let server = if announcement.kind == GameKind::Server { " [server]" } else { "" };
+			let watch = if announcement.players >= announcement.capacity { ", watch" } else { "" };
+			format!(
+				"{}{} - {} ({}/{}{})",
+				announcement.name, server, announcement.mode, announcement.players, announcement.capacity, watch,
+			)
+		}));
+		items.push("Back".to_string());
+		self.menu.set_items(items);
+	}
+
+	fn host(&mut self, shared: &Shared) -> Transition {
+		let settings = MatchSettings {
+			config: shared.config,
+			// Online matches are always duels.
+			rules: MatchRules { format: Format::Duel, multiball: None, ..shared.rules },
+			seed: shared.seed,
+			input_delay: DEFAULT_INPUT_DELAY,
+			host_side: Side::Left,
+		};
+		match Lobby::host(&shared.name, settings, LinkConditions::default()) {
+			Ok(lobby) => Transition::Push(Box::new(LobbyScene::new(shared, lobby))),
+			Err(err) => {
+				self.status_text.set_content(format!("Can't host a game: {}", err));
+				Transition::None
+			}
+		}
+	}
+
+	/// Joins `game`, or watches it if it is full.
+	fn join(&mut self, shared: &Shared, game: &FoundGame) -> Transition {
+		let announcement = &game.announcement;
+		let conditions = LinkConditions::default();
+		let scene: Result<Box<dyn Scene>, _> = match announcement.kind {
+			_ if announcement.players >= announcement.capacity => Spectator::watch(game.address, conditions)
+				.map(|spectator| Box::new(SpectateScene::new(shared, spectator)) as _),
+			GameKind::Lobby => Lobby::join(game.address, &shared.name, conditions)
+				.map(|lobby| Box::new(LobbyScene::new(shared, lobby)) as _),
+			GameKind::Server => ServerClient::connect(game.address, &shared.name, conditions).map(|client| {
+				Box::new(OnlineScene::new(shared, Box::new(client), Control::Keyboard(KeySet::Any))) as _
+			}),
+		};
+		match scene {
+			Ok(scene) => Transition::Push(scene),
+			Err(err) => {
+				self.status_text.set_content(format!("Can't join {}: {}", announcement.name, err));
+				Transition::None
+			}
+		}
+	}
+}
+
+impl Scene for LanScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Pop);
+		}
+
+		if let Some(browser) = &mut self.browser {
+			if let Err(err) = browser.poll() {
+				self.status_text.set_content(format!("Can't look for games: {}", err));
+				self.browser = None;
+			}
+		}
+		self.refresh();
+
+		Ok(match self.menu.update(ctx, shared) {
+			Some(0) => self.host(shared),
+			Some(i) if i <= self.games.len() => {
+				let game = self.games[i - 1].clone();
+				self.join(shared, &game)
+			}
+			Some(_) => Transition::Pop,
+			None => Transition::None,
+		})
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.title, 60.0);
+		self.menu.draw(ctx, shared, 140.0);
+		draw_centred(ctx, shared, &mut self.status_text, shared.config.arena_height - 40.0);
+		Ok(())
+	}
+}
@@ -0,0 +1,133 @@
+use tetra::graphics::text::Text;
+use tetra::Context;
+
+use mfight_ng::net::{Lobby, LobbyPlayer, NetError};
+use mfight_ng::sim::Side;
+
+use crate::controls::{Control, KeySet};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, Menu, OnlineScene, Scene, Shared, Transition};
+
+const READY: usize = 0;
+const SWAP: usize = 1;
+
+/// Two players getting ready for an online match: once both are, it starts on both
+/// machines after a short countdown.
+pub struct LobbyScene {
+	/// Taken when the match starts.
+	lobby: Option<Lobby>,
+	menu: Menu,
+	players_text: Text,
+	status_text: Text,
+	/// Set when the lobby failed; there is nothing left to do but leave.
+	error: Option<NetError>,
+}
+
+impl LobbyScene {
+	pub fn new(shared: &Shared, lobby: Lobby) -> LobbyScene {
+		LobbyScene {
+			lobby: Some(lobby),
+			menu: Menu::new(&shared.assets.small_font, &["Ready", "Swap sides", "Leave"]),
+			players_text: Text::new("", shared.assets.font.clone()),
+			status_text: Text::new("", shared.assets.small_font.clone()),
+			error: None,
+		}
+	}
+
+	fn leave(&mut self) -> Transition {
+		if let Some(lobby) = &mut self.lobby {
+			lobby.leave();
+		}
+		Transition::Pop
+	}
+
+	fn refresh(&mut self) {
+		let lobby = match &self.lobby {
+			Some(lobby) => lobby,
+			None => return,
+		};
+		if let Some(err) = &self.error {
+			self.status_text.set_content(format!("{}", err));
+			return;
+		}
+
+		let describe = |player: Option<LobbyPlayer>| match player {
+			Some(player) if player.ready => format!("{} (ready)", player.name),
+			Some(player) => player.name,
+			None => "waiting for a player...".to_string(),
+		};
+		let players = [lobby.local(), lobby.peer()];
+		let on = |side| players.iter().flatten().find(|player| player.side == side).cloned();
+		self.players_text.set_content(format!(
+			"Left: {}\nRight: {}",
+			describe(on(Side::Left)),
+			describe(on(Side::Right)),
+		));
+
+		let ready = lobby.local().is_some_and(|player| player.ready);
+		self.menu.set_item(READY, (if ready { "Not ready" } else { "Ready" }).to_string());
+
+		let status = match (lobby.countdown(), lobby.settings(), lobby.local_addr()) {
+			(Some(countdown), _, _) => format!("Starting in {}...", countdown.as_secs() + 1),
+			(None, None, _) => "Connecting...".to_string(),
+			(None, Some(settings), Ok(address)) if lobby.is_host() => {
+				format!("{} on port {}", settings.mode(), address.port())
+			}
+			(None, Some(settings), _) => match lobby.latency().ping() {
+				Some(ping) => format!("{}, ping {} ms", settings.mode(), ping.as_millis()),
+				None => settings.mode(),
+			},
+		};
+		self.status_text.set_content(status);
+	}
+}
+
+impl Scene for LobbyScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(self.leave());
+		}
+
+		if self.error.is_none() {
+			if let Some(lobby) = &mut self.lobby {
+				if let Err(err) = lobby.poll() {
+					self.error = Some(err);
+				}
+			}
+		}
+		if self.lobby.as_ref().is_some_and(Lobby::should_start) {
+			if let Some(lobby) = self.lobby.take() {
+				let mut connection = lobby.into_connection();
+				connection.set_spectator_delay(shared.spectator_delay);
+				let connection = Box::new(connection);
+				let control = Control::Keyboard(KeySet::Any);
+				return Ok(Transition::Replace(Box::new(OnlineScene::new(shared, connection, control))));
+			}
+		}
+
+		let chosen = self.menu.update(ctx, shared);
+		let result = match (&mut self.lobby, chosen) {
+			(Some(lobby), Some(READY)) if self.error.is_none() => {
+				let ready = lobby.local().is_some_and(|player| player.ready);
+				lobby.set_ready(!ready)
+			}
+			(Some(lobby), Some(SWAP)) if self.error.is_none() => lobby.swap_sides(),
+			(_, Some(READY)) | (_, Some(SWAP)) | (_, None) => Ok(()),
+			(_, Some(_)) => return Ok(self.leave()),
+		};
+		if let Err(err) = result {
+			self.error = Some(err);
+		}
+		self.refresh();
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.players_text, 80.0);
+		self.menu.draw(ctx, shared, 220.0);
+		draw_centred(ctx, shared, &mut self.status_text, shared.config.arena_height - 40.0);
+		Ok(())
+	}
+}
@@ -0,0 +1,110 @@
+use tetra::graphics::text::{Font, Text};
+use tetra::input::{self, GamepadAxis, GamepadButton};
+use tetra::Context;
+
+use crate::bindings::Action;
+use crate::controls::GamepadSlots;
+
+use super::{draw_centred, Shared};
+
+/// How far a stick must be pushed to move a menu selection.
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// A vertical list of options navigated with either player's up and down keys, or the D-pad
+/// or left stick of any gamepad.
+pub struct Menu {
+	items: Vec<String>,
+	selected: usize,
+	text: Text,
+	/// Which way the stick was pushed last frame, so holding it moves the selection only once.
+	stick: i8,
+}
+
+impl Menu {
+	pub fn new(font: &Font, items: &[&str]) -> Menu {
+		let mut menu = Menu {
+			items: items.iter().map(|item| item.to_string()).collect(),
+			selected: 0,
+			text: Text::new("", font.clone()),
+			stick: 0,
+		};
+		menu.refresh();
+		menu
+	}
+
+	pub fn set_item(&mut self, index: usize, label: String) {
+		self.items[index] = label;
+		self.refresh();
+	}
+
+	/// Replaces every item, keeping the selection where it was if it still exists.
+	pub fn set_items(&mut self, items: Vec<String>) {
+		self.selected = self.selected.min(items.len().saturating_sub(1));
+		self.items = items;
+		self.refresh();
+	}
+
+	/// Moves the selection, returning the index of the item chosen with the confirm key or
+	/// a gamepad's A button.
+	pub fn update(&mut self, ctx: &Context, shared: &Shared) -> Option<usize> {
+		let (bindings, gamepads) = (&shared.bindings, &shared.gamepads);
+		let len = self.items.len();
+		let pressed = |actions: [Action; 2], button| {
+			actions.iter().any(|&action| bindings.is_pressed(ctx, action))
+				|| is_pad_pressed(ctx, gamepads, button)
+		};
+
+		let stick = stick_direction(ctx, gamepads);
+		let flicked = if stick != self.stick { stick } else { 0 };
+		self.stick = stick;
+
+		if pressed([Action::P1Up, Action::P2Up], GamepadButton::Up) || flicked < 0 {
+			self.selected = (self.selected + len - 1) % len;
+			self.refresh();
+		}
+		if pressed([Action::P1Down, Action::P2Down], GamepadButton::Down) || flicked > 0 {
+			self.selected = (self.selected + 1) % len;
+			self.refresh();
+		}
+
+		if bindings.is_pressed(ctx, Action::Confirm) || is_pad_pressed(ctx, gamepads, GamepadButton::A) {
+			Some(self.selected)
+		} else {
+			None
+		}
+	}
+
+	pub fn draw(&mut self, ctx: &mut Context, shared: &Shared, y: f32) {
+		draw_centred(ctx, shared, &mut self.text, y);
+	}
+
+	fn refresh(&mut self) {
+		let lines: Vec<String> = self.items.iter()
+			.enumerate()
+			.map(|(i, item)| if i == self.selected {
+				format!("> {} <", item)
+			} else {
+				item.clone()
+			})
+			.collect();
+		self.text.set_content(lines.join("\n"));
+	}
+}
+
+/// Whether the player asked to leave the current menu.
+pub fn is_back_pressed(ctx: &Context, shared: &Shared) -> bool {
+	shared.bindings.is_pressed(ctx, Action::Back) || is_pad_pressed(ctx, &shared.gamepads, GamepadButton::B)
+}
+
+/// Whether `button` was just pressed on any assigned gamepad.
+pub fn is_pad_pressed(ctx: &Context, gamepads: &GamepadSlots, button: GamepadButton) -> bool {
+	gamepads.connected().any(|id| input::is_gamepad_button_pressed(ctx, id, button))
+}
+
+/// -1 if any assigned gamepad's left stick is pushed up, 1 if down, 0 otherwise.
+fn stick_direction(ctx: &Context, gamepads: &GamepadSlots) -> i8 {
+	gamepads.connected()
+		.map(|id| input::get_gamepad_axis_position(ctx, id, GamepadAxis::LeftStickY))
+		.find(|position| position.abs() >= STICK_THRESHOLD)
+		.map_or(0, |position| if position < 0.0 { -1 } else { 1 })
+}
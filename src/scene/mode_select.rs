@@ -0,0 +1,90 @@
+use tetra::graphics::text::Text;
+use tetra::Context;
+
+use mfight_ng::ai::Difficulty;
+use mfight_ng::session::Session;
+use mfight_ng::sim::Format;
+
+use crate::controls::{Control, KeySet};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, GameScene, MatchSetup, Menu, Scene, Shared, Transition};
+
+pub struct ModeSelectScene {
+	title: Text,
+	menu: Menu,
+	choices: Vec<Control>,
+	/// Who controls each player, indexed by `Player::index`. Only the first two play a duel.
+	controls: [Control; 4],
+}
+
+impl ModeSelectScene {
+	pub fn new(shared: &Shared) -> ModeSelectScene {
+		let cpu = Control::Cpu(Difficulty::Normal);
+		let mut scene = ModeSelectScene {
+			title: Text::new("Select mode", shared.assets.font.clone()),
+			menu: Menu::new(&shared.assets.small_font, &[]),
+			choices: Control::choices(),
+			controls: [Control::Keyboard(KeySet::Any), cpu, cpu, cpu],
+		};
+		scene.refresh(shared);
+		scene
+	}
+
+	/// The arena item, then one item per player, then "Start" and "Back".
+	fn refresh(&mut self, shared: &Shared) {
+		let (arena, names): (_, &[&str]) = match shared.rules.format {
+			Format::Duel => ("1v1", &["Left", "Right"]),
+			Format::FourWay => ("four-way", &["Left", "Right", "Top", "Bottom"]),
+			Format::Doubles => ("2v2", &["Left back", "Right back", "Left front", "Right front"]),
+		};
+		let mut items = vec![format!("Arena: {}", arena)];
+		items.extend(names.iter().zip(&self.controls).map(|(name, control)| format!("{}: {}", name, control.name())));
+		items.push("Start".to_string());
+		items.push("Back".to_string());
+		self.menu.set_items(items);
+	}
+
+	fn cycle(&mut self, i: usize) {
+		let current = self.choices.iter().position(|&choice| choice == self.controls[i]).unwrap_or(0);
+		self.controls[i] = self.choices[(current + 1) % self.choices.len()];
+	}
+
+	fn start(&self, shared: &Shared) -> Transition {
+		let session = Session::new(shared.config, shared.rules, shared.seed);
+		let controls = self.controls[..session.players().len()].to_vec();
+		Transition::Push(Box::new(GameScene::new(shared, MatchSetup::new(session, controls))))
+	}
+}
+
+impl Scene for ModeSelectScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Pop);
+		}
+
+		let players = shared.rules.format.players();
+		match self.menu.update(ctx, shared) {
+			Some(0) => {
+				shared.rules.format = match shared.rules.format {
+					Format::Duel => Format::FourWay,
+					Format::FourWay => Format::Doubles,
+					Format::Doubles => Format::Duel,
+				};
+			}
+			Some(i) if i <= players => self.cycle(i - 1),
+			Some(i) if i == players + 1 => return Ok(self.start(shared)),
+			Some(_) => return Ok(Transition::Pop),
+			None => return Ok(Transition::None),
+		}
+		self.refresh(shared);
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.title, 100.0);
+		self.menu.draw(ctx, shared, 180.0);
+		Ok(())
+	}
+}
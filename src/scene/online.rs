@@ -0,0 +1,158 @@
+use tetra::graphics::text::Text;
+use tetra::math::Vec2;
+use tetra::{time, Context};
+
+use mfight_ng::net::{NetError, OnlineMatch};
+use mfight_ng::sim::{PaddleId, Side, Simulation, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+use crate::bindings::Action;
+use crate::controls::{BoxedController, Control};
+
+use super::game::draw_simulation;
+use super::menu::is_back_pressed;
+use super::{draw_centred, Scene, Shared, Transition};
+
+/// A match against a player on another machine, peer to peer or on a server. The local
+/// player always uses the left paddle's texture, so they can tell which paddle is theirs
+/// whichever side they play on.
+pub struct OnlineScene {
+	connection: Box<dyn OnlineMatch>,
+	control: Control,
+	/// Built once the connection says which side is ours.
+	controller: Option<BoxedController>,
+	previous: Option<Simulation>,
+	timestep: FixedTimestep,
+	score_text: Text,
+	/// Ping, and anything wrong with the connection.
+	status_text: Text,
+	/// Shown over the arena: who won, or why the match ended early.
+	message_text: Text,
+	/// Set when the connection failed; the match is over.
+	error: Option<NetError>,
+}
+
+impl OnlineScene {
+	pub fn new(shared: &Shared, connection: Box<dyn OnlineMatch>, control: Control) -> OnlineScene {
+		OnlineScene {
+			connection,
+			control,
+			controller: None,
+			previous: None,
+			timestep: FixedTimestep::new(TICK_RATE),
+			score_text: Text::new("0 - 0", shared.assets.font.clone()),
+			status_text: Text::new("", shared.assets.small_font.clone()),
+			message_text: Text::new("", shared.assets.font.clone()),
+			error: None,
+		}
+	}
+
+	fn play(&mut self, ctx: &mut Context, shared: &Shared) -> Result<(), NetError> {
+		self.connection.poll()?;
+		let side = match self.connection.local_side() {
+			Some(side) => side,
+			None => return Ok(()),
+		};
+		let control = self.control;
+		let controller = self.controller.get_or_insert_with(|| control.build(PaddleId::new(side, 0), shared));
+		controller.poll(ctx);
+
+		self.timestep.advance(time::get_delta_time(ctx));
+		while self.timestep.tick() {
+			let simulation = match self.connection.simulation() {
+				Some(simulation) => simulation.clone(),
+				None => break,
+			};
+			let input = controller.intent(ctx, &simulation).input;
+			// While waiting for the other player the game stands still, and the time is lost.
+			if !self.connection.advance(input)? {
+				break;
+			}
+			self.previous = Some(simulation);
+		}
+		Ok(())
+	}
+
+	fn refresh(&mut self) {
+		let simulation = match self.connection.simulation() {
+			Some(simulation) => simulation,
+			None => {
+				self.status_text.set_content(self.connection.waiting());
+				return;
+			}
+		};
+
+		let score = simulation.score;
+		self.score_text.set_content(format!("{} - {}", score.left, score.right));
+
+		let latency = self.connection.latency();
+		let mut status = match latency.ping() {
+			Some(ping) => format!("Ping {} ms ± {} ms", ping.as_millis(), latency.jitter().as_millis()),
+			None => "Ping -".to_string(),
+		};
+		match self.connection.spectators() {
+			0 => {}
+			1 => status.push_str("  1 spectator"),
+			count => status.push_str(&format!("  {} spectators", count)),
+		}
+		if self.connection.is_stalled() {
+			status.push_str("  Waiting for the other player...");
+		}
+		if self.connection.is_desynced() {
+			status.push_str("  DESYNC");
+		}
+		self.status_text.set_content(status);
+
+		if let Some(result) = self.connection.result() {
+			let verdict = if Some(result.winner) == self.connection.local_side() { "You win!" } else { "You lose" };
+			let score = result.score;
+			self.message_text.set_content(format!("{} {} - {}\nPress confirm to leave", verdict, score.left, score.right));
+		}
+	}
+}
+
+impl Scene for OnlineScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		let over = self.error.is_some() || self.connection.result().is_some();
+		if is_back_pressed(ctx, shared) || (over && shared.bindings.is_pressed(ctx, Action::Confirm)) {
+			self.connection.leave();
+			return Ok(Transition::Pop);
+		}
+
+		if self.error.is_none() {
+			if let Err(err) = self.play(ctx, shared) {
+				// Once the result is settled, the other player leaving changes nothing.
+				if self.connection.result().is_none() {
+					self.message_text.set_content(format!("Match over: {}\nPress confirm to leave", err));
+					self.error = Some(err);
+				}
+			}
+		}
+		self.refresh();
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		if let Some(current) = self.connection.simulation() {
+			draw_centred(ctx, shared, &mut self.score_text, 8.0);
+
+			let assets = &shared.assets;
+			let (ours, theirs) = (&assets.player1_texture, &assets.player2_texture);
+			let textures = match self.connection.local_side() {
+				Some(Side::Right) => [theirs, ours],
+				_ => [ours, theirs],
+			};
+			let previous = self.previous.as_ref().unwrap_or(current);
+			draw_simulation(ctx, shared, textures, previous, current, self.timestep.alpha());
+		}
+
+		draw_centred(ctx, shared, &mut self.message_text, 180.0);
+		self.status_text.draw(ctx, Vec2::new(8.0, shared.config.arena_height - 24.0));
+		Ok(())
+	}
+
+	fn grabs_mouse(&self) -> bool {
+		self.control.grabs_mouse()
+	}
+}
@@ -0,0 +1,119 @@
+use tetra::graphics::text::Text;
+use tetra::Context;
+
+use mfight_ng::sim::{MultiBall, RallyEnd, ServeRule, Spawn, TICK_RATE};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, ControlsScene, Menu, Scene, Shared, Transition};
+
+const WIN_SCORES: [u32; 4] = [5, 7, 11, 21];
+/// Goals each player can let in before being knocked out of a four-way match.
+const LIVES: [u32; 3] = [1, 3, 5];
+/// The multi-ball settings to pick from, after having it off: a ball joining every ten
+/// seconds or every five hits, up to three, with the rally ending on the first or last
+/// ball out.
+const MULTIBALL: [MultiBall; 4] = [
+	MultiBall { spawn: Spawn::Timer(600), max_balls: 3, rally_end: RallyEnd::FirstOut },
+	MultiBall { spawn: Spawn::Hits(5), max_balls: 3, rally_end: RallyEnd::FirstOut },
+	MultiBall { spawn: Spawn::Timer(600), max_balls: 3, rally_end: RallyEnd::LastOut },
+	MultiBall { spawn: Spawn::Hits(5), max_balls: 3, rally_end: RallyEnd::LastOut },
+];
+/// Caps on mouse-controlled paddles, as fractions of the paddle speed.
+const MOUSE_SPEEDS: [f32; 3] = [0.5, 0.75, 1.0];
+
+pub struct OptionsScene {
+	title: Text,
+	menu: Menu,
+}
+
+impl OptionsScene {
+	pub fn new(shared: &Shared) -> OptionsScene {
+		let mut scene = OptionsScene {
+			title: Text::new("Options", shared.assets.font.clone()),
+			menu: Menu::new(&shared.assets.small_font, &["", "", "", "", "", "", "Controls", "Back"]),
+		};
+		scene.refresh(shared);
+		scene
+	}
+
+	fn refresh(&mut self, shared: &Shared) {
+		let rules = &shared.rules;
+		self.menu.set_item(0, format!("Play to: {}", rules.win_score));
+		self.menu.set_item(1, format!("Win by: {}", rules.win_by));
+		self.menu.set_item(2, match rules.serve {
+			ServeRule::ToConceder => "Serve: to conceder".to_string(),
+			ServeRule::Alternate => "Serve: alternate".to_string(),
+		});
+		self.menu.set_item(3, format!("Four-way lives: {}", rules.lives));
+		self.menu.set_item(4, match rules.multiball {
+			None => "Multi-ball: off".to_string(),
+			Some(multiball) => {
+				let spawn = match multiball.spawn {
+					Spawn::Timer(ticks) => format!("every {}s", ticks / TICK_RATE),
+					Spawn::Hits(hits) => format!("every {} hits", hits),
+				};
+				let end = match multiball.rally_end {
+					RallyEnd::FirstOut => "",
+					RallyEnd::LastOut => ", last out",
+				};
+				format!("Multi-ball: {}{}", spawn, end)
+			}
+		});
+		self.menu.set_item(5, format!("Mouse speed: {}%", (shared.mouse_speed * 100.0).round()));
+	}
+}
+
+impl Scene for OptionsScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Pop);
+		}
+
+		let selected = self.menu.update(ctx, shared);
+		let rules = &mut shared.rules;
+		match selected {
+			Some(0) => {
+				let next = WIN_SCORES.iter()
+					.position(|&score| score == rules.win_score)
+					.map_or(0, |i| (i + 1) % WIN_SCORES.len());
+				rules.win_score = WIN_SCORES[next];
+			}
+			Some(1) => rules.win_by = if rules.win_by == 1 { 2 } else { 1 },
+			Some(2) => rules.serve = match rules.serve {
+				ServeRule::ToConceder => ServeRule::Alternate,
+				ServeRule::Alternate => ServeRule::ToConceder,
+			},
+			Some(3) => {
+				let next = LIVES.iter()
+					.position(|&lives| lives == rules.lives)
+					.map_or(0, |i| (i + 1) % LIVES.len());
+				rules.lives = LIVES[next];
+			}
+			Some(4) => {
+				// Off, then each setting in turn, then off again.
+				let next = rules.multiball.map_or(0, |current| {
+					MULTIBALL.iter().position(|&multiball| multiball == current).map_or(0, |i| i + 1)
+				});
+				rules.multiball = MULTIBALL.get(next).copied();
+			}
+			Some(5) => {
+				let next = MOUSE_SPEEDS.iter()
+					.position(|&speed| speed == shared.mouse_speed)
+					.map_or(0, |i| (i + 1) % MOUSE_SPEEDS.len());
+				shared.mouse_speed = MOUSE_SPEEDS[next];
+			}
+			Some(6) => return Ok(Transition::Push(Box::new(ControlsScene::new(shared)))),
+			Some(_) => return Ok(Transition::Pop),
+			None => return Ok(Transition::None),
+		}
+		self.refresh(shared);
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.title, 100.0);
+		self.menu.draw(ctx, shared, 200.0);
+		Ok(())
+	}
+}
@@ -0,0 +1,54 @@
+use tetra::graphics::text::Text;
+use tetra::graphics::{Color, DrawParams};
+use tetra::input::GamepadButton;
+use tetra::Context;
+
+use crate::bindings::Action;
+
+use super::menu::{is_back_pressed, is_pad_pressed};
+use super::{draw_centred, GameScene, MatchSetup, Menu, Scene, Shared, TitleScene, Transition};
+
+pub struct PauseScene {
+	/// The setup of the paused match, so it can be restarted.
+	setup: MatchSetup,
+	title: Text,
+	menu: Menu,
+}
+
+impl PauseScene {
+	pub fn new(shared: &Shared, setup: MatchSetup) -> PauseScene {
+		PauseScene {
+			setup,
+			title: Text::new("Paused", shared.assets.font.clone()),
+			menu: Menu::new(&shared.assets.small_font, &["Resume", "Restart", "Quit to menu"]),
+		}
+	}
+}
+
+impl Scene for PauseScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		let resume = shared.bindings.is_pressed(ctx, Action::Pause)
+			|| is_pad_pressed(ctx, &shared.gamepads, GamepadButton::Start);
+		if is_back_pressed(ctx, shared) || resume {
+			return Ok(Transition::Pop);
+		}
+
+		Ok(match self.menu.update(ctx, shared) {
+			Some(0) => Transition::Pop,
+			Some(1) => Transition::PopReplace(Box::new(GameScene::new(shared, self.setup.clone()))),
+			Some(_) => Transition::Reset(Box::new(TitleScene::new(shared))),
+			None => Transition::None,
+		})
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		shared.assets.overlay.draw(ctx, DrawParams::new().color(Color::rgba(0.0, 0.0, 0.0, 0.5)));
+		draw_centred(ctx, shared, &mut self.title, 100.0);
+		self.menu.draw(ctx, shared, 220.0);
+		Ok(())
+	}
+
+	fn is_overlay(&self) -> bool {
+		true
+	}
+}
@@ -0,0 +1,137 @@
+use tetra::graphics::text::Text;
+use tetra::input::{self, Key};
+use tetra::math::Vec2;
+use tetra::{time, Context};
+
+use mfight_ng::replay::{Replay, ReplayPlayer};
+use mfight_ng::sim::{Format, Simulation, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+use crate::bindings::Action;
+
+use super::game::{draw_lives, draw_simulation};
+use super::menu::is_back_pressed;
+use super::{draw_centred, Scene, Shared, Transition};
+
+/// Playback speeds, as multiples of real time.
+const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+/// How far the arrow keys seek, in ticks.
+const SEEK_STEP: u64 = 5 * TICK_RATE as u64;
+
+/// Watches a saved match: space pauses, left and right seek, up and down change the speed
+/// and home starts over.
+pub struct ReplayScene {
+	player: ReplayPlayer,
+	previous: Simulation,
+	timestep: FixedTimestep,
+	paused: bool,
+	/// Index into `SPEEDS`.
+	speed: usize,
+	score_text: Text,
+	lives_text: Text,
+	status_text: Text,
+}
+
+impl ReplayScene {
+	pub fn new(shared: &Shared, replay: Replay) -> ReplayScene {
+		let player = ReplayPlayer::new(replay);
+		ReplayScene {
+			previous: player.simulation().clone(),
+			player,
+			timestep: FixedTimestep::new(TICK_RATE),
+			paused: false,
+			speed: NORMAL_SPEED,
+			score_text: Text::new("0 - 0", shared.assets.font.clone()),
+			lives_text: Text::new("", shared.assets.small_font.clone()),
+			status_text: Text::new("", shared.assets.small_font.clone()),
+		}
+	}
+
+	fn step(&mut self) {
+		self.previous.clone_from(self.player.simulation());
+		self.player.step();
+		let (current, previous) = (self.player.simulation(), &self.previous);
+		if current.score != previous.score || current.conceded != previous.conceded {
+			// The ball was just served from the centre, so don't draw it sliding there.
+			self.previous.clone_from(self.player.simulation());
+		}
+	}
+
+	fn seek(&mut self, tick: u64) {
+		self.player.seek(tick);
+		self.previous.clone_from(self.player.simulation());
+	}
+
+	fn refresh(&mut self) {
+		let seconds = |tick: u64| tick as f32 / TICK_RATE as f32;
+		let mut status = format!(
+			"Replay {:.1}s / {:.1}s  x{}",
+			seconds(self.player.tick()), seconds(self.player.replay().len()), SPEEDS[self.speed],
+		);
+		match self.player.verify() {
+			Some(true) => status.push_str("  verified"),
+			Some(false) => status.push_str("  DESYNC: the match no longer plays out as recorded"),
+			None if self.paused => status.push_str("  paused"),
+			None => {}
+		}
+		self.status_text.set_content(status);
+
+		let score = self.player.simulation().score;
+		self.score_text.set_content(format!("{} - {}", score.left, score.right));
+	}
+}
+
+impl Scene for ReplayScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Pop);
+		}
+
+		let bindings = &shared.bindings;
+		if bindings.is_pressed(ctx, Action::Confirm) || bindings.is_pressed(ctx, Action::Pause) {
+			self.paused = !self.paused;
+		}
+		if input::is_key_pressed(ctx, Key::Up) {
+			self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+		}
+		if input::is_key_pressed(ctx, Key::Down) {
+			self.speed = self.speed.saturating_sub(1);
+		}
+		if input::is_key_pressed(ctx, Key::Left) {
+			self.seek(self.player.tick().saturating_sub(SEEK_STEP));
+		}
+		if input::is_key_pressed(ctx, Key::Right) {
+			self.seek(self.player.tick() + SEEK_STEP);
+		}
+		if input::is_key_pressed(ctx, Key::Home) {
+			self.seek(0);
+		}
+
+		if !self.paused && !self.player.is_finished() {
+			self.timestep.advance(time::get_delta_time(ctx).mul_f32(SPEEDS[self.speed]));
+			while self.timestep.tick() {
+				self.step();
+			}
+		}
+		self.refresh();
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		let simulation = self.player.simulation();
+		match simulation.rules.format {
+			Format::Duel | Format::Doubles => draw_centred(ctx, shared, &mut self.score_text, 8.0),
+			Format::FourWay => draw_lives(ctx, &mut self.lives_text, simulation),
+		}
+
+		// Nothing moves between ticks while paused or at the end.
+		let alpha = if self.paused || self.player.is_finished() { 1.0 } else { self.timestep.alpha() };
+		let textures = [&shared.assets.player1_texture, &shared.assets.player2_texture];
+		draw_simulation(ctx, shared, textures, &self.previous, self.player.simulation(), alpha);
+
+		self.status_text.draw(ctx, Vec2::new(8.0, shared.config.arena_height - 24.0));
+		Ok(())
+	}
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tetra::graphics::text::Text;
+use tetra::input::{self, Key};
+use tetra::Context;
+
+use mfight_ng::replay::{Replay, ReplayError};
+use mfight_ng::sim::{Format, MatchResult};
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, GameScene, MatchSetup, Menu, Scene, Shared, TitleScene, Transition};
+
+/// Directory saved replays go in, one file per match named after when it was saved.
+pub const REPLAY_DIR: &str = "./replays";
+
+/// Saves `replay` in `REPLAY_DIR`, returning where.
+fn save_replay(replay: &Replay) -> Result<PathBuf, ReplayError> {
+	fs::create_dir_all(REPLAY_DIR)?;
+	let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+	let path = PathBuf::from(REPLAY_DIR).join(format!("{}.mfr", time));
+	replay.save(&path)?;
+	Ok(path)
+}
+
+pub struct ResultsScene {
+	setup: MatchSetup,
+	result_text: Text,
+	menu: Menu,
+	/// The match just played, until it is saved.
+	replay: Option<Replay>,
+	/// Where the replay was saved, or why it couldn't be.
+	replay_text: Text,
+}
+
+impl ResultsScene {
+	pub fn new(shared: &Shared, setup: MatchSetup, result: MatchResult, replay: Replay) -> ResultsScene {
+		let session = &setup.session;
+		let player = session.player_on(result.winner).index() + 1;
+		let wins = session.players()
+			.iter()
+			.map(|player| session.stats.wins[player.index()].to_string())
+			.collect::<Vec<_>>()
+			.join(" - ");
+		// The last player standing in a four-way match has no single opponent to have outscored.
+		let result_text = match session.rules.format {
+			Format::Duel => format!(
+				"Player {} win! {} - {}\nSession: {}",
+				player, result.score.left, result.score.right, wins,
+			),
+			Format::FourWay => format!("Player {} win!\nSession: {}", player, wins),
+			// Teammates always have the same wins, so the session is shown by team.
+			Format::Doubles => format!(
+				"Players {} & {} win! {} - {}\nSession: {} - {}",
+				player,
+				player + 2,
+				result.score.left,
+				result.score.right,
+				session.stats.wins[0],
+				session.stats.wins[1],
+			),
+		};
+		let result_text = Text::new(result_text, shared.assets.font.clone());
+
+		ResultsScene {
+			setup,
+			result_text,
+			menu: Menu::new(&shared.assets.small_font, &["Rematch", "Rematch, swap sides", "Save replay", "Main menu"]),
+			replay: Some(replay),
+			replay_text: Text::new("", shared.assets.small_font.clone()),
+		}
+	}
+
+	/// Starts a new match in the starting layout, keeping the session's statistics.
+	fn rematch(&self, shared: &Shared, swap_sides: bool) -> Transition {
+		let mut setup = self.setup.clone();
+		if swap_sides {
+			setup.session.swap_sides();
+		}
+		Transition::Replace(Box::new(GameScene::new(shared, setup)))
+	}
+
+	fn save_replay(&mut self) {
+		let replay = match &self.replay {
+			Some(replay) => replay,
+			None => return,
+		};
+		match save_replay(replay) {
+			Ok(path) => {
+				self.replay_text.set_content(format!("Replay saved to {}", path.display()));
+				self.replay = None;
+			}
+			Err(err) => self.replay_text.set_content(format!("Couldn't save the replay: {}", err)),
+		}
+	}
+}
+
+impl Scene for ResultsScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if input::is_key_pressed(ctx, Key::R) {
+			return Ok(self.rematch(shared, false));
+		}
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Reset(Box::new(TitleScene::new(shared))));
+		}
+
+		Ok(match self.menu.update(ctx, shared) {
+			Some(0) => self.rematch(shared, false),
+			Some(1) => self.rematch(shared, true),
+			Some(2) => {
+				self.save_replay();
+				Transition::None
+			}
+			Some(_) => Transition::Reset(Box::new(TitleScene::new(shared))),
+			None => Transition::None,
+		})
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.result_text, 100.0);
+		self.menu.draw(ctx, shared, 260.0);
+		draw_centred(ctx, shared, &mut self.replay_text, shared.config.arena_height - 40.0);
+		Ok(())
+	}
+}
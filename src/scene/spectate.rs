@@ -0,0 +1,137 @@
+use tetra::graphics::text::Text;
+use tetra::math::Vec2;
+use tetra::{time, Context};
+
+use mfight_ng::net::{NetError, Spectator};
+use mfight_ng::sim::{Format, Side, Simulation, TICK_RATE};
+use mfight_ng::timestep::FixedTimestep;
+
+use crate::bindings::Action;
+
+use super::game::{draw_lives, draw_simulation};
+use super::menu::is_back_pressed;
+use super::{draw_centred, Scene, Shared, Transition};
+
+/// Someone else's match, watched a little behind the players. It carries on with the
+/// next match its host shows, until the spectator leaves.
+pub struct SpectateScene {
+	spectator: Spectator,
+	previous: Option<Simulation>,
+	timestep: FixedTimestep,
+	/// The players' names either side of the score.
+	score_text: Text,
+	/// Each player's lives left, drawn by their goal in a four-way match instead.
+	lives_text: Text,
+	status_text: Text,
+	/// Shown over the arena: who won, or why there is nothing more to watch.
+	message_text: Text,
+	/// Set when the connection failed; there is nothing more to watch.
+	error: Option<NetError>,
+}
+
+impl SpectateScene {
+	pub fn new(shared: &Shared, spectator: Spectator) -> SpectateScene {
+		SpectateScene {
+			spectator,
+			previous: None,
+			timestep: FixedTimestep::new(TICK_RATE),
+			score_text: Text::new("", shared.assets.font.clone()),
+			lives_text: Text::new("", shared.assets.small_font.clone()),
+			status_text: Text::new("", shared.assets.small_font.clone()),
+			message_text: Text::new("", shared.assets.font.clone()),
+			error: None,
+		}
+	}
+
+	fn watch(&mut self, ctx: &Context) -> Result<(), NetError> {
+		self.spectator.poll()?;
+		self.timestep.advance(time::get_delta_time(ctx));
+		while self.timestep.tick() {
+			let simulation = self.spectator.simulation().cloned();
+			// Out of snapshots: wait for the next, and lose the time.
+			if !self.spectator.advance() {
+				break;
+			}
+			self.previous = simulation;
+		}
+		Ok(())
+	}
+
+	/// The name shown for the player on `side`.
+	fn name(&self, side: Side) -> String {
+		match self.spectator.names().map(|names| names[side as usize].as_str()) {
+			Some(name) if !name.is_empty() => name.to_string(),
+			_ => match side {
+				Side::Left => "Left".to_string(),
+				Side::Right => "Right".to_string(),
+				Side::Top => "Top".to_string(),
+				Side::Bottom => "Bottom".to_string(),
+			},
+		}
+	}
+
+	fn refresh(&mut self) {
+		let simulation = match self.spectator.simulation() {
+			Some(simulation) => simulation,
+			None => {
+				self.score_text.set_content("");
+				self.status_text.set_content("Waiting for the match...");
+				return;
+			}
+		};
+
+		let score = simulation.score;
+		let (left, right) = (self.name(Side::Left), self.name(Side::Right));
+		self.score_text.set_content(format!("{}  {} - {}  {}", left, score.left, score.right, right));
+
+		let delay = self.spectator.delay().map_or(0.0, |delay| delay.as_secs_f32());
+		self.status_text.set_content(format!("Spectating, {:.1} s behind", delay));
+
+		let message = match (simulation.rules.format, simulation.result) {
+			(Format::FourWay, Some(result)) => format!("{} wins", self.name(result.winner)),
+			(_, Some(result)) => format!("{} wins {} - {}", self.name(result.winner), result.score.left, result.score.right),
+			(_, None) => String::new(),
+		};
+		self.message_text.set_content(message);
+	}
+}
+
+impl Scene for SpectateScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		let over = self.error.is_some();
+		if is_back_pressed(ctx, shared) || (over && shared.bindings.is_pressed(ctx, Action::Confirm)) {
+			self.spectator.leave();
+			return Ok(Transition::Pop);
+		}
+
+		if self.error.is_none() {
+			match self.watch(ctx) {
+				Ok(()) => self.refresh(),
+				Err(err) => {
+					self.message_text.set_content(format!("Stopped watching: {}\nPress confirm to leave", err));
+					self.error = Some(err);
+				}
+			}
+		}
+
+		Ok(Transition::None)
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		if let Some(current) = self.spectator.simulation() {
+			match current.rules.format {
+				Format::Duel | Format::Doubles => draw_centred(ctx, shared, &mut self.score_text, 8.0),
+				Format::FourWay => draw_lives(ctx, &mut self.lives_text, current),
+			}
+
+			let assets = &shared.assets;
+			let textures = [&assets.player1_texture, &assets.player2_texture];
+			let previous = self.previous.as_ref().filter(|previous| previous.tick < current.tick).unwrap_or(current);
+			draw_simulation(ctx, shared, textures, previous, current, self.timestep.alpha());
+		}
+
+		draw_centred(ctx, shared, &mut self.message_text, 180.0);
+		self.status_text.draw(ctx, Vec2::new(8.0, shared.config.arena_height - 24.0));
+		Ok(())
+	}
+}
@@ -0,0 +1,41 @@
+use tetra::graphics::text::Text;
+use tetra::Context;
+
+use super::menu::is_back_pressed;
+use super::{draw_centred, LanScene, Menu, ModeSelectScene, OptionsScene, Scene, Shared, Transition};
+
+pub struct TitleScene {
+	title: Text,
+	menu: Menu,
+}
+
+impl TitleScene {
+	pub fn new(shared: &Shared) -> TitleScene {
+		TitleScene {
+			title: Text::new("mfight-ng", shared.assets.font.clone()),
+			menu: Menu::new(&shared.assets.small_font, &["Play", "LAN games", "Options", "Quit"]),
+		}
+	}
+}
+
+impl Scene for TitleScene {
+	fn update(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result<Transition> {
+		if is_back_pressed(ctx, shared) {
+			return Ok(Transition::Quit);
+		}
+
+		Ok(match self.menu.update(ctx, shared) {
+			Some(0) => Transition::Push(Box::new(ModeSelectScene::new(shared))),
+			Some(1) => Transition::Push(Box::new(LanScene::new(shared))),
+			Some(2) => Transition::Push(Box::new(OptionsScene::new(shared))),
+			Some(_) => Transition::Quit,
+			None => Transition::None,
+		})
+	}
+
+	fn draw(&mut self, ctx: &mut Context, shared: &mut Shared) -> tetra::Result {
+		draw_centred(ctx, shared, &mut self.title, 100.0);
+		self.menu.draw(ctx, shared, 220.0);
+		Ok(())
+	}
+}
@@ -0,0 +1,116 @@
+//! A run of matches between the same players, e.g. rematches without restarting.
+
+use crate::config::Config;
+use crate::sim::{Format, MatchResult, MatchRules, PaddleId, Side, Simulation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+	One,
+	Two,
+	/// Only in four-way and doubles matches. In doubles, players three and four are the
+	/// forwards of the teams players one and two defend for.
+	Three,
+	Four,
+}
+
+impl Player {
+	pub const ALL: [Player; 4] = [Player::One, Player::Two, Player::Three, Player::Four];
+
+	pub fn index(self) -> usize {
+		match self {
+			Player::One => 0,
+			Player::Two => 1,
+			Player::Three => 2,
+			Player::Four => 3,
+		}
+	}
+}
+
+/// Statistics accumulated over every finished match of a session, indexed by `Player::index`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+	pub matches: u32,
+	pub wins: [u32; 4],
+	pub points: [u32; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+	pub config: Config,
+	pub rules: MatchRules,
+	/// Seed of the first match; each later one gets the next value.
+	pub seed: u64,
+	pub stats: SessionStats,
+	/// Whether player one is playing on the right, and player two on the left.
+	swapped: bool,
+}
+
+impl Session {
+	pub fn new(config: Config, rules: MatchRules, seed: u64) -> Session {
+		Session {
+			config,
+			rules,
+			seed,
+			stats: SessionStats::default(),
+			swapped: false,
+		}
+	}
+
+	/// Everyone playing in the session's matches.
+	pub fn players(&self) -> &'static [Player] {
+		&Player::ALL[..self.rules.format.players()]
+	}
+
+	pub fn side_of(&self, player: Player) -> Side {
+		self.paddle_of(player).side
+	}
+
+	pub fn paddle_of(&self, player: Player) -> PaddleId {
+		let doubles = self.rules.format == Format::Doubles;
+		match (player, self.swapped) {
+			(Player::One, false) | (Player::Two, true) => PaddleId::new(Side::Left, 0),
+			(Player::One, true) | (Player::Two, false) => PaddleId::new(Side::Right, 0),
+			(Player::Three, _) if doubles => PaddleId::new(self.side_of(Player::One), 1),
+			(Player::Four, _) if doubles => PaddleId::new(self.side_of(Player::Two), 1),
+			(Player::Three, _) => PaddleId::new(Side::Top, 0),
+			(Player::Four, _) => PaddleId::new(Side::Bottom, 0),
+		}
+	}
+
+	/// The player on `side`, or in doubles the one defending it.
+	pub fn player_on(&self, side: Side) -> Player {
+		match side {
+			Side::Top => Player::Three,
+			Side::Bottom => Player::Four,
+			_ if self.side_of(Player::One) == side => Player::One,
+			_ => Player::Two,
+		}
+	}
+
+	pub fn swap_sides(&mut self) {
+		self.swapped = !self.swapped;
+	}
+
+	/// A fresh match in the starting layout. Restarting a match before it finishes replays
+	/// the same seed; rematches get a new one.
+	pub fn new_match(&self) -> Simulation {
+		Simulation::with_config(self.config, self.rules, self.match_seed())
+	}
+
+	/// Seed of the match `new_match` starts.
+	pub fn match_seed(&self) -> u64 {
+		self.seed.wrapping_add(u64::from(self.stats.matches))
+	}
+
+	pub fn record(&mut self, result: &MatchResult) {
+		self.stats.matches += 1;
+		// Both players of a doubles team share its win and its points.
+		for &player in self.players() {
+			let side = self.side_of(player);
+			if side == result.winner {
+				self.stats.wins[player.index()] += 1;
+			}
+			self.stats.points[player.index()] += result.score.get(side);
+		}
+	}
+}
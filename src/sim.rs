@@ -0,0 +1,976 @@
+//! Headless simulation of a match: paddles, ball and the rules that move them.
+
+mod collision;
+mod rules;
+
+use vek::{Aabr, Vec2};
+
+use crate::config::Config;
+use crate::rng::Rng;
+
+use self::collision::{penetration, sweep, Hit};
+
+pub use self::rules::{Format, MatchResult, MatchRules, MultiBall, RallyEnd, Score, ServeRule, Spawn, MAX_BALLS};
+
+/// Gap between each paddle and its goal line.
+pub const PADDLE_MARGIN: f32 = 16.0;
+
+/// How far up the arena the forwards of a doubles match play, as a fraction of its width
+/// from their goal line.
+pub const FORWARD_DEPTH: f32 = 0.3;
+/// Length of a forward, as a fraction of the configured paddle height. Shorter than the
+/// defenders, so the ball gets past them often enough to keep rallies short.
+pub const FORWARD_LENGTH: f32 = 0.5;
+
+/// Number of simulation ticks per second of game time.
+pub const TICK_RATE: u32 = 60;
+/// Length of one simulation tick, in seconds.
+pub const TIMESTEP: f32 = 1.0 / TICK_RATE as f32;
+
+/// Upper bound on the number of bounces the ball can make within one tick.
+const MAX_BOUNCES: usize = 4;
+
+/// Gap below which the ball still counts as touching a paddle.
+const CONTACT_SLOP: f32 = 0.01;
+
+/// Ticks the ball waits in the centre before each serve.
+pub const SERVE_DELAY: u32 = TICK_RATE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+	Left,
+	Right,
+	/// The top and bottom only have players in a four-way match.
+	Top,
+	Bottom,
+}
+
+impl Side {
+	pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+	/// The side across the arena.
+	pub fn opponent(self) -> Side {
+		match self {
+			Side::Left => Side::Right,
+			Side::Right => Side::Left,
+			Side::Top => Side::Bottom,
+			Side::Bottom => Side::Top,
+		}
+	}
+
+	/// Whether a paddle on this side lies along the top or bottom and moves sideways.
+	pub fn is_horizontal(self) -> bool {
+		matches!(self, Side::Top | Side::Bottom)
+	}
+
+	/// The side's name, as shown to players.
+	pub fn name(self) -> &'static str {
+		match self {
+			Side::Left => "left",
+			Side::Right => "right",
+			Side::Top => "top",
+			Side::Bottom => "bottom",
+		}
+	}
+
+	/// The next side round the arena, going clockwise.
+	fn clockwise(self) -> Side {
+		match self {
+			Side::Left => Side::Top,
+			Side::Top => Side::Right,
+			Side::Right => Side::Bottom,
+			Side::Bottom => Side::Left,
+		}
+	}
+}
+
+/// One paddle: the side it guards, and which of that side's paddles it is, counting out
+/// from the goal line. Only doubles have more than one paddle a side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaddleId {
+	pub side: Side,
+	pub index: usize,
+}
+
+impl PaddleId {
+	pub fn new(side: Side, index: usize) -> PaddleId {
+		PaddleId { side, index }
+	}
+}
+
+/// What one paddle wants to do during a single tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PaddleInput {
+	/// Movement along the paddle's side in `-1.0..=1.0`. Negative moves the paddle up, or
+	/// left for a paddle on the top or bottom.
+	pub movement: f32,
+}
+
+impl PaddleInput {
+	pub fn new(movement: f32) -> PaddleInput {
+		PaddleInput { movement }
+	}
+}
+
+/// Inputs of every paddle for a single tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TickInput {
+	pub player1: PaddleInput,
+	pub player2: PaddleInput,
+	/// The top and bottom players' in a four-way match, or the left and right forwards'
+	/// in doubles. Duels ignore them.
+	pub player3: PaddleInput,
+	pub player4: PaddleInput,
+}
+
+impl TickInput {
+	pub fn get(&self, side: Side) -> PaddleInput {
+		match side {
+			Side::Left => self.player1,
+			Side::Right => self.player2,
+			Side::Top => self.player3,
+			Side::Bottom => self.player4,
+		}
+	}
+
+	pub fn set(&mut self, side: Side, input: PaddleInput) {
+		match side {
+			Side::Left => self.player1 = input,
+			Side::Right => self.player2 = input,
+			Side::Top => self.player3 = input,
+			Side::Bottom => self.player4 = input,
+		}
+	}
+
+	/// The input for `paddle`, which is its side's unless it is a forward.
+	pub fn get_paddle(&self, paddle: PaddleId) -> PaddleInput {
+		match (paddle.side, paddle.index) {
+			(side, 0) => self.get(side),
+			(Side::Left, _) => self.player3,
+			(Side::Right, _) => self.player4,
+			_ => PaddleInput::default(),
+		}
+	}
+
+	pub fn set_paddle(&mut self, paddle: PaddleId, input: PaddleInput) {
+		match (paddle.side, paddle.index) {
+			(side, 0) => self.set(side, input),
+			(Side::Left, _) => self.player3 = input,
+			(Side::Right, _) => self.player4 = input,
+			_ => {}
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+	pub position: Vec2<f32>,
+	pub velocity: Vec2<f32>,
+	pub size: Vec2<f32>,
+}
+
+impl Entity {
+	pub fn new(position: Vec2<f32>, velocity: Vec2<f32>, size: Vec2<f32>) -> Entity {
+		Entity { position, velocity, size }
+	}
+
+	/// Keeps the entity between the top and bottom of an arena `arena_height` tall.
+	pub fn fix_position(&mut self, arena_height: f32) {
+		self.clamp_y(0.0, arena_height);
+	}
+
+	/// Keeps the entity between the heights `min` and `max`.
+	pub fn clamp_y(&mut self, min: f32, max: f32) {
+		let max_y = max - self.height();
+		if self.position.y > max_y {
+			self.position.y = max_y;
+		} else if self.position.y < min {
+			self.position.y = min;
+		}
+	}
+
+	/// Keeps the entity between `min` and `max` along the X axis, as paddles on the top and
+	/// bottom are.
+	pub fn clamp_x(&mut self, min: f32, max: f32) {
+		let max_x = max - self.width();
+		if self.position.x > max_x {
+			self.position.x = max_x;
+		} else if self.position.x < min {
+			self.position.x = min;
+		}
+	}
+
+	pub fn width(&self) -> f32 {
+		self.size.x
+	}
+
+	pub fn height(&self) -> f32 {
+		self.size.y
+	}
+
+	pub fn bounds(&self) -> Aabr<f32> {
+		Aabr {
+			min: self.position,
+			max: self.position + self.size,
+		}
+	}
+
+	pub fn centre(&self) -> Vec2<f32> {
+		Vec2::new(
+			self.position.x + self.width()  / 2.0,
+			self.position.y + self.height() / 2.0,
+		)
+	}
+}
+
+/// A ball in play, and what it has touched since it was served.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ball {
+	pub entity: Entity,
+	/// The paddle the ball is currently touching. It cannot hit the ball again until they
+	/// separate, so one contact never counts as several hits.
+	pub contact: Option<PaddleId>,
+	/// In a four-way match, the paddle that last hit the ball since it was served.
+	pub last_hit: Option<Side>,
+}
+
+impl Ball {
+	pub fn new(entity: Entity) -> Ball {
+		Ball { entity, contact: None, last_hit: None }
+	}
+
+	/// Reflects the ball off a wall facing along `normal`, unless it is already heading
+	/// back into the arena.
+	fn hit_wall(&mut self, normal: Vec2<f32>) {
+		if self.entity.velocity.x * normal.x < 0.0 {
+			self.entity.velocity.x = -self.entity.velocity.x;
+		}
+		if self.entity.velocity.y * normal.y < 0.0 {
+			self.entity.velocity.y = -self.entity.velocity.y;
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+	pub config: Config,
+	/// Seed for everything random in the match, so the same seed and inputs always play
+	/// out the same way.
+	pub seed: u64,
+	pub rng: Rng,
+	/// Every side's paddles, indexed by `Side` and nearest the goal line first. Sides
+	/// nobody plays on have none.
+	pub paddles: [Vec<Entity>; 4],
+	/// The balls in play, oldest first. There is always at least one, and only ever one
+	/// unless the rules allow multi-ball.
+	pub balls: Vec<Ball>,
+	/// Number of ticks simulated so far.
+	pub tick: u64,
+	pub rules: MatchRules,
+	pub score: Score,
+	/// Set once the match is over, after which the simulation no longer advances.
+	pub result: Option<MatchResult>,
+	/// The side the next serve goes toward.
+	pub serve_toward: Side,
+	/// Ticks left before the ball is served.
+	pub serve_delay: u32,
+	/// Goals let in by each side of a four-way match, indexed by `Side`.
+	pub conceded: [u32; 4],
+	/// Ticks or paddle hits, whichever `Spawn` counts, toward the next ball joining a
+	/// multi-ball rally.
+	pub spawn_progress: u32,
+}
+
+impl Simulation {
+	pub fn new() -> Simulation {
+		Simulation::with_rules(MatchRules::default())
+	}
+
+	pub fn with_rules(rules: MatchRules) -> Simulation {
+		Simulation::with_config(Config::default(), rules, 0)
+	}
+
+	/// A match tuned by `config`, which is expected to have been validated.
+	pub fn with_config(config: Config, rules: MatchRules, seed: u64) -> Simulation {
+		let paddles = Side::ALL.map(|side| {
+			if rules.format.sides().contains(&side) {
+				starting_paddles(&config, rules.format, side)
+			} else {
+				Vec::new()
+			}
+		});
+
+		let mut simulation = Simulation {
+			config,
+			seed,
+			rng: Rng::new(seed),
+			paddles,
+			balls: Vec::new(),
+			tick: 0,
+			rules,
+			score: Score::default(),
+			result: None,
+			serve_toward: Side::Left,
+			serve_delay: 0,
+			conceded: [0; 4],
+			spawn_progress: 0,
+		};
+
+		// The opening serve goes to anyone and, unlike later ones, right away.
+		simulation.serve_toward = simulation.random_side();
+		let velocity = simulation.velocity_toward(simulation.serve_toward);
+		let ball = simulation.centred_ball(velocity);
+		simulation.balls.push(ball);
+		simulation
+	}
+
+	/// A fingerprint of everything that decides how the match plays on, for checking that
+	/// two simulations fed the same inputs (a replay, a remote peer) still agree.
+	pub fn state_hash(&self) -> u64 {
+		let mut hash = StateHash::new();
+		hash.write(self.tick);
+		for entity in &[self.paddle(Side::Left), self.paddle(Side::Right), self.ball()] {
+			hash.write_motion(entity);
+		}
+		hash.write(u64::from(self.score.left));
+		hash.write(u64::from(self.score.right));
+		hash.write(self.result.map_or(0, |result| result.winner as u64 + 1));
+		hash.write(self.serve_toward as u64);
+		hash.write(u64::from(self.serve_delay));
+		hash.write(self.balls[0].contact.map_or(0, |paddle| paddle.side as u64 + 1));
+		// The generator's state is private, but its next output depends on all of it.
+		hash.write(self.rng.clone().next_u64());
+		// Duels hash just what they did before there were other formats, so replays
+		// recorded back then still verify.
+		match self.rules.format {
+			Format::Duel => {}
+			Format::FourWay => {
+				for &side in &[Side::Top, Side::Bottom] {
+					hash.write_motion(self.paddle(side));
+				}
+				hash.write(u64::from(self.score.top));
+				hash.write(u64::from(self.score.bottom));
+				for &conceded in &self.conceded {
+					hash.write(u64::from(conceded));
+				}
+				hash.write(self.balls[0].last_hit.map_or(0, |side| side as u64 + 1));
+			}
+			Format::Doubles => {
+				for &side in &[Side::Left, Side::Right] {
+					for forward in &self.paddles[side as usize][1..] {
+						hash.write_motion(forward);
+					}
+				}
+				hash.write(self.balls[0].contact.map_or(0, |paddle| paddle.index as u64));
+			}
+		}
+		// Likewise the other balls, only when there can be any.
+		if self.rules.multiball.is_some() {
+			hash.write(self.balls.len() as u64);
+			for ball in &self.balls[1..] {
+				hash.write_motion(&ball.entity);
+				hash.write(ball.contact.map_or(0, |paddle| (paddle.side as u64 + 1) | (paddle.index as u64) << 8));
+				hash.write(ball.last_hit.map_or(0, |side| side as u64 + 1));
+			}
+			hash.write(u64::from(self.spawn_progress));
+		}
+		hash.finish()
+	}
+
+	/// The oldest ball in play, which is the only one outside multi-ball.
+	pub fn ball(&self) -> &Entity {
+		&self.balls[0].entity
+	}
+
+	pub fn ball_mut(&mut self) -> &mut Entity {
+		&mut self.balls[0].entity
+	}
+
+	/// The paddle of `side` nearest its goal line, which is its only one outside doubles.
+	/// Panics if nobody plays on `side`.
+	pub fn paddle(&self, side: Side) -> &Entity {
+		&self.paddles[side as usize][0]
+	}
+
+	pub fn paddle_mut(&mut self, side: Side) -> &mut Entity {
+		&mut self.paddles[side as usize][0]
+	}
+
+	pub fn paddle_at(&self, paddle: PaddleId) -> &Entity {
+		&self.paddles[paddle.side as usize][paddle.index]
+	}
+
+	pub fn paddle_at_mut(&mut self, paddle: PaddleId) -> &mut Entity {
+		&mut self.paddles[paddle.side as usize][paddle.index]
+	}
+
+	/// Every paddle of the sides still in the match.
+	pub fn paddles_in_play(&self) -> impl Iterator<Item = PaddleId> + '_ {
+		self.sides_in_play().flat_map(move |side| {
+			(0..self.paddles[side as usize].len()).map(move |index| PaddleId::new(side, index))
+		})
+	}
+
+	/// Whether `side` has a player still in the match.
+	pub fn in_play(&self, side: Side) -> bool {
+		match self.rules.format {
+			Format::Duel | Format::Doubles => !side.is_horizontal(),
+			Format::FourWay => self.conceded[side as usize] < self.rules.lives,
+		}
+	}
+
+	/// The sides with a player still in the match, in the order of `Side::ALL`.
+	pub fn sides_in_play(&self) -> impl Iterator<Item = Side> + '_ {
+		Side::ALL.iter().copied().filter(move |&side| self.in_play(side))
+	}
+
+	/// Goals `side` can still let in before being knocked out of a four-way match.
+	pub fn lives(&self, side: Side) -> u32 {
+		self.rules.lives.saturating_sub(self.conceded[side as usize])
+	}
+
+	/// Advances the match by one tick of `TIMESTEP` seconds. Does nothing once the match is over.
+	pub fn step(&mut self, input: &TickInput) {
+		if self.result.is_some() {
+			return;
+		}
+		self.tick += 1;
+
+		let (config, format) = (self.config, self.rules.format);
+		let paddles: Vec<_> = self.paddles_in_play().collect();
+		for &paddle in &paddles {
+			move_paddle(self.paddle_at_mut(paddle), paddle.side, input.get_paddle(paddle), &config, format);
+		}
+
+		if self.serve_delay > 0 {
+			self.serve_delay -= 1;
+			return;
+		}
+
+		for ball in 0..self.balls.len() {
+			// A paddle may have moved into the ball, so push it back out before it travels.
+			for &paddle in &paddles {
+				self.resolve_overlap(ball, paddle);
+			}
+			self.keep_ball_inside(ball);
+
+			self.move_ball(ball);
+			self.keep_ball_inside(ball);
+			self.update_contact(ball);
+		}
+
+		let mut ball = 0;
+		while ball < self.balls.len() {
+			match self.goal(ball) {
+				Some(side) => {
+					if self.lose_ball(ball, side) {
+						return;
+					}
+				}
+				None => ball += 1,
+			}
+		}
+
+		if let Some(multiball) = self.rules.multiball {
+			let every = match multiball.spawn {
+				Spawn::Timer(ticks) => {
+					self.spawn_progress += 1;
+					ticks
+				}
+				Spawn::Hits(hits) => hits,
+			};
+			if self.spawn_progress >= every {
+				self.spawn_progress = 0;
+				if (self.balls.len() as u32) < multiball.max_balls {
+					self.spawn_ball();
+				}
+			}
+		}
+	}
+
+	/// The side whose goal `ball` has gone out through, if any. In a four-way match it
+	/// has to go all the way through, as the goals meet at the corners.
+	fn goal(&self, ball: usize) -> Option<Side> {
+		let entity = &self.balls[ball].entity;
+		if self.rules.format != Format::FourWay {
+			return if entity.position.x < 0.0 {
+				Some(Side::Left)
+			} else if entity.position.x > self.config.arena_width {
+				Some(Side::Right)
+			} else {
+				None
+			};
+		}
+
+		let bounds = entity.bounds();
+		if bounds.max.x < 0.0 {
+			Some(Side::Left)
+		} else if bounds.min.x > self.config.arena_width {
+			Some(Side::Right)
+		} else if bounds.max.y < 0.0 {
+			Some(Side::Top)
+		} else if bounds.min.y > self.config.arena_height {
+			Some(Side::Bottom)
+		} else {
+			None
+		}
+	}
+
+	/// Scores `ball` going out through the goal of `side`, then takes it out of play.
+	/// Returns whether that ended the rally, either by finishing the match or by the next
+	/// one being served.
+	///
+	/// In a duel or doubles match the other side wins the point. In a four-way match
+	/// `side` loses a life and whoever sent the ball there wins the point; letting in one
+	/// too many knocks a player out, and the last one left wins.
+	fn lose_ball(&mut self, ball: usize, side: Side) -> bool {
+		match self.rules.format {
+			Format::Duel | Format::Doubles => self.score.add_point(side.opponent()),
+			Format::FourWay => {
+				self.conceded[side as usize] += 1;
+				if let Some(scorer) = self.balls[ball].last_hit.filter(|&scorer| scorer != side) {
+					self.score.add_point(scorer);
+				}
+			}
+		}
+		if let Some(winner) = self.winner() {
+			// The ball stays where it went out, for showing the final moment.
+			self.result = Some(MatchResult { winner, score: self.score });
+			return true;
+		}
+
+		let rally_over = self.balls.len() == 1
+			|| !matches!(self.rules.multiball, Some(MultiBall { rally_end: RallyEnd::LastOut, .. }));
+		if !rally_over {
+			self.balls.remove(ball);
+			return false;
+		}
+
+		self.serve_toward = match (self.rules.format, self.rules.serve) {
+			(Format::FourWay, ServeRule::ToConceder) if self.in_play(side) => side,
+			(Format::FourWay, ServeRule::ToConceder) => self.next_in_play(side),
+			(Format::FourWay, ServeRule::Alternate) => self.next_in_play(self.serve_toward),
+			(_, ServeRule::ToConceder) => side,
+			(_, ServeRule::Alternate) => self.serve_toward.opponent(),
+		};
+		self.serve();
+		true
+	}
+
+	/// The side that has won the match as it stands, if it is over.
+	fn winner(&self) -> Option<Side> {
+		match self.rules.format {
+			Format::Duel | Format::Doubles => self.rules.winner(self.score),
+			Format::FourWay => {
+				let mut left = self.sides_in_play();
+				match (left.next(), left.next()) {
+					(Some(last), None) => Some(last),
+					_ => None,
+				}
+			}
+		}
+	}
+
+	/// The first side clockwise of `side` with a player still in the match, or `side` if
+	/// there is none.
+	fn next_in_play(&self, side: Side) -> Side {
+		let mut next = side.clockwise();
+		while !self.in_play(next) && next != side {
+			next = next.clockwise();
+		}
+		next
+	}
+
+	/// Clears away every ball and puts one back in the centre, ready to head toward
+	/// `serve_toward` once the serve delay runs out.
+	pub fn serve(&mut self) {
+		let velocity = self.velocity_toward(self.serve_toward);
+		self.balls = vec![self.centred_ball(velocity)];
+		self.serve_delay = SERVE_DELAY;
+		self.spawn_progress = 0;
+	}
+
+	/// Sends another ball into a multi-ball rally from the centre, toward a side picked at
+	/// random.
+	fn spawn_ball(&mut self) {
+		let toward = self.random_side();
+		let velocity = self.velocity_toward(toward);
+		let ball = self.centred_ball(velocity);
+		self.balls.push(ball);
+	}
+
+	fn centred_ball(&self, velocity: Vec2<f32>) -> Ball {
+		let size = self.config.ball_size;
+		let position = Vec2::new(
+			(self.config.arena_width -  size) / 2.0,
+			(self.config.arena_height - size) / 2.0,
+		);
+		Ball::new(Entity::new(position, velocity, Vec2::broadcast(size)))
+	}
+
+	/// One of the sides still in the match, picked at random, or the side being served
+	/// toward if there is none.
+	fn random_side(&mut self) -> Side {
+		match self.rules.format {
+			Format::Duel | Format::Doubles => if self.rng.coin() { Side::Left } else { Side::Right },
+			Format::FourWay => {
+				let sides: Vec<_> = self.sides_in_play().collect();
+				if sides.is_empty() {
+					return self.serve_toward;
+				}
+				sides[(self.rng.next_u64() % sides.len() as u64) as usize]
+			}
+		}
+	}
+
+	/// A velocity toward `side` at a random angle within the configured range, randomly
+	/// to either side of straight at it.
+	fn velocity_toward(&mut self, side: Side) -> Vec2<f32> {
+		let direction = match side {
+			Side::Left | Side::Top => -1.0,
+			Side::Right | Side::Bottom => 1.0,
+		};
+		let degrees = self.rng.range(self.config.min_serve_angle, self.config.max_serve_angle);
+		let across = if self.rng.coin() { 1.0 } else { -1.0 };
+
+		let (sin, cos) = sin_cos(degrees.to_radians());
+		let velocity = if side.is_horizontal() {
+			Vec2::new(across * sin, direction * cos)
+		} else {
+			Vec2::new(direction * cos, across * sin)
+		};
+		velocity * self.config.ball_speed
+	}
+
+	/// Moves `ball` through the arena for one tick, bouncing off every paddle and wall it
+	/// touches on the way instead of only checking where it ends up.
+	fn move_ball(&mut self, ball: usize) {
+		let mut remaining = 1.0;
+		for _ in 0..MAX_BOUNCES {
+			let motion = self.balls[ball].entity.velocity * TIMESTEP * remaining;
+			let hit = self.first_hit(ball, motion);
+			match hit {
+				Some((obstacle, hit)) => {
+					self.balls[ball].entity.position += motion * hit.time;
+					match obstacle {
+						Obstacle::Paddle(paddle) => self.hit_paddle(ball, paddle, hit.normal),
+						Obstacle::Wall => self.balls[ball].hit_wall(hit.normal),
+					}
+					remaining *= 1.0 - hit.time;
+				}
+				None => {
+					self.balls[ball].entity.position += motion;
+					return;
+				}
+			}
+		}
+	}
+
+	fn first_hit(&self, ball: usize, motion: Vec2<f32>) -> Option<(Obstacle, Hit)> {
+		let bounds = self.balls[ball].entity.bounds();
+		let contact = self.balls[ball].contact.map(Obstacle::Paddle);
+		let mut obstacles: Vec<_> = self.paddles_in_play()
+			.filter(|&paddle| self.blocks(ball, paddle))
+			.map(|paddle| (Obstacle::Paddle(paddle), self.paddle_at(paddle).bounds()))
+			.collect();
+		obstacles.extend(self.walls().into_iter().map(|wall| (Obstacle::Wall, wall)));
+
+		obstacles.iter()
+			.filter(|&&(obstacle, _)| Some(obstacle) != contact)
+			.filter_map(|&(obstacle, wall)| sweep(bounds, motion, wall).map(|hit| (obstacle, hit)))
+			.min_by(|(_, a), (_, b)| a.time.total_cmp(&b.time))
+	}
+
+	/// Whether `ball` can hit `paddle` now. A forward only stops a ball heading for its
+	/// own goal, so the shots of its side's defender pass through it from behind.
+	fn blocks(&self, ball: usize, paddle: PaddleId) -> bool {
+		let velocity = self.balls[ball].entity.velocity;
+		let heading = match paddle.side {
+			Side::Left => -velocity.x,
+			Side::Right => velocity.x,
+			Side::Top => -velocity.y,
+			Side::Bottom => velocity.y,
+		};
+		paddle.index == 0 || heading > 0.0
+	}
+
+	/// Everything the ball bounces off besides the paddles. A duel or doubles match is
+	/// walled at the top and bottom; a four-way match only at the corners, and across the
+	/// goals of players who have been knocked out.
+	pub fn walls(&self) -> Vec<Aabr<f32>> {
+		match self.rules.format {
+			Format::Duel | Format::Doubles => vec![side_wall(&self.config, Side::Top), side_wall(&self.config, Side::Bottom)],
+			Format::FourWay => {
+				let mut walls = corner_walls(&self.config).to_vec();
+				walls.extend(Side::ALL.iter()
+					.filter(|&&side| !self.in_play(side))
+					.map(|&side| closed_goal(&self.config, side)));
+				walls
+			}
+		}
+	}
+
+	fn resolve_overlap(&mut self, ball: usize, paddle: PaddleId) {
+		if !self.blocks(ball, paddle) {
+			return;
+		}
+		if let Some((normal, depth)) = penetration(self.balls[ball].entity.bounds(), self.paddle_at(paddle).bounds()) {
+			self.balls[ball].entity.position += normal * depth;
+			self.hit_paddle(ball, paddle, normal);
+		}
+	}
+
+	/// Responds to `ball` touching `id` on the face with the given `normal`.
+	fn hit_paddle(&mut self, ball: usize, id: PaddleId, normal: Vec2<f32>) {
+		if self.balls[ball].contact == Some(id) {
+			return;
+		}
+		self.balls[ball].contact = Some(id);
+		let side = id.side;
+		if self.rules.format == Format::FourWay {
+			self.balls[ball].last_hit = Some(side);
+		}
+
+		let paddle = self.paddle_at(id);
+		let paddle_velocity = paddle.velocity;
+		let (paddle_centre, paddle_size) = (paddle.centre(), paddle.size);
+		let relative = self.balls[ball].entity.velocity - paddle_velocity;
+		if relative.dot(normal) >= 0.0 {
+			// Already moving away from the paddle, so the contact only grazes it.
+			return;
+		}
+		if let Some(MultiBall { spawn: Spawn::Hits(_), .. }) = self.rules.multiball {
+			self.spawn_progress += 1;
+		}
+
+		let config = self.config;
+		let ball = &mut self.balls[ball].entity;
+		if side.is_horizontal() && normal.x == 0.0 {
+			// The face of a paddle on the top or bottom: the same as below, turned sideways.
+			let offset = (paddle_centre.x - ball.centre().x) / paddle_size.x;
+			ball.velocity.y = -(ball.velocity.y + (config.ball_acc * ball.velocity.y.signum()));
+			ball.velocity.x += config.paddle_spin * -offset;
+		} else if !side.is_horizontal() && normal.y == 0.0 {
+			// Calculate the offset between the paddle and the ball, as a number between
+			// -1.0 and 1.0.
+			let offset = (paddle_centre.y - ball.centre().y) / paddle_size.y;
+
+			// Increase the ball's velocity, then flip it.
+			ball.velocity.x = -(ball.velocity.x + (config.ball_acc * ball.velocity.x.signum()));
+
+			// Apply the spin to the ball.
+			ball.velocity.y += config.paddle_spin * -offset;
+
+			if id.index > 0 {
+				level_off(ball, &config);
+			}
+		} else {
+			// The ends or a corner of the paddle: reflect off it the way a moving wall
+			// would, which also carries the paddle's own speed into the ball.
+			let reflected = relative - normal * (2.0 * relative.dot(normal));
+			ball.velocity = reflected + paddle_velocity;
+		}
+
+		let speed = ball.velocity.magnitude();
+		if speed > config.max_ball_speed {
+			ball.velocity *= config.max_ball_speed / speed;
+		}
+	}
+
+	/// Moves `ball` back out of the walls if anything pushed it into one.
+	fn keep_ball_inside(&mut self, ball: usize) {
+		if self.rules.format == Format::FourWay {
+			for wall in self.walls() {
+				let ball = &mut self.balls[ball];
+				if let Some((normal, depth)) = penetration(ball.entity.bounds(), wall) {
+					ball.entity.position += normal * depth;
+					ball.hit_wall(normal);
+				}
+			}
+			return;
+		}
+
+		let ball = &mut self.balls[ball];
+		let max_y = self.config.arena_height - ball.entity.height();
+		if ball.entity.position.y < 0.0 {
+			ball.entity.position.y = 0.0;
+			ball.hit_wall(Vec2::new(0.0, 1.0));
+		} else if ball.entity.position.y > max_y {
+			ball.entity.position.y = max_y;
+			ball.hit_wall(Vec2::new(0.0, -1.0));
+		}
+	}
+
+	/// Forgets the current contact of `ball` once it has left the paddle.
+	fn update_contact(&mut self, ball: usize) {
+		if let Some(paddle) = self.balls[ball].contact {
+			let bounds = self.balls[ball].entity.bounds();
+			let paddle = self.paddle_at(paddle).bounds();
+			let touching = bounds.min.x <= paddle.max.x + CONTACT_SLOP
+				&& bounds.max.x >= paddle.min.x - CONTACT_SLOP
+				&& bounds.min.y <= paddle.max.y + CONTACT_SLOP
+				&& bounds.max.y >= paddle.min.y - CONTACT_SLOP;
+			if !touching {
+				self.balls[ball].contact = None;
+			}
+		}
+	}
+}
+
+/// 64-bit FNV-1a, fed whole words.
+struct StateHash(u64);
+
+impl StateHash {
+	fn new() -> StateHash {
+		StateHash(0xCBF2_9CE4_8422_2325)
+	}
+
+	fn write(&mut self, value: u64) {
+		for byte in &value.to_le_bytes() {
+			self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01B3);
+		}
+	}
+
+	fn write_motion(&mut self, entity: &Entity) {
+		for value in &[entity.position.x, entity.position.y, entity.velocity.x, entity.velocity.y] {
+			self.write(u64::from(value.to_bits()));
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Obstacle {
+	Paddle(PaddleId),
+	Wall,
+}
+
+/// The paddles `side` starts a match with: one in the middle of its goal line, and in
+/// doubles a shorter forward further up the arena.
+fn starting_paddles(config: &Config, format: Format, side: Side) -> Vec<Entity> {
+	let upright = Vec2::new(config.paddle_width, config.paddle_height);
+	// Lying flat, so as long across as the others are tall.
+	let flat = Vec2::new(config.paddle_height, config.paddle_width);
+	let middle_x = (config.arena_width - config.paddle_height) / 2.0;
+	let middle_y = (config.arena_height - config.paddle_height) / 2.0;
+	let (position, size) = match side {
+		Side::Left => (Vec2::new(PADDLE_MARGIN, middle_y), upright),
+		Side::Right => (Vec2::new(config.arena_width - config.paddle_width - PADDLE_MARGIN, middle_y), upright),
+		Side::Top => (Vec2::new(middle_x, PADDLE_MARGIN), flat),
+		Side::Bottom => (Vec2::new(middle_x, config.arena_height - config.paddle_width - PADDLE_MARGIN), flat),
+	};
+	let mut paddles = vec![Entity::new(position, Vec2::zero(), size)];
+
+	if format == Format::Doubles {
+		let depth = config.arena_width * FORWARD_DEPTH;
+		let x = match side {
+			Side::Left => depth,
+			_ => config.arena_width - depth - config.paddle_width,
+		};
+		let length = config.paddle_height * FORWARD_LENGTH;
+		let y = (config.arena_height - length) / 2.0;
+		paddles.push(Entity::new(Vec2::new(x, y), Vec2::zero(), Vec2::new(config.paddle_width, length)));
+	}
+	paddles
+}
+
+// The walls reach well past the goal lines either side of them, so a ball leaving the
+// arena near a corner still bounces off them.
+fn side_wall(config: &Config, side: Side) -> Aabr<f32> {
+	let (width, height) = (config.arena_width, config.arena_height);
+	let (min, max) = match side {
+		Side::Left => (Vec2::new(-width, -height), Vec2::new(0.0, 2.0 * height)),
+		Side::Right => (Vec2::new(width, -height), Vec2::new(2.0 * width, 2.0 * height)),
+		Side::Top => (Vec2::new(-width, -height), Vec2::new(2.0 * width, 0.0)),
+		Side::Bottom => (Vec2::new(-width, height), Vec2::new(2.0 * width, 2.0 * height)),
+	};
+	Aabr { min, max }
+}
+
+/// The wall across the goal of a player knocked out of a four-way match, reaching in to
+/// where their paddle was so it can be seen.
+fn closed_goal(config: &Config, side: Side) -> Aabr<f32> {
+	let mut wall = side_wall(config, side);
+	match side {
+		Side::Left => wall.max.x += PADDLE_MARGIN,
+		Side::Right => wall.min.x -= PADDLE_MARGIN,
+		Side::Top => wall.max.y += PADDLE_MARGIN,
+		Side::Bottom => wall.min.y -= PADDLE_MARGIN,
+	}
+	wall
+}
+
+/// How far the walls in the corners of a four-way arena reach along each side: just past
+/// the paddles either side, so each paddle has a goal of its own to guard.
+fn corner_size(config: &Config) -> f32 {
+	PADDLE_MARGIN + config.paddle_width
+}
+
+fn corner_walls(config: &Config) -> [Aabr<f32>; 4] {
+	let (width, height) = (config.arena_width, config.arena_height);
+	let corner = corner_size(config);
+	[
+		Aabr { min: Vec2::new(-width, -height), max: Vec2::new(corner, corner) },
+		Aabr { min: Vec2::new(width - corner, -height), max: Vec2::new(2.0 * width, corner) },
+		Aabr { min: Vec2::new(-width, height - corner), max: Vec2::new(corner, 2.0 * height) },
+		Aabr { min: Vec2::new(width - corner, height - corner), max: Vec2::new(2.0 * width, 2.0 * height) },
+	]
+}
+
+impl Default for Simulation {
+	fn default() -> Simulation {
+		Simulation::new()
+	}
+}
+
+/// Turns `ball` no steeper than a serve can be, keeping its speed. Forwards do this to
+/// the balls they hit, so two of them can't trade a ball bouncing up and down the arena
+/// for ever.
+fn level_off(ball: &mut Entity, config: &Config) {
+	let velocity = ball.velocity;
+	let speed = velocity.magnitude();
+	let (max_sin, _) = sin_cos(config.max_serve_angle.to_radians());
+	let max_y = speed * max_sin;
+	if velocity.y.abs() > max_y {
+		let y = max_y * velocity.y.signum();
+		ball.velocity = Vec2::new((speed * speed - y * y).sqrt() * velocity.x.signum(), y);
+	}
+}
+
+fn move_paddle(paddle: &mut Entity, side: Side, input: PaddleInput, config: &Config, format: Format) {
+	let movement = config.paddle_speed * TIMESTEP * input.movement.clamp(-1.0, 1.0);
+	if side.is_horizontal() {
+		let start = paddle.position.x;
+		paddle.position.x += movement;
+		paddle.clamp_x(corner_size(config), config.arena_width - corner_size(config));
+		paddle.velocity.x = (paddle.position.x - start) / TIMESTEP;
+		return;
+	}
+
+	let start = paddle.position.y;
+	paddle.position.y += movement;
+	match format {
+		Format::Duel | Format::Doubles => paddle.fix_position(config.arena_height),
+		Format::FourWay => paddle.clamp_y(corner_size(config), config.arena_height - corner_size(config)),
+	}
+	paddle.velocity.y = (paddle.position.y - start) / TIMESTEP;
+}
+
+/// Sine and cosine of `angle` (in radians, at most about 1.3) from their Taylor series.
+/// Only additions and multiplications are used, which give the same result everywhere,
+/// unlike the platform's maths library.
+fn sin_cos(angle: f32) -> (f32, f32) {
+	let square = angle * angle;
+	let mut sin = 0.0;
+	let mut cos = 0.0;
+	// Horner's method over the first terms of each series, highest power first.
+	for n in (0..7).rev() {
+		let k = 2.0 * n as f32;
+		sin = 1.0 - square * sin / ((k + 2.0) * (k + 3.0));
+		cos = 1.0 - square * cos / ((k + 1.0) * (k + 2.0));
+	}
+	(angle * sin, cos)
+}
@@ -0,0 +1,72 @@
+//! Swept collision between axis-aligned boxes.
+
+use vek::{Aabr, Vec2};
+
+/// How close two entry times must be for a hit to count as landing on a corner.
+const CORNER_EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+	/// Fraction of the motion travelled before contact, in `0.0..=1.0`.
+	pub time: f32,
+	/// Unit normal of the face that was hit, pointing out of the obstacle.
+	pub normal: Vec2<f32>,
+}
+
+/// Entry and exit times of a moving interval `[min, max]` against the static interval
+/// `[target_min, target_max]` along one axis, as fractions of `motion`.
+fn axis_times(min: f32, max: f32, motion: f32, target_min: f32, target_max: f32) -> Option<(f32, f32)> {
+	if motion == 0.0 {
+		if max > target_min && min < target_max {
+			Some((f32::NEG_INFINITY, f32::INFINITY))
+		} else {
+			None
+		}
+	} else if motion > 0.0 {
+		Some(((target_min - max) / motion, (target_max - min) / motion))
+	} else {
+		Some(((target_max - min) / motion, (target_min - max) / motion))
+	}
+}
+
+/// Sweeps `moving` along `motion` against the static `target`, returning the first contact
+/// within the motion. Boxes that already overlap at the start do not count as a hit.
+pub fn sweep(moving: Aabr<f32>, motion: Vec2<f32>, target: Aabr<f32>) -> Option<Hit> {
+	let (entry_x, exit_x) = axis_times(moving.min.x, moving.max.x, motion.x, target.min.x, target.max.x)?;
+	let (entry_y, exit_y) = axis_times(moving.min.y, moving.max.y, motion.y, target.min.y, target.max.y)?;
+
+	let entry = entry_x.max(entry_y);
+	let exit = exit_x.min(exit_y);
+	if entry >= exit || !(0.0..=1.0).contains(&entry) {
+		return None;
+	}
+
+	let normal = if (entry_x - entry_y).abs() <= CORNER_EPSILON {
+		Vec2::new(-motion.x.signum(), -motion.y.signum()).normalized()
+	} else if entry_x > entry_y {
+		Vec2::new(-motion.x.signum(), 0.0)
+	} else {
+		Vec2::new(0.0, -motion.y.signum())
+	};
+
+	Some(Hit { time: entry, normal })
+}
+
+/// The shortest way to push `moving` out of `target`, as a unit normal pointing out of
+/// `target` and the distance to move along it. Returns `None` if the boxes do not overlap.
+pub fn penetration(moving: Aabr<f32>, target: Aabr<f32>) -> Option<(Vec2<f32>, f32)> {
+	let candidates = [
+		(Vec2::new(-1.0, 0.0), moving.max.x - target.min.x),
+		(Vec2::new(1.0, 0.0), target.max.x - moving.min.x),
+		(Vec2::new(0.0, -1.0), moving.max.y - target.min.y),
+		(Vec2::new(0.0, 1.0), target.max.y - moving.min.y),
+	];
+
+	if candidates.iter().any(|&(_, depth)| depth <= 0.0) {
+		return None;
+	}
+
+	candidates.iter()
+		.copied()
+		.min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
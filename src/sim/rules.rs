@@ -0,0 +1,167 @@
+//! Scoring and the rules deciding who serves and when a match is over.
+
+use super::Side;
+
+/// Who the ball is served toward after a point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServeRule {
+	/// Toward the player who just conceded.
+	ToConceder,
+	/// Toward each player in turn, regardless of who scored.
+	Alternate,
+}
+
+/// Who plays a match, and so which walls of the arena are goals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// Two players, on the left and right. The top and bottom are walls.
+	Duel,
+	/// A player on every side, knocked out after letting in `MatchRules::lives` goals and
+	/// their goal walled off. The last one left wins.
+	FourWay,
+	/// Teams of two on the left and right, scored like a duel. Each team has a defender
+	/// near its goal line and a forward further up the arena.
+	Doubles,
+}
+
+impl Format {
+	/// The sides with a player at the start of a match.
+	pub fn sides(self) -> &'static [Side] {
+		match self {
+			Format::Duel | Format::Doubles => &[Side::Left, Side::Right],
+			Format::FourWay => &Side::ALL,
+		}
+	}
+
+	/// How many players a match has, each with a paddle of their own.
+	pub fn players(self) -> usize {
+		match self {
+			Format::Duel => 2,
+			Format::FourWay | Format::Doubles => 4,
+		}
+	}
+}
+
+/// What sets off another ball joining a multi-ball rally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spawn {
+	/// Every this many ticks of the rally.
+	Timer(u32),
+	/// Every this many times a paddle hits a ball during the rally.
+	Hits(u32),
+}
+
+/// When a multi-ball rally is over and the next one is served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RallyEnd {
+	/// As soon as any ball goes out. The rest are cleared away.
+	FirstOut,
+	/// Once the last ball is out, so every ball in play can still score.
+	LastOut,
+}
+
+/// Most balls a multi-ball match can have in play at once.
+pub const MAX_BALLS: u32 = 16;
+
+/// More than one ball in play at a time. Every ball scores for itself when it goes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiBall {
+	pub spawn: Spawn,
+	/// Most balls in play at once.
+	pub max_balls: u32,
+	pub rally_end: RallyEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchRules {
+	/// Points needed to win a duel or doubles match.
+	pub win_score: u32,
+	/// Lead the winner of a duel or doubles match must have over the other side, e.g. 2
+	/// for win-by-two.
+	pub win_by: u32,
+	pub serve: ServeRule,
+	pub format: Format,
+	/// Goals a player can let in during a four-way match before they are knocked out.
+	pub lives: u32,
+	/// Extra balls in each rally, or `None` for a single ball.
+	pub multiball: Option<MultiBall>,
+}
+
+impl Default for MatchRules {
+	fn default() -> MatchRules {
+		MatchRules {
+			win_score: 11,
+			win_by: 2,
+			serve: ServeRule::ToConceder,
+			format: Format::Duel,
+			lives: 3,
+			multiball: None,
+		}
+	}
+}
+
+impl MatchRules {
+	/// The winner of a duel or doubles match standing at `score`, if it is over.
+	pub fn winner(&self, score: Score) -> Option<Side> {
+		let wins = |points: u32, other: u32| points >= self.win_score && points >= other + self.win_by;
+		if wins(score.left, score.right) {
+			Some(Side::Left)
+		} else if wins(score.right, score.left) {
+			Some(Side::Right)
+		} else {
+			None
+		}
+	}
+
+	/// Checks the rules can be played, for ones read from a file or the network.
+	pub fn validate(&self) -> Result<(), String> {
+		if self.lives == 0 {
+			return Err("no lives".to_string());
+		}
+		if let Some(multiball) = self.multiball {
+			let (Spawn::Timer(every) | Spawn::Hits(every)) = multiball.spawn;
+			if every == 0 {
+				return Err("multi-ball spawning every 0".to_string());
+			}
+			if !(2..=MAX_BALLS).contains(&multiball.max_balls) {
+				return Err(format!("multi-ball with up to {} balls", multiball.max_balls));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Points won by each side. In a four-way match, a goal is won by whoever last hit the ball.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+	pub left: u32,
+	pub right: u32,
+	pub top: u32,
+	pub bottom: u32,
+}
+
+impl Score {
+	pub fn get(&self, side: Side) -> u32 {
+		match side {
+			Side::Left => self.left,
+			Side::Right => self.right,
+			Side::Top => self.top,
+			Side::Bottom => self.bottom,
+		}
+	}
+
+	pub fn add_point(&mut self, side: Side) {
+		match side {
+			Side::Left => self.left += 1,
+			Side::Right => self.right += 1,
+			Side::Top => self.top += 1,
+			Side::Bottom => self.bottom += 1,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchResult {
+	pub winner: Side,
+	pub score: Score,
+}
@@ -0,0 +1,43 @@
+//! Accumulator that turns variable frame times into a whole number of fixed simulation ticks.
+
+use std::time::Duration;
+
+/// The longest frame the accumulator will catch up on. Anything beyond this is dropped,
+/// so a stall (e.g. dragging the window) slows the game down instead of freezing it
+/// while hundreds of ticks are simulated at once.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+	step: Duration,
+	accumulator: Duration,
+}
+
+impl FixedTimestep {
+	pub fn new(ticks_per_second: u32) -> FixedTimestep {
+		FixedTimestep {
+			step: Duration::from_secs(1) / ticks_per_second,
+			accumulator: Duration::from_secs(0),
+		}
+	}
+
+	/// Adds the time that passed since the last frame.
+	pub fn advance(&mut self, elapsed: Duration) {
+		self.accumulator += elapsed.min(MAX_FRAME_TIME);
+	}
+
+	/// Consumes one tick worth of accumulated time, returning whether a tick should run.
+	pub fn tick(&mut self) -> bool {
+		if self.accumulator >= self.step {
+			self.accumulator -= self.step;
+			true
+		} else {
+			false
+		}
+	}
+
+	/// How far the accumulator is between the last tick and the next one, in `0.0..1.0`.
+	pub fn alpha(&self) -> f32 {
+		self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+	}
+}
@@ -0,0 +1,48 @@
+use mfight_ng::ai::{predict_ball_x, predict_ball_y, Difficulty};
+use mfight_ng::sim::{Format, MatchRules, Simulation, TickInput, TIMESTEP};
+use vek::Vec2;
+
+#[test]
+fn harder_difficulties_react_faster_and_guess_better() {
+	let profiles: Vec<_> = Difficulty::ALL.iter().map(|difficulty| difficulty.profile()).collect();
+	for pair in profiles.windows(2) {
+		let (easier, harder) = (pair[0], pair[1]);
+		assert!(harder.reaction_ticks < easier.reaction_ticks);
+		assert!(harder.prediction_error < easier.prediction_error);
+		assert!(harder.max_speed > easier.max_speed);
+		assert!(harder.spin > easier.spin);
+	}
+}
+
+#[test]
+fn prediction_follows_the_ball_off_the_walls() {
+	let mut simulation = Simulation::new();
+	let config = simulation.config;
+	// Steep enough to bounce off the top and bottom walls several times on the way.
+	simulation.ball_mut().velocity = Vec2::new(-150.0, 900.0);
+	let target = config.arena_width / 4.0;
+	let predicted = predict_ball_y(&config, simulation.ball(), target).unwrap();
+
+	while simulation.ball().position.x > target {
+		simulation.step(&TickInput::default());
+	}
+	// The ball crossed `target` during the last tick, so it can be off by one tick's travel.
+	let ball = simulation.ball();
+	let overshoot = (target - ball.position.x) / -ball.velocity.x;
+	let actual = ball.position.y - ball.velocity.y * overshoot;
+	assert!(overshoot <= TIMESTEP);
+	assert!((predicted - actual).abs() < 0.5, "predicted {}, actually {}", predicted, actual);
+
+	// Heading the other way, it never gets there.
+	assert_eq!(predict_ball_y(&config, ball, config.arena_width), None);
+}
+
+#[test]
+fn prediction_works_along_either_axis() {
+	let simulation = Simulation::with_rules(MatchRules { format: Format::FourWay, ..MatchRules::default() });
+	let config = simulation.config;
+	let mut ball = simulation.ball().clone();
+	ball.velocity = Vec2::new(0.0, 300.0);
+	assert_eq!(predict_ball_x(&config, &ball, config.arena_height), Some(ball.position.x));
+	assert_eq!(predict_ball_y(&config, &ball, 0.0), None);
+}
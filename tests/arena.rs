@@ -0,0 +1,98 @@
+mod common;
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::config::Config;
+use mfight_ng::sim::{Format, MatchRules, PaddleInput, Side, Simulation, TickInput, PADDLE_MARGIN};
+use vek::Vec2;
+
+use common::{run, with_ball};
+
+fn rules(lives: u32) -> MatchRules {
+	MatchRules { format: Format::FourWay, lives, ..MatchRules::default() }
+}
+
+/// Steps until the ball has gone out or `ticks` have passed.
+fn run_until_goal(simulation: &mut Simulation, ticks: usize) {
+	let conceded = simulation.conceded;
+	for _ in 0..ticks {
+		simulation.step(&TickInput::default());
+		if simulation.conceded != conceded {
+			return;
+		}
+	}
+}
+
+#[test]
+fn top_and_bottom_paddles_stay_between_the_corners() {
+	let mut simulation = Simulation::with_config(Config::default(), rules(3), 1);
+	let input = TickInput {
+		player3: PaddleInput::new(-1.0),
+		player4: PaddleInput::new(1.0),
+		..TickInput::default()
+	};
+	for _ in 0..600 {
+		simulation.step(&input);
+	}
+
+	let config = simulation.config;
+	let corner = PADDLE_MARGIN + config.paddle_width;
+	let (top, bottom) = (simulation.paddle(Side::Top).bounds(), simulation.paddle(Side::Bottom).bounds());
+	assert_eq!(top.min, Vec2::new(corner, PADDLE_MARGIN));
+	assert_eq!(bottom.max, Vec2::new(config.arena_width - corner, config.arena_height - PADDLE_MARGIN));
+}
+
+#[test]
+fn ball_through_the_top_costs_the_top_player_a_life() {
+	let config = Config::default();
+	let position = Vec2::new(config.arena_width / 2.0 + 200.0, config.arena_height / 2.0);
+	let mut simulation = with_ball(rules(3), position, Vec2::new(0.0, -400.0));
+	simulation.balls[0].last_hit = Some(Side::Left);
+	run_until_goal(&mut simulation, 300);
+
+	assert_eq!(simulation.lives(Side::Top), 2);
+	assert_eq!(simulation.score.left, 1);
+	assert_eq!(simulation.result, None);
+}
+
+#[test]
+fn knocked_out_players_are_walled_off() {
+	let config = Config::default();
+	let position = Vec2::new(config.arena_width / 2.0 + 200.0, config.arena_height / 2.0);
+	let mut simulation = with_ball(rules(1), position, Vec2::new(0.0, -400.0));
+	run_until_goal(&mut simulation, 300);
+	assert!(!simulation.in_play(Side::Top));
+	assert_eq!(simulation.sides_in_play().count(), 3);
+
+	// Nothing gets past the top any more: the ball comes back down instead.
+	simulation.ball_mut().position = position;
+	simulation.ball_mut().velocity = Vec2::new(0.0, -400.0);
+	run_until_goal(&mut simulation, 120);
+	assert_eq!(simulation.conceded[Side::Top as usize], 1);
+	assert!(simulation.ball().velocity.y > 0.0);
+	assert!(simulation.ball().position.y > 0.0);
+}
+
+#[test]
+fn last_player_standing_wins() {
+	let mut simulation = Simulation::with_config(Config::default(), rules(2), 7);
+	let mut ai = Ai::new(Side::Bottom, Difficulty::Hard);
+	while simulation.result.is_none() {
+		assert!(simulation.tick < 60 * 600, "nobody was ever knocked out");
+		let input = TickInput { player4: ai.update(&simulation), ..TickInput::default() };
+		simulation.step(&input);
+	}
+
+	let winner = simulation.result.unwrap().winner;
+	assert_eq!(simulation.sides_in_play().collect::<Vec<_>>(), vec![winner]);
+	for &side in &Side::ALL {
+		assert_eq!(simulation.lives(side) > 0, side == winner);
+	}
+}
+
+#[test]
+fn match_without_lives_still_plays() {
+	// Nobody is ever in play, so the ball has no side to head toward but the one served.
+	let mut simulation = Simulation::with_config(Config::default(), rules(0), 1);
+	run(&mut simulation, 600);
+	assert_eq!(simulation.result, None);
+}
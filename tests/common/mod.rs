@@ -0,0 +1,46 @@
+//! Setup shared by the integration tests. Each test file only uses some of it.
+#![allow(dead_code)]
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::session::Session;
+use mfight_ng::sim::{MatchRules, PaddleId, ServeRule, Simulation, TickInput};
+use vek::Vec2;
+
+/// Rules for a match that is over quickly: first to 3, serving in turn.
+pub fn short_match() -> MatchRules {
+	MatchRules { win_score: 3, win_by: 1, serve: ServeRule::Alternate, ..MatchRules::default() }
+}
+
+/// A match of `rules` with the ball moved to `position`, heading along `velocity`.
+pub fn with_ball(rules: MatchRules, position: Vec2<f32>, velocity: Vec2<f32>) -> Simulation {
+	let mut simulation = Simulation::with_rules(rules);
+	simulation.ball_mut().position = position;
+	simulation.ball_mut().velocity = velocity;
+	simulation
+}
+
+/// Steps `simulation` through `ticks` ticks with nobody moving.
+pub fn run(simulation: &mut Simulation, ticks: usize) {
+	for _ in 0..ticks {
+		simulation.step(&TickInput::default());
+	}
+}
+
+/// A CPU on every paddle of a session's matches.
+pub struct Cpus(Vec<(PaddleId, Ai)>);
+
+impl Cpus {
+	pub fn new(session: &Session, difficulty: Difficulty) -> Cpus {
+		let paddles = session.players().iter().map(|&player| session.paddle_of(player));
+		Cpus(paddles.map(|paddle| (paddle, Ai::for_paddle(paddle, difficulty.profile()))).collect())
+	}
+
+	/// What every CPU does next in `simulation`.
+	pub fn input(&mut self, simulation: &Simulation) -> TickInput {
+		let mut input = TickInput::default();
+		for (paddle, ai) in &mut self.0 {
+			input.set_paddle(*paddle, ai.update(simulation));
+		}
+		input
+	}
+}
@@ -0,0 +1,44 @@
+use mfight_ng::config::{Config, ConfigError, PRESETS};
+
+#[test]
+fn every_preset_is_valid() {
+	for name in PRESETS.iter() {
+		let config = Config::preset(name).unwrap();
+		assert!(config.validate().is_ok(), "preset {} is invalid", name);
+	}
+}
+
+#[test]
+fn empty_file_gives_defaults() {
+	assert_eq!(Config::from_toml("").unwrap(), Config::default());
+}
+
+#[test]
+fn file_overrides_its_preset() {
+	let config = Config::from_toml("preset = \"fast\"\npaddle_height = 80.0\n").unwrap();
+	let fast = Config::preset("fast").unwrap();
+
+	assert_eq!(config.paddle_height, 80.0);
+	assert_eq!(config.ball_speed, fast.ball_speed);
+}
+
+#[test]
+fn unknown_preset_is_rejected() {
+	match Config::from_toml("preset = \"slow\"") {
+		Err(ConfigError::UnknownPreset(name)) => assert_eq!(name, "slow"),
+		other => panic!("expected an unknown preset error, got {:?}", other),
+	}
+}
+
+#[test]
+fn unknown_field_is_rejected() {
+	assert!(matches!(Config::from_toml("ball_sped = 400.0"), Err(ConfigError::Parse(_))));
+}
+
+#[test]
+fn invalid_value_names_the_field() {
+	match Config::from_toml("ball_speed = 500.0\nmax_ball_speed = 400.0") {
+		Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, "max_ball_speed"),
+		other => panic!("expected an invalid value error, got {:?}", other),
+	}
+}
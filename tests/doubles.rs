@@ -0,0 +1,103 @@
+mod common;
+
+use mfight_ng::ai::Difficulty;
+use mfight_ng::config::Config;
+use mfight_ng::session::{Player, Session};
+use mfight_ng::sim::{Format, MatchRules, PaddleId, PaddleInput, Side, Simulation, TickInput, FORWARD_DEPTH};
+use vek::Vec2;
+
+use common::{run, Cpus};
+
+fn rules() -> MatchRules {
+	MatchRules { format: Format::Doubles, win_score: 3, ..MatchRules::default() }
+}
+
+fn doubles() -> Simulation {
+	Simulation::with_config(Config::default(), rules(), 4)
+}
+
+/// Puts the ball level with the middle of `paddle`, `gap` away from its left side, heading
+/// along the X axis at `speed`.
+fn aim_at(simulation: &mut Simulation, paddle: PaddleId, gap: f32, speed: f32) {
+	let target = simulation.paddle_at(paddle).bounds();
+	let ball = simulation.ball().size;
+	simulation.ball_mut().position = Vec2::new(target.min.x - gap - ball.x, (target.min.y + target.max.y - ball.y) / 2.0);
+	simulation.ball_mut().velocity = Vec2::new(speed, 0.0);
+	simulation.serve_delay = 0;
+}
+
+/// Plays a match between two CPU teams, returning every tick's input alongside the result.
+fn play(session: &Session) -> (Simulation, Vec<TickInput>) {
+	let mut simulation = session.new_match();
+	let mut cpus = Cpus::new(session, Difficulty::Normal);
+	let mut inputs = Vec::new();
+	while simulation.result.is_none() {
+		assert!(simulation.tick < 60 * 600, "the match never finished");
+		let input = cpus.input(&simulation);
+		inputs.push(input);
+		simulation.step(&input);
+	}
+	(simulation, inputs)
+}
+
+#[test]
+fn each_side_has_a_defender_and_a_forward() {
+	let mut simulation = doubles();
+	let config = simulation.config;
+	assert_eq!(simulation.paddles[Side::Left as usize].len(), 2);
+	assert_eq!(simulation.paddles[Side::Right as usize].len(), 2);
+	assert!(simulation.paddles[Side::Top as usize].is_empty());
+
+	let forward = PaddleId::new(Side::Left, 1);
+	assert_eq!(simulation.paddle_at(forward).position.x, config.arena_width * FORWARD_DEPTH);
+	assert_eq!(simulation.paddle_at(forward).centre().y, simulation.paddle(Side::Left).centre().y);
+	assert!(simulation.paddle_at(forward).height() < simulation.paddle(Side::Left).height());
+
+	// The third player's input moves the left forward, and only it.
+	let input = TickInput { player3: PaddleInput::new(1.0), ..TickInput::default() };
+	let start = simulation.clone();
+	simulation.step(&input);
+	assert!(simulation.paddle_at(forward).position.y > start.paddle_at(forward).position.y);
+	assert_eq!(simulation.paddle(Side::Left), start.paddle(Side::Left));
+	assert_eq!(simulation.paddle_at(PaddleId::new(Side::Right, 1)), start.paddle_at(PaddleId::new(Side::Right, 1)));
+}
+
+#[test]
+fn shots_from_behind_pass_through_a_forward() {
+	let mut simulation = doubles();
+	aim_at(&mut simulation, PaddleId::new(Side::Left, 1), 4.0, 400.0);
+	run(&mut simulation, 10);
+
+	assert!(simulation.ball().velocity.x > 0.0);
+	assert!(simulation.ball().position.x > simulation.paddle_at(PaddleId::new(Side::Left, 1)).bounds().max.x);
+}
+
+#[test]
+fn forward_stops_the_ball_heading_for_its_goal() {
+	let mut simulation = doubles();
+	let forward = PaddleId::new(Side::Right, 1);
+	aim_at(&mut simulation, forward, 4.0, 400.0);
+	run(&mut simulation, 10);
+
+	assert!(simulation.ball().velocity.x < 0.0);
+	assert!(simulation.ball().bounds().max.x <= simulation.paddle_at(forward).position.x);
+	assert_eq!(simulation.balls[0].contact, None);
+}
+
+#[test]
+fn teammates_share_the_score_and_the_win() {
+	let mut session = Session::new(Config::default(), rules(), 21);
+	session.swap_sides();
+	assert_eq!(session.paddle_of(Player::Three), PaddleId::new(Side::Right, 1));
+	assert_eq!(session.paddle_of(Player::Four), PaddleId::new(Side::Left, 1));
+
+	let (simulation, _) = play(&session);
+	let result = simulation.result.unwrap();
+	session.record(&result);
+	let stats = session.stats;
+	assert_eq!(stats.wins[0], stats.wins[2]);
+	assert_eq!(stats.wins[1], stats.wins[3]);
+	assert_eq!(stats.wins[0] + stats.wins[1], 1);
+	assert_eq!(stats.points[0], result.score.right);
+	assert_eq!(stats.points[3], result.score.left);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use mfight_ng::config::Config;
+use mfight_ng::net::{
+	Announcement, Browser, GameKind, Lobby, LinkConditions, MatchSettings, Message, OnlineMatch, COUNTDOWN,
+	MAX_NAME_LENGTH,
+};
+use mfight_ng::sim::{PaddleInput, Side};
+
+use common::short_match;
+
+fn settings() -> MatchSettings {
+	MatchSettings {
+		config: Config::preset("fast").unwrap(),
+		rules: short_match(),
+		seed: 42,
+		input_delay: 2,
+		host_side: Side::Left,
+	}
+}
+
+fn loopback(lobby: &Lobby) -> SocketAddr {
+	SocketAddr::from((Ipv4Addr::LOCALHOST, lobby.local_addr().unwrap().port()))
+}
+
+/// Polls both lobbies until `done`, failing if that takes too long.
+fn poll_until(host: &mut Lobby, guest: &mut Lobby, what: &str, done: impl Fn(&Lobby, &Lobby) -> bool) {
+	let started = Instant::now();
+	while !done(host, guest) {
+		assert!(started.elapsed() < COUNTDOWN + Duration::from_secs(5), "timed out waiting for {}", what);
+		host.poll().unwrap();
+		guest.poll().unwrap();
+		std::thread::sleep(Duration::from_millis(2));
+	}
+}
+
+#[test]
+fn lobby_messages_survive_encoding() {
+	let messages = vec![
+		Message::Discover,
+		Message::Announce(Announcement {
+			name: "den".to_string(),
+			mode: settings().mode(),
+			kind: GameKind::Lobby,
+			players: 1,
+			capacity: 2,
+		}),
+		Message::Seat { name: "Zoë".to_string(), ready: true, swaps: 3 },
+		Message::Room { settings: settings(), names: ["den".to_string(), String::new()], ready: [true, false] },
+		Message::Start { after: 2_950 },
+	];
+	for message in messages {
+		assert_eq!(Message::decode(&message.encode()), Ok(message));
+	}
+}
+
+#[test]
+fn long_names_are_cut_short() {
+	// Two bytes each, so only half of `MAX_NAME_LENGTH` fit.
+	let name = "é".repeat(MAX_NAME_LENGTH);
+	let message = Message::Seat { name, ready: false, swaps: 0 };
+	match Message::decode(&message.encode()) {
+		Ok(Message::Seat { name, .. }) => assert_eq!(name, "é".repeat(MAX_NAME_LENGTH / 2)),
+		other => panic!("unexpected {:?}", other),
+	}
+}
+
+#[test]
+fn browser_finds_a_hosted_lobby() {
+	let mut lobby = Lobby::host("den", settings(), LinkConditions::default()).unwrap();
+	let mut browser = Browser::with_targets(vec![loopback(&lobby)]).unwrap();
+
+	let started = Instant::now();
+	while browser.games().is_empty() {
+		assert!(started.elapsed() < Duration::from_secs(5), "the lobby was never found");
+		browser.poll().unwrap();
+		lobby.poll().unwrap();
+		std::thread::sleep(Duration::from_millis(2));
+	}
+
+	let game = &browser.games()[0];
+	assert_eq!(game.address, loopback(&lobby));
+	assert_eq!(game.announcement.name, "den");
+	assert_eq!(game.announcement.kind, GameKind::Lobby);
+	assert_eq!(game.announcement.mode, "1v1, first to 3");
+	assert_eq!((game.announcement.players, game.announcement.capacity), (1, 2));
+}
+
+#[test]
+fn players_ready_up_and_start_together() {
+	let mut host = Lobby::host("den", settings(), LinkConditions::default()).unwrap();
+	let mut guest = Lobby::join(loopback(&host), "visitor", LinkConditions::default()).unwrap();
+	poll_until(&mut host, &mut guest, "the guest to join", |host, guest| {
+		host.peer().is_some() && guest.peer().is_some()
+	});
+	assert_eq!(guest.peer().unwrap().name, "den");
+	assert_eq!(host.peer().unwrap().name, "visitor");
+	assert_eq!(guest.local_side(), Some(Side::Right));
+
+	guest.swap_sides().unwrap();
+	poll_until(&mut host, &mut guest, "the sides to swap", |host, guest| {
+		host.local_side() == Some(Side::Right) && guest.local_side() == Some(Side::Left)
+	});
+
+	host.set_ready(true).unwrap();
+	guest.set_ready(true).unwrap();
+	poll_until(&mut host, &mut guest, "the countdown", |host, guest| {
+		host.countdown().is_some() && guest.countdown().is_some()
+	});
+	let apart = host.countdown().unwrap().abs_diff(guest.countdown().unwrap());
+	assert!(apart < Duration::from_millis(200), "the countdowns are {:?} apart", apart);
+
+	poll_until(&mut host, &mut guest, "the match to start", |host, guest| {
+		host.should_start() && guest.should_start()
+	});
+	let mut peers = [host.into_connection(), guest.into_connection()];
+	assert_eq!(peers[0].local_side(), Some(Side::Right));
+	assert_eq!(peers[1].local_side(), Some(Side::Left));
+
+	let started = Instant::now();
+	while peers.iter().any(|peer| peer.confirmed().map_or(0, |simulation| simulation.tick) < 60) {
+		assert!(started.elapsed() < Duration::from_secs(10), "the match stopped making progress");
+		for peer in &mut peers {
+			peer.poll().unwrap();
+			peer.advance(PaddleInput::new(0.5)).unwrap();
+		}
+		std::thread::sleep(Duration::from_millis(2));
+	}
+	assert!(!peers[0].is_desynced() && !peers[1].is_desynced());
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use mfight_ng::sim::{Ball, Entity, MatchRules, MultiBall, RallyEnd, Side, Simulation, Spawn, TickInput};
+use vek::Vec2;
+
+use common::run;
+
+fn rules(spawn: Spawn, max_balls: u32, rally_end: RallyEnd) -> MatchRules {
+	MatchRules { multiball: Some(MultiBall { spawn, max_balls, rally_end }), ..MatchRules::default() }
+}
+
+/// Adds a ball just inside the right goal line, on its way out.
+fn add_ball_leaving_right(simulation: &mut Simulation) {
+	let config = simulation.config;
+	let position = Vec2::new(config.arena_width - 2.0, 40.0);
+	let size = Vec2::broadcast(config.ball_size);
+	simulation.balls.push(Ball::new(Entity::new(position, Vec2::new(600.0, 0.0), size)));
+}
+
+#[test]
+fn balls_join_on_a_timer() {
+	let mut simulation = Simulation::with_rules(rules(Spawn::Timer(30), 3, RallyEnd::FirstOut));
+	run(&mut simulation, 29);
+	assert_eq!(simulation.balls.len(), 1);
+	run(&mut simulation, 1);
+	assert_eq!(simulation.balls.len(), 2);
+
+	// Each new ball starts from the centre, heading for one of the goals.
+	let config = simulation.config;
+	let ball = &simulation.balls[1].entity;
+	assert!(ball.velocity.x != 0.0);
+	assert!((ball.velocity.magnitude() - config.ball_speed).abs() < 0.01);
+	assert!((ball.centre().x - config.arena_width / 2.0).abs() < config.ball_speed / 60.0 + 0.01);
+}
+
+#[test]
+fn no_more_than_max_balls_are_in_play() {
+	let mut simulation = Simulation::with_rules(rules(Spawn::Timer(5), 3, RallyEnd::LastOut));
+	run(&mut simulation, 40);
+	assert_eq!(simulation.balls.len(), 3);
+
+	// Without multi-ball there is only ever the one.
+	let mut simulation = Simulation::new();
+	for _ in 0..2000 {
+		simulation.step(&TickInput::default());
+		assert_eq!(simulation.balls.len(), 1);
+	}
+}
+
+#[test]
+fn balls_join_on_paddle_hits() {
+	let mut simulation = Simulation::with_rules(rules(Spawn::Hits(1), 3, RallyEnd::FirstOut));
+	let paddle = simulation.paddle(Side::Left).bounds();
+	simulation.ball_mut().position = Vec2::new(paddle.max.x + 4.0, (paddle.min.y + paddle.max.y) / 2.0 - 5.0);
+	simulation.ball_mut().velocity = Vec2::new(-400.0, 0.0);
+	run(&mut simulation, 10);
+
+	assert!(simulation.ball().velocity.x > 0.0);
+	assert_eq!(simulation.balls.len(), 2);
+}
+
+#[test]
+fn every_ball_out_scores_and_the_last_out_ends_the_rally() {
+	let mut simulation = Simulation::with_rules(rules(Spawn::Timer(600), 3, RallyEnd::LastOut));
+	let first = simulation.ball().clone();
+	add_ball_leaving_right(&mut simulation);
+	run(&mut simulation, 1);
+
+	// The extra ball scored, and the rally carries on with the first.
+	assert_eq!(simulation.score.left, 1);
+	assert_eq!(simulation.balls.len(), 1);
+	assert_eq!(simulation.serve_delay, 0);
+	assert!(simulation.ball().position != first.position);
+	assert_eq!(simulation.ball().velocity, first.velocity);
+
+	add_ball_leaving_right(&mut simulation);
+	simulation.balls.swap(0, 1);
+	simulation.balls[1].entity.position.x = -100.0;
+	simulation.balls[1].entity.velocity = Vec2::new(-600.0, 0.0);
+	run(&mut simulation, 1);
+
+	// Both went out on the same tick: each scores, and then the next rally is served.
+	assert_eq!((simulation.score.left, simulation.score.right), (2, 1));
+	assert_eq!(simulation.balls.len(), 1);
+	assert!(simulation.serve_delay > 0);
+	assert_eq!(simulation.serve_toward, Side::Left);
+}
+
+#[test]
+fn first_out_clears_the_other_balls() {
+	let mut simulation = Simulation::with_rules(rules(Spawn::Timer(600), 3, RallyEnd::FirstOut));
+	add_ball_leaving_right(&mut simulation);
+	run(&mut simulation, 1);
+
+	let config = simulation.config;
+	assert_eq!(simulation.score.left, 1);
+	assert_eq!(simulation.balls.len(), 1);
+	assert!(simulation.serve_delay > 0);
+	assert_eq!(simulation.ball().centre(), Vec2::new(config.arena_width, config.arena_height) / 2.0);
+}
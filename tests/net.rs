@@ -0,0 +1,212 @@
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::config::Config;
+use mfight_ng::net::{
+	Connection, DecodeError, Link, LinkConditions, MatchSettings, Message, Rollback, PROTOCOL_VERSION,
+};
+use mfight_ng::sim::{PaddleInput, Side, Simulation, TIMESTEP};
+
+use common::short_match;
+
+fn settings() -> MatchSettings {
+	MatchSettings {
+		config: Config::preset("fast").unwrap(),
+		rules: short_match(),
+		seed: 42,
+		input_delay: 2,
+		host_side: Side::Left,
+	}
+}
+
+#[test]
+fn messages_survive_encoding() {
+	let messages = vec![
+		Message::Join { name: "guest".to_string() },
+		Message::Welcome { settings: settings(), name: "host".to_string() },
+		Message::Inputs {
+			ack: 17,
+			first: 9,
+			inputs: vec![PaddleInput::new(-1.0), PaddleInput::new(0.25)],
+			checksum: Some((60, 0xDEAD_BEEF)),
+		},
+		Message::Inputs { ack: 0, first: 1, inputs: Vec::new(), checksum: None },
+		Message::Ping { time: 123_456 },
+		Message::Pong { time: 123_456 },
+		Message::Leave,
+	];
+	for message in messages {
+		assert_eq!(Message::decode(&message.encode()), Ok(message));
+	}
+}
+
+#[test]
+fn foreign_and_incompatible_packets_are_rejected() {
+	assert_eq!(Message::decode(b"hello"), Err(DecodeError::Foreign));
+
+	let mut packet = Message::Leave.encode();
+	packet[2..4].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+	assert_eq!(Message::decode(&packet), Err(DecodeError::Version(PROTOCOL_VERSION + 1)));
+
+	let mut packet = Message::Ping { time: 1 }.encode();
+	packet.pop();
+	assert!(matches!(Message::decode(&packet), Err(DecodeError::Malformed(_))));
+}
+
+/// One peer of a match played over an in-memory link.
+struct Peer {
+	rollback: Rollback,
+	ai: Ai,
+	acked: u64,
+	/// Carries this peer's packets to the other one.
+	link: Link<Vec<u8>>,
+}
+
+impl Peer {
+	fn new(side: Side, difficulty: Difficulty, conditions: LinkConditions, seed: u64) -> Peer {
+		let settings = settings();
+		let simulation = Simulation::with_config(settings.config, settings.rules, settings.seed);
+		Peer {
+			rollback: Rollback::new(simulation, side, settings.input_delay),
+			ai: Ai::new(side, difficulty),
+			acked: 0,
+			link: Link::new(conditions, seed),
+		}
+	}
+
+	fn tick(&mut self, now: Instant) {
+		let input = self.ai.update(self.rollback.simulation());
+		self.rollback.advance(input);
+
+		let (first, inputs) = self.rollback.unacked(self.acked);
+		let message = Message::Inputs {
+			ack: self.rollback.received(),
+			first,
+			inputs: inputs.to_vec(),
+			checksum: self.rollback.checksum(),
+		};
+		self.link.send(now, message.encode());
+	}
+
+	fn receive(&mut self, packet: &[u8]) {
+		if let Ok(Message::Inputs { ack, first, inputs, checksum }) = Message::decode(packet) {
+			self.acked = self.acked.max(ack);
+			self.rollback.receive(first, &inputs);
+			self.rollback.sync((first + inputs.len() as u64).saturating_sub(1), ack);
+			if let Some((tick, hash)) = checksum {
+				self.rollback.check(tick, hash);
+			}
+		}
+	}
+}
+
+#[test]
+fn peers_agree_despite_loss_and_latency() {
+	let conditions = LinkConditions {
+		loss: 0.2,
+		latency: Duration::from_millis(60),
+		jitter: Duration::from_millis(30),
+	};
+	let mut peers = [
+		Peer::new(Side::Left, Difficulty::Hard, conditions, 1),
+		Peer::new(Side::Right, Difficulty::Normal, conditions, 2),
+	];
+
+	let start = Instant::now();
+	for tick in 0..200_000u32 {
+		if peers.iter().all(|peer| peer.rollback.confirmed().result.is_some()) {
+			break;
+		}
+		let now = start + Duration::from_secs_f32(tick as f32 * TIMESTEP);
+		for peer in &mut peers {
+			peer.tick(now);
+		}
+		for (from, to) in [(0, 1), (1, 0)].iter().copied() {
+			for packet in peers[from].link.due(now) {
+				peers[to].receive(&packet);
+			}
+		}
+	}
+
+	let (left, right) = (&peers[0].rollback, &peers[1].rollback);
+	assert!(left.confirmed().result.is_some(), "the match never finished");
+	assert_eq!(left.confirmed(), right.confirmed());
+	assert_eq!(left.simulation(), left.confirmed());
+	assert!(!left.is_desynced() && !right.is_desynced());
+	assert!(left.rollbacks() > 0 && right.rollbacks() > 0, "the link never made a prediction wrong");
+}
+
+#[test]
+fn diverging_peers_are_caught() {
+	let settings = settings();
+	let simulation = Simulation::with_config(settings.config, settings.rules, settings.seed);
+	let mut left = Rollback::new(simulation.clone(), Side::Left, 0);
+	let mut right = Rollback::new(simulation, Side::Right, 0);
+	for tick in 1..=120 {
+		left.advance(PaddleInput::new(1.0));
+		right.advance(PaddleInput::default());
+		// The right peer hears inputs the left never made.
+		left.receive(tick, &[PaddleInput::default()]);
+		right.receive(tick, &[PaddleInput::new(-1.0)]);
+	}
+
+	let (tick, hash) = right.checksum().unwrap();
+	left.check(tick, hash);
+	assert!(left.is_desynced());
+}
+
+#[test]
+fn connection_plays_over_loopback() {
+	let host = Connection::host(0, "host", settings(), LinkConditions::default()).unwrap();
+	let port = host.local_addr().unwrap().port();
+	let join = Connection::join(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), "guest", LinkConditions::default()).unwrap();
+	let mut peers = [(host, Ai::new(Side::Left, Difficulty::Hard)), (join, Ai::new(Side::Right, Difficulty::Easy))];
+
+	let started = Instant::now();
+	let confirmed = |connection: &Connection| connection.rollback().map_or(0, |rollback| rollback.confirmed().tick);
+	while peers.iter().any(|(connection, _)| confirmed(connection) < 600) {
+		assert!(started.elapsed() < Duration::from_secs(20), "the peers stopped making progress");
+		for (connection, ai) in &mut peers {
+			connection.poll().unwrap();
+			if let Some(simulation) = connection.simulation() {
+				let input = ai.update(simulation);
+				connection.advance(input).unwrap();
+			}
+		}
+		std::thread::sleep(Duration::from_millis(1));
+	}
+
+	let [(host, _), (join, _)] = &peers;
+	assert_eq!(join.settings(), Some(&settings()));
+	assert_eq!(join.rollback().unwrap().local_side(), Side::Right);
+	assert!(!host.rollback().unwrap().is_desynced() && !join.rollback().unwrap().is_desynced());
+	assert!(host.latency().ping().is_some());
+}
+
+#[test]
+fn inputs_from_the_far_future_are_ignored() {
+	let mut host = Connection::host(0, "host", settings(), LinkConditions::default()).unwrap();
+	let port = host.local_addr().unwrap().port();
+	let guest = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+	guest.connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+	guest.send(&Message::Join { name: "guest".to_string() }.encode()).unwrap();
+
+	let started = Instant::now();
+	while host.rollback().is_none() {
+		assert!(started.elapsed() < Duration::from_secs(5), "the guest was never let in");
+		host.poll().unwrap();
+		std::thread::sleep(Duration::from_millis(1));
+	}
+	let received = host.rollback().unwrap().received();
+
+	let inputs = Message::Inputs { ack: 0, first: u64::MAX, inputs: vec![PaddleInput::new(1.0); 4], checksum: None };
+	guest.send(&inputs.encode()).unwrap();
+	for _ in 0..50 {
+		host.poll().unwrap();
+		std::thread::sleep(Duration::from_millis(1));
+	}
+	assert_eq!(host.rollback().unwrap().received(), received);
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use mfight_ng::config::Config;
+use mfight_ng::sim::{MatchRules, Score, Side, Simulation, TIMESTEP};
+use vek::Vec2;
+
+use common::{run, with_ball};
+
+#[test]
+fn ball_bounces_off_top_wall() {
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0, 2.0),
+		Vec2::new(0.0, -300.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().position.y >= 0.0);
+	assert!(simulation.ball().velocity.y > 0.0);
+}
+
+#[test]
+fn ball_bounces_off_bottom_wall() {
+	let max_y = Config::default().arena_height - Config::default().ball_size;
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0, max_y - 2.0),
+		Vec2::new(0.0, 300.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().position.y <= max_y);
+	assert!(simulation.ball().velocity.y < 0.0);
+}
+
+#[test]
+fn ball_past_wall_is_clamped_back_inside() {
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0, -5.0),
+		Vec2::new(0.0, -10.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().position.y >= 0.0);
+	assert!(simulation.ball().velocity.y > 0.0);
+}
+
+#[test]
+fn ball_leaving_wall_is_not_reflected_back() {
+	// Stuck past the wall but already heading back in: it must keep going, not flip.
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0, -5.0),
+		Vec2::new(0.0, 10.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().position.y >= 0.0);
+	assert_eq!(simulation.ball().velocity.y, 10.0);
+}
+
+#[test]
+fn ball_with_spin_does_not_jitter_along_wall() {
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0 - 100.0, 0.5),
+		Vec2::new(300.0, -4.0),
+	);
+	let mut flips = 0;
+	let mut last = simulation.ball().velocity.y;
+	for _ in 0..20 {
+		run(&mut simulation, 1);
+		if simulation.ball().velocity.y.signum() != last.signum() {
+			flips += 1;
+		}
+		last = simulation.ball().velocity.y;
+		assert!(simulation.ball().position.y >= 0.0);
+	}
+
+	assert_eq!(flips, 1);
+}
+
+#[test]
+fn fast_ball_does_not_tunnel_through_paddle() {
+	let paddle_y = Simulation::new().paddle(Side::Left).centre().y;
+	let speed = Config::default().arena_width / TIMESTEP;
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(Config::default().arena_width / 2.0, paddle_y - Config::default().ball_size / 2.0),
+		Vec2::new(-speed, 0.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().velocity.x > 0.0);
+	assert!(simulation.ball().position.x >= simulation.paddle(Side::Left).bounds().max.x);
+	assert_eq!(simulation.score, Score::default());
+}
+
+#[test]
+fn ball_glancing_paddle_top_deflects_up() {
+	let paddle = Simulation::new().paddle(Side::Left).bounds();
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(paddle.min.x, paddle.min.y - Config::default().ball_size - 1.0),
+		Vec2::new(0.0, 300.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().velocity.y < 0.0);
+	assert_eq!(simulation.ball().velocity.x, 0.0);
+	assert!(simulation.ball().bounds().max.y <= paddle.min.y);
+}
+
+#[test]
+fn paddle_hits_ball_once_per_contact() {
+	let paddle = Simulation::new().paddle(Side::Left).bounds();
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(paddle.max.x + 1.0, paddle.min.y + 10.0),
+		Vec2::new(-120.0, 0.0),
+	);
+	run(&mut simulation, 1);
+	let after_hit = simulation.ball().velocity;
+	run(&mut simulation, 5);
+
+	assert!(after_hit.x > 0.0);
+	assert_eq!(simulation.ball().velocity, after_hit);
+}
+
+#[test]
+fn ball_speed_is_capped() {
+	let paddle = Simulation::new().paddle(Side::Left).bounds();
+	let max_ball_speed = Config::default().max_ball_speed;
+	let mut simulation = with_ball(
+		MatchRules::default(),
+		Vec2::new(paddle.max.x + 1.0, paddle.min.y + 10.0),
+		Vec2::new(-2.0 * max_ball_speed, 0.0),
+	);
+	run(&mut simulation, 1);
+
+	assert!(simulation.ball().velocity.x > 0.0);
+	assert!(simulation.ball().velocity.magnitude() <= max_ball_speed + 0.01);
+}
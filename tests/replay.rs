@@ -0,0 +1,193 @@
+mod common;
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::config::Config;
+use mfight_ng::replay::{Recorder, Replay, ReplayError, ReplayPlayer, KEYFRAME_INTERVAL};
+use mfight_ng::session::Session;
+use mfight_ng::sim::{
+	Format, MatchRules, MultiBall, PaddleInput, RallyEnd, Side, Simulation, Spawn, TickInput, MAX_BALLS,
+};
+
+use common::Cpus;
+
+/// Records a short match between two CPUs.
+fn record(ticks: u64) -> (Replay, Simulation) {
+	let rules = MatchRules { win_score: 3, ..MatchRules::default() };
+	let mut simulation = Simulation::with_config(Config::default(), rules, 99);
+	let mut recorder = Recorder::new(&simulation);
+	let mut left = Ai::new(Side::Left, Difficulty::Hard);
+	let mut right = Ai::new(Side::Right, Difficulty::Easy);
+
+	while simulation.tick < ticks && simulation.result.is_none() {
+		let input = TickInput {
+			player1: left.update(&simulation),
+			player2: right.update(&simulation),
+			..TickInput::default()
+		};
+		recorder.record(&input);
+		simulation.step(&input);
+	}
+	(recorder.finish(&simulation), simulation)
+}
+
+/// Plays a whole match of `rules` between CPUs on every paddle, and checks its replay
+/// survives a round trip through bytes and plays back the same match. Returns the replay.
+fn assert_plays_back_the_same(rules: MatchRules, seed: u64) -> Replay {
+	let session = Session::new(Config::default(), rules, seed);
+	let mut simulation = session.new_match();
+	let mut recorder = Recorder::new(&simulation);
+	let mut cpus = Cpus::new(&session, Difficulty::Normal);
+	while simulation.result.is_none() {
+		assert!(simulation.tick < 60 * 600, "the match never finished");
+		let input = cpus.input(&simulation);
+		recorder.record(&input);
+		simulation.step(&input);
+	}
+	let replay = recorder.finish(&simulation);
+
+	let mut bytes = Vec::new();
+	replay.write_to(&mut bytes).unwrap();
+	let read = Replay::read_from(&mut bytes.as_slice()).unwrap();
+	assert_eq!(read, replay);
+
+	let mut player = ReplayPlayer::new(read);
+	while player.step() {}
+	assert_eq!(player.simulation(), &simulation);
+	assert_eq!(player.verify(), Some(true));
+	replay
+}
+
+#[test]
+fn replay_survives_a_round_trip_through_bytes() {
+	let (replay, _) = record(3000);
+	let mut bytes = Vec::new();
+	replay.write_to(&mut bytes).unwrap();
+
+	assert_eq!(Replay::read_from(&mut bytes.as_slice()).unwrap(), replay);
+}
+
+#[test]
+fn playback_reproduces_the_match() {
+	let (replay, simulation) = record(3000);
+	let mut player = ReplayPlayer::new(replay);
+	while player.step() {}
+
+	assert_eq!(player.simulation(), &simulation);
+	assert_eq!(player.verify(), Some(true));
+}
+
+#[test]
+fn seeking_matches_playing_straight_through() {
+	let (replay, _) = record(3000);
+	let target = 2 * KEYFRAME_INTERVAL + 17;
+	let mut straight = ReplayPlayer::new(replay.clone());
+	straight.seek(target);
+
+	let mut seeking = ReplayPlayer::new(replay);
+	seeking.seek(2500);
+	seeking.seek(10);
+	seeking.seek(target);
+
+	assert_eq!(seeking.tick(), target);
+	assert_eq!(seeking.simulation(), straight.simulation());
+}
+
+#[test]
+fn tampered_inputs_are_detected_as_desync() {
+	let (mut replay, _) = record(3000);
+	for input in &mut replay.inputs[100..200] {
+		input.player2 = PaddleInput::new(1.0);
+	}
+	let mut player = ReplayPlayer::new(replay);
+	while player.step() {}
+
+	assert_eq!(player.verify(), Some(false));
+}
+
+#[test]
+fn four_way_replays_play_back_the_same() {
+	assert_plays_back_the_same(MatchRules { format: Format::FourWay, lives: 1, ..MatchRules::default() }, 12);
+}
+
+#[test]
+fn doubles_replays_play_back_the_same() {
+	assert_plays_back_the_same(MatchRules { format: Format::Doubles, win_score: 3, ..MatchRules::default() }, 33);
+}
+
+#[test]
+fn multiball_replays_play_back_the_same() {
+	let multiball = MultiBall { spawn: Spawn::Hits(3), max_balls: 4, rally_end: RallyEnd::LastOut };
+	let rules = MatchRules { win_score: 5, multiball: Some(multiball), ..MatchRules::default() };
+	let replay = assert_plays_back_the_same(rules, 8);
+
+	let mut player = ReplayPlayer::new(replay);
+	let mut most = 0;
+	while player.step() {
+		most = most.max(player.simulation().balls.len());
+	}
+	assert!(most > 1);
+}
+
+#[test]
+fn other_files_are_rejected() {
+	let result = Replay::read_from(&mut &b"PK\x03\x04 not a replay"[..]);
+	assert!(matches!(result, Err(ReplayError::NotAReplay)));
+}
+
+/// The bytes of a replay with no ticks, and where in them the config length is.
+fn empty_replay() -> (Vec<u8>, usize) {
+	let simulation = Simulation::new();
+	let mut bytes = Vec::new();
+	Recorder::new(&simulation).finish(&simulation).write_to(&mut bytes).unwrap();
+	// Everything before the config length: magic, version, seed, then the rules.
+	(bytes, 4 + 2 + 8 + 4 + 4 + 1 + 1 + 4 + 1 + 4 + 4 + 1)
+}
+
+#[test]
+fn match_without_lives_is_rejected() {
+	let (mut bytes, _) = empty_replay();
+	// The lives follow the magic, version, seed, win score, win by, serve rule and format.
+	let at = 4 + 2 + 8 + 4 + 4 + 1 + 1;
+	bytes[at..at + 4].copy_from_slice(&0u32.to_le_bytes());
+
+	let result = Replay::read_from(&mut bytes.as_slice());
+	assert!(matches!(result, Err(ReplayError::Corrupt(_))));
+}
+
+#[test]
+fn impossible_multi_ball_is_rejected() {
+	// The multi-ball settings follow the lives.
+	let at = 4 + 2 + 8 + 4 + 4 + 1 + 1 + 4;
+	for &(every, max_balls) in &[(0, 3), (600, 1), (600, MAX_BALLS + 1)] {
+		let (mut bytes, _) = empty_replay();
+		bytes[at] = 1;
+		bytes[at + 1..at + 5].copy_from_slice(&u32::to_le_bytes(every));
+		bytes[at + 5..at + 9].copy_from_slice(&u32::to_le_bytes(max_balls));
+
+		let result = Replay::read_from(&mut bytes.as_slice());
+		assert!(matches!(result, Err(ReplayError::Corrupt(_))), "every {}, up to {} balls", every, max_balls);
+	}
+}
+
+#[test]
+fn oversized_config_is_rejected_before_reading_it() {
+	let (mut bytes, at) = empty_replay();
+	bytes.truncate(at);
+	bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+
+	let result = Replay::read_from(&mut bytes.as_slice());
+	assert!(matches!(result, Err(ReplayError::Corrupt(_))));
+}
+
+#[test]
+fn overlong_match_is_rejected_before_reading_it() {
+	let (mut bytes, at) = empty_replay();
+	let config = u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize;
+	bytes.truncate(at + 4 + config);
+	bytes.extend_from_slice(&1u32.to_le_bytes());
+	bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+	bytes.extend_from_slice(&[0; 8]);
+
+	let result = Replay::read_from(&mut bytes.as_slice());
+	assert!(matches!(result, Err(ReplayError::Corrupt(_))));
+}
@@ -0,0 +1,83 @@
+use mfight_ng::sim::{MatchRules, Score, ServeRule, Side, Simulation, TickInput};
+use vek::Vec2;
+
+fn score(left: u32, right: u32) -> Score {
+	Score { left, right, ..Score::default() }
+}
+
+/// Sends the ball out through the goal of `side` and steps until the point is scored.
+fn concede(simulation: &mut Simulation, side: Side) {
+	let config = simulation.config;
+	let (x, direction) = match side {
+		Side::Left => (-10.0, -1.0),
+		_ => (config.arena_width + 10.0, 1.0),
+	};
+	simulation.serve_delay = 0;
+	simulation.ball_mut().position = Vec2::new(x, config.arena_height / 2.0);
+	simulation.ball_mut().velocity = Vec2::new(direction * config.ball_speed, 0.0);
+	simulation.step(&TickInput::default());
+}
+
+#[test]
+fn first_to_win_score_wins() {
+	let rules = MatchRules { win_score: 5, win_by: 1, ..MatchRules::default() };
+	assert_eq!(rules.winner(score(4, 4)), None);
+	assert_eq!(rules.winner(score(5, 4)), Some(Side::Left));
+	assert_eq!(rules.winner(score(2, 5)), Some(Side::Right));
+}
+
+#[test]
+fn win_by_two_plays_on_past_the_win_score() {
+	let rules = MatchRules { win_score: 11, win_by: 2, ..MatchRules::default() };
+	assert_eq!(rules.winner(score(11, 10)), None);
+	assert_eq!(rules.winner(score(12, 11)), None);
+	assert_eq!(rules.winner(score(11, 9)), Some(Side::Left));
+	assert_eq!(rules.winner(score(12, 14)), Some(Side::Right));
+}
+
+#[test]
+fn match_ends_on_the_winning_point() {
+	let mut simulation = Simulation::with_rules(MatchRules { win_score: 2, win_by: 1, ..MatchRules::default() });
+	concede(&mut simulation, Side::Right);
+	assert_eq!(simulation.score, score(1, 0));
+	assert_eq!(simulation.result, None);
+
+	concede(&mut simulation, Side::Right);
+	let result = simulation.result.unwrap();
+	assert_eq!(result.winner, Side::Left);
+	assert_eq!(result.score, score(2, 0));
+
+	// A finished match no longer advances.
+	let tick = simulation.tick;
+	simulation.step(&TickInput::default());
+	assert_eq!(simulation.tick, tick);
+}
+
+#[test]
+fn ball_is_served_from_the_centre_toward_the_conceder() {
+	let mut simulation = Simulation::with_rules(MatchRules { serve: ServeRule::ToConceder, ..MatchRules::default() });
+	for &side in &[Side::Left, Side::Left, Side::Right] {
+		concede(&mut simulation, side);
+		assert_eq!(simulation.serve_toward, side);
+		assert!(simulation.serve_delay > 0);
+		let config = simulation.config;
+		assert_eq!(simulation.ball().centre(), Vec2::new(config.arena_width, config.arena_height) / 2.0);
+	}
+
+	// The serve heads for the conceder once the delay runs out.
+	while simulation.serve_delay > 0 {
+		simulation.step(&TickInput::default());
+	}
+	assert!(simulation.ball().velocity.x > 0.0);
+}
+
+#[test]
+fn alternate_serves_switch_sides_whoever_scores() {
+	let mut simulation = Simulation::with_rules(MatchRules { serve: ServeRule::Alternate, ..MatchRules::default() });
+	let mut toward = simulation.serve_toward;
+	for _ in 0..4 {
+		concede(&mut simulation, Side::Left);
+		toward = toward.opponent();
+		assert_eq!(simulation.serve_toward, toward);
+	}
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::config::Config;
+use mfight_ng::net::{
+	DecodeError, LinkConditions, Message, OnlineMatch, Server, ServerClient, ServerEvent, Snapshot,
+};
+use mfight_ng::session::Session;
+use mfight_ng::sim::{
+	Format, MatchRules, MultiBall, PaddleInput, RallyEnd, Side, Simulation, Spawn, TickInput, MAX_BALLS,
+};
+
+use common::{short_match, Cpus};
+
+fn server() -> (Server, SocketAddr) {
+	let server = Server::bind(0, Config::preset("fast").unwrap(), short_match(), 42, LinkConditions::default()).unwrap();
+	let address = SocketAddr::from((Ipv4Addr::LOCALHOST, server.local_addr().unwrap().port()));
+	(server, address)
+}
+
+#[test]
+fn server_messages_survive_encoding() {
+	let messages = vec![
+		Message::Enter { name: "player".to_string() },
+		Message::Admit { config: Config::preset("big").unwrap(), rules: short_match(), seed: 7, side: Side::Right },
+		Message::Command { ack: 30, first: 31, inputs: vec![PaddleInput::new(0.5), PaddleInput::new(-1.0)] },
+		Message::State { baseline: 28, margin: -3, delta: vec![1, 2, 3, 4] },
+		Message::Refuse { reason: "the server is full".to_string() },
+	];
+	for message in messages {
+		assert_eq!(Message::decode(&message.encode()), Ok(message));
+	}
+}
+
+#[test]
+fn snapshot_deltas_are_small_and_restore_the_match() {
+	let config = Config::preset("fast").unwrap();
+	let mut simulation = Simulation::with_config(config, short_match(), 9);
+	let input = TickInput {
+		player1: PaddleInput::new(1.0),
+		player2: PaddleInput::new(-0.5),
+		..TickInput::default()
+	};
+	let mut baseline = None;
+	for _ in 0..200 {
+		simulation.step(&input);
+		let snapshot = Snapshot::capture(&simulation, input);
+		let full = snapshot.encode_delta(None);
+		let delta = snapshot.encode_delta(baseline.as_ref());
+		assert!(delta.len() <= full.len());
+		assert_eq!(Snapshot::decode_delta(&delta, baseline.as_ref()), Ok(snapshot.clone()));
+		assert_eq!(snapshot.restore(config, short_match(), 9), simulation);
+		baseline = Some(snapshot);
+	}
+
+	// Between ticks where nothing but the ball moves, only a few words change.
+	let still = TickInput::default();
+	for _ in 0..10 {
+		simulation.step(&still);
+	}
+	let before = Snapshot::capture(&simulation, still);
+	simulation.step(&still);
+	let after = Snapshot::capture(&simulation, still);
+	assert!(after.encode_delta(Some(&before)).len() < after.encode_delta(None).len() / 2);
+	assert!(Snapshot::decode_delta(&after.encode_delta(Some(&before)), None).is_err());
+}
+
+/// Plays a whole match of `rules` between CPUs on every paddle, checking that every tick's
+/// snapshot survives encoding against the one before and restores the match exactly.
+fn assert_snapshots_restore(rules: MatchRules) {
+	let config = Config::default();
+	let session = Session::new(config, rules, 9);
+	let mut simulation = session.new_match();
+	let mut cpus = Cpus::new(&session, Difficulty::Easy);
+	let mut baseline = None;
+	while simulation.result.is_none() {
+		assert!(simulation.tick < 60 * 600, "the match never finished");
+		let input = cpus.input(&simulation);
+		simulation.step(&input);
+		let snapshot = Snapshot::capture(&simulation, input);
+		let delta = snapshot.encode_delta(baseline.as_ref());
+		assert_eq!(Snapshot::decode_delta(&delta, baseline.as_ref()), Ok(snapshot.clone()));
+		assert_eq!(snapshot.restore(config, rules, 9), simulation);
+		baseline = Some(snapshot);
+	}
+}
+
+#[test]
+fn four_way_snapshots_restore_the_match() {
+	assert_snapshots_restore(MatchRules { format: Format::FourWay, lives: 2, ..short_match() });
+}
+
+#[test]
+fn doubles_snapshots_restore_the_match() {
+	assert_snapshots_restore(MatchRules { format: Format::Doubles, ..short_match() });
+}
+
+#[test]
+fn multi_ball_snapshots_restore_the_match() {
+	let multiball = MultiBall { spawn: Spawn::Timer(90), max_balls: MAX_BALLS, rally_end: RallyEnd::LastOut };
+	assert_snapshots_restore(MatchRules { multiball: Some(multiball), ..short_match() });
+}
+
+#[test]
+fn only_single_ball_duels_are_played_online() {
+	let multiball = MultiBall { spawn: Spawn::Hits(3), max_balls: 2, rally_end: RallyEnd::FirstOut };
+	for rules in [
+		MatchRules { format: Format::FourWay, ..short_match() },
+		MatchRules { format: Format::Doubles, ..short_match() },
+		MatchRules { multiball: Some(multiball), ..short_match() },
+	] {
+		let admit = Message::Admit { config: Config::default(), rules, seed: 7, side: Side::Left };
+		assert!(matches!(Message::decode(&admit.encode()), Err(DecodeError::Malformed(_))));
+	}
+}
+
+#[test]
+fn clients_play_a_match_on_the_server() {
+	let (mut server, address) = server();
+	let mut clients: Vec<(ServerClient, Option<Ai>, Difficulty)> = [Difficulty::Hard, Difficulty::Easy]
+		.iter()
+		.map(|&difficulty| {
+			let client = ServerClient::connect(address, difficulty.name(), LinkConditions::default()).unwrap();
+			(client, None, difficulty)
+		})
+		.collect();
+
+	let started = Instant::now();
+	let confirmed = |client: &ServerClient| client.confirmed().map_or(0, |simulation| simulation.tick);
+	let mut next_tick = Instant::now();
+	while clients.iter().any(|(client, _, _)| confirmed(client) < 300 && client.result().is_none()) {
+		assert!(started.elapsed() < Duration::from_secs(20), "the match stopped making progress");
+		server.poll().unwrap();
+		for (client, ai, difficulty) in &mut clients {
+			client.poll().unwrap();
+			if let (Some(side), Some(simulation)) = (client.local_side(), client.simulation()) {
+				let input = ai.get_or_insert_with(|| Ai::new(side, *difficulty)).update(simulation);
+				if Instant::now() >= next_tick {
+					client.advance(input).unwrap();
+				}
+			}
+		}
+		if Instant::now() >= next_tick {
+			server.step().unwrap();
+			next_tick += Duration::from_millis(16);
+		}
+		std::thread::sleep(Duration::from_millis(1));
+	}
+
+	let events = server.take_events();
+	assert!(events.contains(&ServerEvent::Started(42)));
+	let sides: Vec<_> = clients.iter().map(|(client, _, _)| client.local_side().unwrap()).collect();
+	assert_eq!(sides, [Side::Left, Side::Right]);
+	for (client, _, _) in &clients {
+		let snapshot = client.snapshot().unwrap();
+		assert!(snapshot.tick <= server.simulation().tick);
+		assert_eq!(client.config(), Some(Config::preset("fast").unwrap()));
+		assert!(client.latency().ping().is_some());
+	}
+}
+
+/// Plays a client against the server that sends `command(tick)` every tick, and checks
+/// it gets removed for it.
+fn assert_cheat_is_removed(command: impl Fn(u64) -> Message) {
+	let (mut server, address) = server();
+	let cheat = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+	cheat.set_nonblocking(true).unwrap();
+	let mut honest = ServerClient::connect(address, "player", LinkConditions::default()).unwrap();
+	cheat.send_to(&Message::Enter { name: "cheat".to_string() }.encode(), address).unwrap();
+
+	let started = Instant::now();
+	let mut refused = None;
+	while refused.is_none() {
+		assert!(started.elapsed() < Duration::from_secs(10), "the cheat was never removed");
+		server.poll().unwrap();
+		// Told the match is off once the cheat is removed.
+		let _ = honest.poll();
+		server.step().unwrap();
+
+		cheat.send_to(&command(server.simulation().tick).encode(), address).unwrap();
+
+		let mut buffer = [0; 2048];
+		while let Ok((length, _)) = cheat.recv_from(&mut buffer) {
+			if let Ok(Message::Refuse { reason }) = Message::decode(&buffer[..length]) {
+				refused = Some(reason);
+			}
+		}
+		std::thread::sleep(Duration::from_millis(5));
+	}
+
+	assert_eq!(refused.as_deref(), Some("sent invalid inputs"));
+	let events = server.take_events();
+	assert!(events.iter().any(|event| matches!(event, ServerEvent::Kicked(_, _))));
+	assert!(events.contains(&ServerEvent::Abandoned));
+	assert!(!server.is_running());
+}
+
+#[test]
+fn cheating_client_is_removed() {
+	// Far faster than any paddle may move.
+	assert_cheat_is_removed(|tick| Message::Command { ack: 0, first: tick + 1, inputs: vec![PaddleInput::new(10.0); 4] });
+}
+
+#[test]
+fn inputs_for_impossible_ticks_are_violations() {
+	assert_cheat_is_removed(|_| Message::Command { ack: 0, first: u64::MAX, inputs: vec![PaddleInput::new(0.5); 4] });
+	assert_cheat_is_removed(|_| Message::Command { ack: 0, first: 0, inputs: vec![PaddleInput::new(0.5); 4] });
+}
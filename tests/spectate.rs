@@ -0,0 +1,255 @@
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use mfight_ng::ai::{Ai, Difficulty};
+use mfight_ng::config::Config;
+use mfight_ng::net::{
+	Broadcast, Connection, LinkConditions, MatchSettings, Message, NetError, OnlineMatch, Server, ServerClient,
+	Spectator, SNAPSHOT_INTERVAL,
+};
+use mfight_ng::session::Session;
+use mfight_ng::sim::{
+	Format, MatchRules, MultiBall, PaddleInput, RallyEnd, Side, Spawn, TickInput, TICK_RATE,
+};
+
+use common::{short_match, Cpus};
+
+fn multiball() -> MultiBall {
+	MultiBall { spawn: Spawn::Hits(2), max_balls: 3, rally_end: RallyEnd::LastOut }
+}
+
+fn names(names: [&str; 4]) -> [String; 4] {
+	names.map(str::to_string)
+}
+
+fn loopback(address: SocketAddr) -> SocketAddr {
+	SocketAddr::from((Ipv4Addr::LOCALHOST, address.port()))
+}
+
+#[test]
+fn spectator_messages_survive_encoding() {
+	let messages = vec![
+		Message::Watch { epoch: 0, ack: 0 },
+		Message::Watch { epoch: 3, ack: 1_204 },
+		Message::Spectate {
+			epoch: 3,
+			config: Config::preset("big").unwrap(),
+			rules: short_match(),
+			seed: 99,
+			names: names(["Ada", "", "Grace", ""]),
+			delay: 2_000,
+		},
+		Message::Spectate {
+			epoch: 4,
+			config: Config::default(),
+			rules: MatchRules { format: Format::FourWay, lives: 5, ..short_match() },
+			seed: 100,
+			names: names(["Ada", "Grace", "Alan", "Edsger"]),
+			delay: 0,
+		},
+		Message::Spectate {
+			epoch: 5,
+			config: Config::default(),
+			rules: MatchRules { multiball: Some(multiball()), ..short_match() },
+			seed: 101,
+			names: names(["Ada", "Grace", "", ""]),
+			delay: 0,
+		},
+		Message::View { epoch: 3, baseline: 1_202, delta: vec![9, 8, 7] },
+	];
+	for message in messages {
+		assert_eq!(Message::decode(&message.encode()), Ok(message));
+	}
+}
+
+/// Plays a local match of `rules` between CPUs, shown to `spectator` through `broadcast`,
+/// until 120 ticks of it were shown. Every snapshot shown must be exactly the match as it
+/// was played. Returns the tick the players were on when the spectator was first shown it.
+fn show_local_match(broadcast: &mut Broadcast, spectator: &mut Spectator, rules: MatchRules, names: [String; 4]) -> u64 {
+	let config = Config::preset("fast").unwrap();
+	let session = Session::new(config, rules, 5);
+	let mut simulation = session.new_match();
+	broadcast.begin(config, rules, 5, names);
+	broadcast.record(&simulation, TickInput::default());
+	let mut played = vec![simulation.clone()];
+	let mut cpus = Cpus::new(&session, Difficulty::Hard);
+
+	let started = Instant::now();
+	let mut next_tick = Instant::now();
+	let mut first_shown = None;
+	while spectator.simulation().map_or(0, |shown| shown.tick) < 120 {
+		assert!(started.elapsed() < Duration::from_secs(10), "the spectator never caught up");
+		broadcast.poll().unwrap();
+		spectator.poll().unwrap();
+		if Instant::now() >= next_tick {
+			let input = cpus.input(&simulation);
+			simulation.step(&input);
+			broadcast.record(&simulation, input);
+			played.push(simulation.clone());
+
+			if spectator.advance() {
+				let shown = spectator.simulation().unwrap();
+				first_shown.get_or_insert(simulation.tick);
+				// Snapshots are shown exactly; the ticks between them are predicted.
+				if shown.tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+					assert_eq!(shown, &played[shown.tick as usize]);
+				}
+				assert!(shown.tick < simulation.tick);
+			}
+			next_tick += Duration::from_secs(1) / TICK_RATE;
+		}
+		std::thread::sleep(Duration::from_millis(1));
+	}
+	first_shown.unwrap()
+}
+
+#[test]
+fn broadcast_shows_a_local_match_late() {
+	let delay = Duration::from_millis(300);
+	let mut broadcast = Broadcast::bind(0, delay, LinkConditions::default()).unwrap();
+	let address = loopback(broadcast.local_addr().unwrap());
+	let mut spectator = Spectator::watch(address, LinkConditions::default()).unwrap();
+
+	let first_shown = show_local_match(&mut broadcast, &mut spectator, short_match(), names(["Ada", "CPU (Hard)", "", ""]));
+	assert!(first_shown as f32 >= delay.as_secs_f32() * TICK_RATE as f32 * 0.8);
+	assert_eq!(spectator.names().unwrap(), &names(["Ada", "CPU (Hard)", "", ""]));
+	assert_eq!(spectator.delay(), Some(delay));
+	assert_eq!(broadcast.spectators(), 1);
+}
+
+#[test]
+fn broadcast_shows_a_four_way_match() {
+	let mut broadcast = Broadcast::bind(0, Duration::ZERO, LinkConditions::default()).unwrap();
+	let address = loopback(broadcast.local_addr().unwrap());
+	let mut spectator = Spectator::watch(address, LinkConditions::default()).unwrap();
+
+	let rules = MatchRules { format: Format::FourWay, lives: 2, ..short_match() };
+	show_local_match(&mut broadcast, &mut spectator, rules, names(["Ada", "Grace", "Alan", "Edsger"]));
+	assert_eq!(spectator.simulation().unwrap().rules, rules);
+	assert_eq!(spectator.names().unwrap(), &names(["Ada", "Grace", "Alan", "Edsger"]));
+}
+
+#[test]
+fn broadcast_shows_a_doubles_match() {
+	let mut broadcast = Broadcast::bind(0, Duration::ZERO, LinkConditions::default()).unwrap();
+	let address = loopback(broadcast.local_addr().unwrap());
+	let mut spectator = Spectator::watch(address, LinkConditions::default()).unwrap();
+
+	let rules = MatchRules { format: Format::Doubles, ..short_match() };
+	show_local_match(&mut broadcast, &mut spectator, rules, names(["Ada & Alan", "Grace & Edsger", "", ""]));
+	assert_eq!(spectator.simulation().unwrap().rules, rules);
+	assert_eq!(spectator.names().unwrap(), &names(["Ada & Alan", "Grace & Edsger", "", ""]));
+}
+
+#[test]
+fn broadcast_shows_a_multi_ball_match() {
+	let mut broadcast = Broadcast::bind(0, Duration::ZERO, LinkConditions::default()).unwrap();
+	let address = loopback(broadcast.local_addr().unwrap());
+	let mut spectator = Spectator::watch(address, LinkConditions::default()).unwrap();
+
+	let rules = MatchRules { multiball: Some(multiball()), ..short_match() };
+	show_local_match(&mut broadcast, &mut spectator, rules, names(["Ada", "Grace", "", ""]));
+	assert_eq!(spectator.simulation().unwrap().rules, rules);
+}
+
+#[test]
+fn spectator_joins_a_server_match_midway() {
+	let config = Config::preset("fast").unwrap();
+	let mut server = Server::bind(0, config, short_match(), 42, LinkConditions::default()).unwrap();
+	server.set_spectator_delay(Duration::from_millis(500));
+	let address = loopback(server.local_addr().unwrap());
+	let mut clients: Vec<(ServerClient, Option<Ai>, Difficulty)> = [Difficulty::Hard, Difficulty::Easy]
+		.iter()
+		.map(|&difficulty| {
+			let client = ServerClient::connect(address, difficulty.name(), LinkConditions::default()).unwrap();
+			(client, None, difficulty)
+		})
+		.collect();
+
+	let started = Instant::now();
+	let mut spectator: Option<Spectator> = None;
+	let mut first_shown = None;
+	let mut next_tick = Instant::now();
+	while spectator.as_ref().and_then(Spectator::simulation).is_none_or(|shown| shown.tick < 200) {
+		assert!(started.elapsed() < Duration::from_secs(20), "the spectator was never shown the match");
+		server.poll().unwrap();
+		for (client, ai, difficulty) in &mut clients {
+			client.poll().unwrap();
+			if let (Some(side), Some(simulation)) = (client.local_side(), client.simulation()) {
+				let input = ai.get_or_insert_with(|| Ai::new(side, *difficulty)).update(simulation);
+				if Instant::now() >= next_tick {
+					client.advance(input).unwrap();
+				}
+			}
+		}
+		if server.simulation().tick >= 100 && spectator.is_none() {
+			spectator = Some(Spectator::watch(address, LinkConditions::default()).unwrap());
+		}
+		if let Some(spectator) = &mut spectator {
+			spectator.poll().unwrap();
+			if Instant::now() >= next_tick && spectator.advance() {
+				first_shown.get_or_insert(spectator.simulation().unwrap().tick);
+			}
+		}
+		if Instant::now() >= next_tick {
+			server.step().unwrap();
+			next_tick += Duration::from_secs(1) / TICK_RATE;
+		}
+		std::thread::sleep(Duration::from_millis(1));
+	}
+
+	// Shown from a snapshot of the match in progress, not replayed from its start, and
+	// about half a second behind the players.
+	assert!(first_shown.unwrap() >= 60);
+	let spectator = spectator.unwrap();
+	let behind = server.simulation().tick - spectator.simulation().unwrap().tick;
+	assert!((20..=60).contains(&behind), "{} ticks behind", behind);
+	assert_eq!(spectator.names().unwrap(), &names(["Hard", "Easy", "", ""]));
+	assert_eq!(server.spectators(), 1);
+}
+
+#[test]
+fn host_of_an_online_match_takes_spectators() {
+	let settings = MatchSettings {
+		config: Config::preset("fast").unwrap(),
+		rules: short_match(),
+		seed: 8,
+		input_delay: 2,
+		host_side: Side::Right,
+	};
+	let mut host = Connection::host(0, "Ada", settings, LinkConditions::default()).unwrap();
+	host.set_spectator_delay(Duration::ZERO);
+	let address = loopback(host.local_addr().unwrap());
+	let mut guest = Connection::join(address, "Grace", LinkConditions::default()).unwrap();
+	let mut spectator = Spectator::watch(address, LinkConditions::default()).unwrap();
+
+	let started = Instant::now();
+	while spectator.simulation().is_none_or(|shown| shown.tick < 60) {
+		assert!(started.elapsed() < Duration::from_secs(10), "the spectator was never shown the match");
+		for peer in [&mut host, &mut guest] {
+			peer.poll().unwrap();
+			peer.advance(PaddleInput::new(-0.5)).unwrap();
+		}
+		spectator.poll().unwrap();
+		spectator.advance();
+		std::thread::sleep(Duration::from_millis(2));
+	}
+	assert_eq!(spectator.names().unwrap(), &names(["Grace", "Ada", "", ""]));
+	assert_eq!(host.spectators(), 1);
+
+	// Told once the host is gone, rather than left to time out.
+	host.leave();
+	let started = Instant::now();
+	loop {
+		assert!(started.elapsed() < Duration::from_secs(5), "the spectator was never told");
+		match spectator.poll() {
+			Ok(()) => std::thread::sleep(Duration::from_millis(2)),
+			Err(err) => {
+				assert!(matches!(err, NetError::Closed), "unexpected {:?}", err);
+				break;
+			}
+		}
+	}
+}